@@ -0,0 +1,63 @@
+MEMORY {
+    /*
+     * Must agree with the application's memory.x:
+     *
+     *   FLASH (BOOTLOADER) 48K  this bootloader
+     *   BOOTLOADER_STATE    4K  swap progress and boot confirmation
+     *   ACTIVE           2016K  the running application
+     *   DFU              2020K  staging area for OTA images
+     *   (STORAGE            8K  application data, left alone)
+     */
+    FLASH            : ORIGIN = 0x10000000, LENGTH = 48K
+    BOOTLOADER_STATE : ORIGIN = 0x1000C000, LENGTH = 4K
+    ACTIVE           : ORIGIN = 0x1000D000, LENGTH = 2016K
+    DFU              : ORIGIN = 0x10205000, LENGTH = 2020K
+    /*
+     * Striped RAM only: SRAM9 holds the application's crash record,
+     * which has to survive the trip through the bootloader.
+     */
+    RAM : ORIGIN = 0x20000000, LENGTH = 512K
+}
+
+__bootloader_state_start = ORIGIN(BOOTLOADER_STATE) - ORIGIN(FLASH);
+__bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE) - ORIGIN(FLASH);
+
+__bootloader_active_start = ORIGIN(ACTIVE) - ORIGIN(FLASH);
+__bootloader_active_end = ORIGIN(ACTIVE) + LENGTH(ACTIVE) - ORIGIN(FLASH);
+
+__bootloader_dfu_start = ORIGIN(DFU) - ORIGIN(FLASH);
+__bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU) - ORIGIN(FLASH);
+
+SECTIONS {
+    /* ### Boot ROM info
+     *
+     * Goes after .vector_table, to keep it in the first 4K of flash
+     * where the Boot ROM (and picotool) can find it
+     */
+    .start_block : ALIGN(4)
+    {
+        __start_block_addr = .;
+        KEEP(*(.start_block));
+        KEEP(*(.boot_info));
+    } > FLASH
+
+} INSERT AFTER .vector_table;
+
+/* move .text to start /after/ the boot info */
+_stext = ADDR(.start_block) + SIZEOF(.start_block);
+
+SECTIONS {
+    /* ### Boot ROM extra info
+     *
+     * Goes after everything in our program, so it can contain a signature.
+     */
+    .end_block : ALIGN(4)
+    {
+        __end_block_addr = .;
+        KEEP(*(.end_block));
+    } > FLASH
+
+} INSERT AFTER .uninit;
+
+PROVIDE(start_to_end = __end_block_addr - __start_block_addr);
+PROVIDE(end_to_start = __start_block_addr - __end_block_addr);
@@ -0,0 +1,109 @@
+use crate::target::Band;
+use crate::types::{PumpCommand, TasksResponse};
+
+/// What the server asked for in a tasks poll.
+#[derive(Clone, Copy, Default)]
+pub struct TaskActions {
+    pub pump: Option<PumpCommand>,
+    pub cancel_pump: bool,
+    pub calibrate_pump: bool,
+    pub calibration_ml: Option<u16>,
+    pub target: Option<Band>,
+    pub target_off: bool,
+    pub vacation_until: Option<u16>,
+    pub vacation_off: bool,
+    pub firmware_update: bool,
+    pub upload_record: bool,
+    pub day_of_year: Option<u16>,
+}
+
+/// Parses a tasks poll body; `None` if it is not valid JSON for us.
+pub fn parse_tasks(body: &[u8]) -> Option<TaskActions> {
+    let (tasks, _) = serde_json_core::from_slice::<TasksResponse>(body).ok()?;
+
+    Some(TaskActions {
+        pump: (tasks.pump_duration > 0 || tasks.pump_volume_ml > 0).then_some(PumpCommand {
+            duration_secs: tasks.pump_duration,
+            pulses: tasks.pump_pulses.max(1),
+            soak_secs: tasks.pump_soak_secs,
+            volume_ml: tasks.pump_volume_ml,
+            targeted: false,
+            zone: tasks.pump_zone,
+        }),
+        cancel_pump: tasks.pump_cancel,
+        calibrate_pump: tasks.calibrate_pump,
+        calibration_ml: (tasks.calibration_ml > 0).then_some(tasks.calibration_ml),
+        target: Band::new(tasks.target_moisture_low, tasks.target_moisture_high),
+        target_off: tasks.target_off,
+        vacation_until: (1..=365)
+            .contains(&tasks.vacation_until_day)
+            .then_some(tasks.vacation_until_day),
+        vacation_off: tasks.vacation_off,
+        firmware_update: tasks.firmware_update,
+        upload_record: tasks.upload_record,
+        day_of_year: (1..=366)
+            .contains(&tasks.day_of_year)
+            .then_some(tasks.day_of_year),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_poll_asks_for_nothing() {
+        let actions = parse_tasks(b"{}").unwrap();
+        assert!(actions.pump.is_none() && actions.target.is_none());
+        assert!(!actions.cancel_pump && !actions.firmware_update && !actions.upload_record);
+        assert_eq!(actions.calibration_ml, None);
+        assert_eq!(actions.day_of_year, None);
+    }
+
+    #[test]
+    fn plain_run_is_one_pulse() {
+        let pump = parse_tasks(br#"{"pump_duration":10,"pump_pulses":0}"#)
+            .unwrap()
+            .pump
+            .unwrap();
+        assert_eq!(
+            (pump.duration_secs, pump.pulses, pump.soak_secs),
+            (10, 1, 0)
+        );
+        assert!(!pump.targeted);
+
+        let pump = parse_tasks(br#"{"pump_volume_ml":250}"#)
+            .unwrap()
+            .pump
+            .unwrap();
+        assert_eq!((pump.duration_secs, pump.volume_ml), (0, 250));
+    }
+
+    #[test]
+    fn out_of_range_values_are_left_out() {
+        let actions = parse_tasks(
+            br#"{"target_moisture_low":60,"target_moisture_high":40,"vacation_until_day":400,"day_of_year":367}"#,
+        )
+        .unwrap();
+        assert!(actions.target.is_none());
+        assert_eq!(actions.vacation_until, None);
+        assert_eq!(actions.day_of_year, None);
+
+        let actions = parse_tasks(br#"{"vacation_until_day":200,"day_of_year":366}"#).unwrap();
+        assert_eq!(actions.vacation_until, Some(200));
+        assert_eq!(actions.day_of_year, Some(366));
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let actions = parse_tasks(br#"{"pump_duration":5,"new_feature":true}"#).unwrap();
+        assert!(actions.pump.is_some());
+    }
+
+    #[test]
+    fn rejects_what_is_not_json() {
+        assert!(parse_tasks(b"").is_none());
+        assert!(parse_tasks(b"<html>").is_none());
+        assert!(parse_tasks(br#"{"pump_duration":-1}"#).is_none());
+    }
+}
@@ -0,0 +1,345 @@
+//! Versioned boot/crash record kept in no-init RAM across resets.
+//!
+//! Pure encode/decode with no hardware access, so the format is tested on
+//! the host.
+
+use core::fmt::Write;
+
+use heapless::String;
+use serde::Serialize;
+
+use crate::types::TaskId;
+
+pub const RECORD_VERSION: u8 = 1;
+pub const RECORD_SIZE: usize = 192;
+pub const MESSAGE_LEN: usize = 96;
+pub const FILE_LEN: usize = 64;
+
+const MAGIC: u32 = 0x4449_4147; // "DIAG"
+const NO_TASK: u8 = 0xFF;
+const FLAG_PANIC: u8 = 1 << 0;
+const FLAG_SOFTWARE_RESET: u8 = 1 << 1;
+
+// Byte offsets of the v1 layout
+const OFF_VERSION: usize = 4;
+const OFF_REASON: usize = 5;
+const OFF_TASK: usize = 6;
+const OFF_FLAGS: usize = 7;
+const OFF_BOOT_COUNT: usize = 8;
+const OFF_LINE: usize = 12;
+const OFF_MESSAGE_LEN: usize = 16;
+const OFF_FILE_LEN: usize = 17;
+const OFF_MESSAGE: usize = 18;
+const OFF_FILE: usize = OFF_MESSAGE + MESSAGE_LEN;
+const OFF_CRC: usize = RECORD_SIZE - 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetReason {
+    PowerOn,
+    Brownout,
+    Watchdog,
+    Panic,
+    Software,
+    // RAM survived but nothing recorded why: RUN pin or debugger
+    External,
+}
+
+impl ResetReason {
+    const ALL: [ResetReason; 6] = [
+        ResetReason::PowerOn,
+        ResetReason::Brownout,
+        ResetReason::Watchdog,
+        ResetReason::Panic,
+        ResetReason::Software,
+        ResetReason::External,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ResetReason::PowerOn => "power-on",
+            ResetReason::Brownout => "brownout",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::Panic => "panic",
+            ResetReason::Software => "software",
+            ResetReason::External => "external",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct PanicReport {
+    pub message: String<MESSAGE_LEN>,
+    pub file: String<FILE_LEN>,
+    pub line: u32,
+}
+
+#[derive(Clone, PartialEq, Eq, Serialize)]
+pub struct CrashRecord {
+    pub boot_count: u32,
+    pub reset_reason: ResetReason,
+    pub stalled_task: Option<TaskId>,
+    pub panic: Option<PanicReport>,
+    // Set right before a deliberate reboot, never reported
+    #[serde(skip)]
+    pub software_reset: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadChecksum,
+    Corrupt,
+}
+
+impl CrashRecord {
+    pub fn encode(&self) -> [u8; RECORD_SIZE] {
+        let mut buf = [0u8; RECORD_SIZE];
+
+        buf[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        buf[OFF_VERSION] = RECORD_VERSION;
+        buf[OFF_REASON] = self.reset_reason as u8;
+        buf[OFF_TASK] = self.stalled_task.map_or(NO_TASK, |task| task as u8);
+        buf[OFF_BOOT_COUNT..OFF_BOOT_COUNT + 4].copy_from_slice(&self.boot_count.to_le_bytes());
+
+        let mut flags = 0;
+        if self.software_reset {
+            flags |= FLAG_SOFTWARE_RESET;
+        }
+        if let Some(panic) = &self.panic {
+            flags |= FLAG_PANIC;
+            buf[OFF_LINE..OFF_LINE + 4].copy_from_slice(&panic.line.to_le_bytes());
+            buf[OFF_MESSAGE_LEN] = panic.message.len() as u8;
+            buf[OFF_FILE_LEN] = panic.file.len() as u8;
+            buf[OFF_MESSAGE..OFF_MESSAGE + panic.message.len()]
+                .copy_from_slice(panic.message.as_bytes());
+            buf[OFF_FILE..OFF_FILE + panic.file.len()].copy_from_slice(panic.file.as_bytes());
+        }
+        buf[OFF_FLAGS] = flags;
+
+        let crc = crc32(&buf[..OFF_CRC]);
+        buf[OFF_CRC..].copy_from_slice(&crc.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(buf: &[u8; RECORD_SIZE]) -> Result<Self, DecodeError> {
+        if read_u32(buf, 0) != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        if buf[OFF_VERSION] != RECORD_VERSION {
+            return Err(DecodeError::UnsupportedVersion(buf[OFF_VERSION]));
+        }
+        if read_u32(buf, OFF_CRC) != crc32(&buf[..OFF_CRC]) {
+            return Err(DecodeError::BadChecksum);
+        }
+
+        let reset_reason = *ResetReason::ALL
+            .get(buf[OFF_REASON] as usize)
+            .ok_or(DecodeError::Corrupt)?;
+        let stalled_task = match buf[OFF_TASK] {
+            NO_TASK => None,
+            index => Some(TaskId::from_index(index as u32).ok_or(DecodeError::Corrupt)?),
+        };
+
+        let flags = buf[OFF_FLAGS];
+        let panic = if flags & FLAG_PANIC != 0 {
+            let message_len = buf[OFF_MESSAGE_LEN] as usize;
+            let file_len = buf[OFF_FILE_LEN] as usize;
+            if message_len > MESSAGE_LEN || file_len > FILE_LEN {
+                return Err(DecodeError::Corrupt);
+            }
+            Some(PanicReport {
+                message: to_string(&buf[OFF_MESSAGE..OFF_MESSAGE + message_len])?,
+                file: to_string(&buf[OFF_FILE..OFF_FILE + file_len])?,
+                line: read_u32(buf, OFF_LINE),
+            })
+        } else {
+            None
+        };
+
+        Ok(Self {
+            boot_count: read_u32(buf, OFF_BOOT_COUNT),
+            reset_reason,
+            stalled_task,
+            panic,
+            software_reset: flags & FLAG_SOFTWARE_RESET != 0,
+        })
+    }
+}
+
+/// `fmt::Write` adapter that silently drops whatever does not fit.
+pub struct Truncating<'a, const N: usize>(pub &'a mut String<N>);
+
+impl<const N: usize> Write for Truncating<'_, N> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        for c in s.chars() {
+            if self.0.push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn to_string<const N: usize>(bytes: &[u8]) -> Result<String<N>, DecodeError> {
+    let text = core::str::from_utf8(bytes).map_err(|_| DecodeError::Corrupt)?;
+    let mut s = String::new();
+    s.push_str(text).map_err(|_| DecodeError::Corrupt)?;
+    Ok(s)
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+// CRC-32 (IEEE 802.3), bitwise to avoid a lookup table
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn panicked() -> CrashRecord {
+        let mut message = String::new();
+        message.push_str("index out of bounds").unwrap();
+        let mut file = String::new();
+        file.push_str("src/tasks/pump.rs").unwrap();
+        CrashRecord {
+            boot_count: 42,
+            reset_reason: ResetReason::Panic,
+            stalled_task: Some(TaskId::Http),
+            panic: Some(PanicReport {
+                message,
+                file,
+                line: 117,
+            }),
+            software_reset: true,
+        }
+    }
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn round_trip() {
+        let record = panicked();
+        assert!(CrashRecord::decode(&record.encode()) == Ok(record));
+
+        let plain = CrashRecord {
+            boot_count: 1,
+            reset_reason: ResetReason::PowerOn,
+            stalled_task: None,
+            panic: None,
+            software_reset: false,
+        };
+        assert!(CrashRecord::decode(&plain.encode()) == Ok(plain));
+    }
+
+    #[test]
+    fn full_length_strings_fit() {
+        let mut record = panicked();
+        let panic = record.panic.as_mut().unwrap();
+        panic.message.clear();
+        panic.file.clear();
+        for _ in 0..MESSAGE_LEN {
+            panic.message.push('m').unwrap();
+        }
+        for _ in 0..FILE_LEN {
+            panic.file.push('f').unwrap();
+        }
+        assert!(CrashRecord::decode(&record.encode()) == Ok(record));
+    }
+
+    #[test]
+    fn any_flipped_bit_is_caught() {
+        let buf = panicked().encode();
+        for i in OFF_VERSION + 1..RECORD_SIZE {
+            let mut bad = buf;
+            bad[i] ^= 0x10;
+            assert_eq!(
+                CrashRecord::decode(&bad).err(),
+                Some(DecodeError::BadChecksum),
+                "byte {i}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut buf = panicked().encode();
+        buf[OFF_VERSION] = RECORD_VERSION + 1;
+        assert_eq!(
+            CrashRecord::decode(&buf).err(),
+            Some(DecodeError::UnsupportedVersion(RECORD_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_uninitialized_ram() {
+        assert_eq!(
+            CrashRecord::decode(&[0; RECORD_SIZE]).err(),
+            Some(DecodeError::BadMagic)
+        );
+        assert_eq!(
+            CrashRecord::decode(&[0xA5; RECORD_SIZE]).err(),
+            Some(DecodeError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn rejects_fields_out_of_range() {
+        // Valid checksum over values no encoder writes
+        let reseal = |mut buf: [u8; RECORD_SIZE]| {
+            let crc = crc32(&buf[..OFF_CRC]);
+            buf[OFF_CRC..].copy_from_slice(&crc.to_le_bytes());
+            buf
+        };
+        let buf = panicked().encode();
+
+        let mut bad = buf;
+        bad[OFF_REASON] = ResetReason::ALL.len() as u8;
+        assert_eq!(
+            CrashRecord::decode(&reseal(bad)).err(),
+            Some(DecodeError::Corrupt)
+        );
+
+        let mut bad = buf;
+        bad[OFF_TASK] = TaskId::ALL.len() as u8;
+        assert_eq!(
+            CrashRecord::decode(&reseal(bad)).err(),
+            Some(DecodeError::Corrupt)
+        );
+
+        let mut bad = buf;
+        bad[OFF_MESSAGE_LEN] = MESSAGE_LEN as u8 + 1;
+        assert_eq!(
+            CrashRecord::decode(&reseal(bad)).err(),
+            Some(DecodeError::Corrupt)
+        );
+
+        let mut bad = buf;
+        bad[OFF_FILE] = 0xFF;
+        assert_eq!(
+            CrashRecord::decode(&reseal(bad)).err(),
+            Some(DecodeError::Corrupt)
+        );
+    }
+}
@@ -0,0 +1,360 @@
+//! Pump flow calibration, millilitre targets and the water delivered.
+//!
+//! Worked example: a calibration run of 20 s that fills a jug with 250 ml
+//! gives 12.5 ml/s; a 500 ml request in 3 pulses then becomes 3 × 13 s,
+//! which delivers 487.5 ml.
+
+use libm::roundf;
+use serde::Serialize;
+
+use crate::target::Band;
+use crate::types::PumpCommand;
+
+// magic, version, seq, rate, day, day and total ml, target band, gain,
+// vacation end, checksum
+pub const STORED_LEN: usize = 4 + 1 + 4 + 4 + 2 + 4 + 8 + 2 * 4 + 4 + 2 + 4;
+
+const MAGIC: [u8; 4] = *b"WDOS";
+const VERSION: u8 = 3;
+// Version 1 had no target band or gain, version 2 no vacation
+const V1_BODY_LEN: usize = 27;
+const V2_BODY_LEN: usize = 39;
+const BODY_LEN: usize = STORED_LEN - 4;
+
+/// Flow rate from a calibration run that delivered `ml` in `on_secs`.
+pub fn flow_rate(ml: f32, on_secs: u32) -> Option<f32> {
+    (ml > 0.0 && on_secs > 0).then(|| ml / on_secs as f32)
+}
+
+/// Pulse length that delivers `volume_ml` in `pulses` at `ml_per_sec`.
+pub fn secs_for_volume(volume_ml: u16, pulses: u8, ml_per_sec: f32) -> u16 {
+    let per_pulse = volume_ml as f32 / pulses.max(1) as f32;
+    roundf(per_pulse / ml_per_sec).clamp(0.0, u16::MAX as f32) as u16
+}
+
+/// Volume pumped in `on_secs` at `ml_per_sec`.
+pub fn volume_ml(on_secs: u32, ml_per_sec: f32) -> u32 {
+    roundf(on_secs as f32 * ml_per_sec).max(0.0) as u32
+}
+
+/// Turns a volume request into one in seconds; `None` while the pump is
+/// uncalibrated. Requests in seconds pass through unchanged.
+pub fn resolve(cmd: PumpCommand, ml_per_sec: Option<f32>) -> Option<PumpCommand> {
+    if cmd.volume_ml == 0 {
+        return Some(cmd);
+    }
+    Some(PumpCommand {
+        duration_secs: secs_for_volume(cmd.volume_ml, cmd.pulses, ml_per_sec?),
+        volume_ml: 0,
+        ..cmd
+    })
+}
+
+/// Water delivered today and over the device's life.
+///
+/// The day is the calendar day from the server, so the count survives a
+/// reboot; until the date is known everything goes to the current day.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize)]
+pub struct Totals {
+    pub day_of_year: Option<u16>,
+    pub day_ml: u32,
+    pub total_ml: u64,
+}
+
+impl Totals {
+    pub fn add(&mut self, ml: u32, day_of_year: Option<u16>) {
+        if day_of_year.is_some() && day_of_year != self.day_of_year {
+            self.day_of_year = day_of_year;
+            self.day_ml = 0;
+        }
+        self.day_ml = self.day_ml.saturating_add(ml);
+        self.total_ml = self.total_ml.saturating_add(ml as u64);
+    }
+
+    /// Millilitres delivered on `day_of_year`, 0 if nothing has been yet.
+    pub fn today_ml(&self, day_of_year: Option<u16>) -> u32 {
+        match day_of_year {
+            Some(day) if self.day_of_year != Some(day) => 0,
+            _ => self.day_ml,
+        }
+    }
+}
+
+/// What is kept in flash across reboots and updates.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Stored {
+    // Write count, so the newer of two copies can be told apart
+    pub seq: u32,
+    pub ml_per_sec: Option<f32>,
+    pub totals: Totals,
+    // Target-moisture mode, see `target`
+    pub target: Option<Band>,
+    pub gain: Option<f32>,
+    // Day of year a vacation ends, see `vacation`
+    pub vacation_until: Option<u16>,
+}
+
+impl Stored {
+    pub const fn new() -> Self {
+        Self {
+            seq: 0,
+            ml_per_sec: None,
+            totals: Totals {
+                day_of_year: None,
+                day_ml: 0,
+                total_ml: 0,
+            },
+            target: None,
+            gain: None,
+            vacation_until: None,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; STORED_LEN] {
+        let mut buf = [0u8; STORED_LEN];
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        buf[5..9].copy_from_slice(&self.seq.to_le_bytes());
+        buf[9..13].copy_from_slice(&self.ml_per_sec.unwrap_or(f32::NAN).to_le_bytes());
+        // Day 0 does not exist, so it stands for an unknown date
+        buf[13..15].copy_from_slice(&self.totals.day_of_year.unwrap_or(0).to_le_bytes());
+        buf[15..19].copy_from_slice(&self.totals.day_ml.to_le_bytes());
+        buf[19..27].copy_from_slice(&self.totals.total_ml.to_le_bytes());
+        let (low, high) = self
+            .target
+            .map_or((f32::NAN, f32::NAN), |b| (b.low, b.high));
+        buf[27..31].copy_from_slice(&low.to_le_bytes());
+        buf[31..35].copy_from_slice(&high.to_le_bytes());
+        buf[35..39].copy_from_slice(&self.gain.unwrap_or(f32::NAN).to_le_bytes());
+        buf[39..41].copy_from_slice(&self.vacation_until.unwrap_or(0).to_le_bytes());
+        let checksum = fnv1a(&buf[..BODY_LEN]);
+        buf[BODY_LEN..].copy_from_slice(&checksum.to_le_bytes());
+        buf
+    }
+
+    /// `None` for erased flash, another layout or a torn write. Copies of
+    /// older versions load without the fields they lacked.
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.get(..4)? != MAGIC {
+            return None;
+        }
+        let body_len = match *buf.get(4)? {
+            1 => V1_BODY_LEN,
+            2 => V2_BODY_LEN,
+            VERSION => BODY_LEN,
+            _ => return None,
+        };
+        let buf = buf.get(..body_len + 4)?;
+        if fnv1a(&buf[..body_len]).to_le_bytes() != buf[body_len..] {
+            return None;
+        }
+
+        let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        let f32_at = |i: usize| Some(f32::from_bits(u32_at(i))).filter(|v| v.is_finite());
+        let day = u16::from_le_bytes([buf[13], buf[14]]);
+        let mut total = [0u8; 8];
+        total.copy_from_slice(&buf[19..27]);
+        let has_target = body_len > V1_BODY_LEN;
+        let vacation_until = match body_len > V2_BODY_LEN {
+            true => u16::from_le_bytes([buf[39], buf[40]]),
+            false => 0,
+        };
+        Some(Self {
+            seq: u32_at(5),
+            ml_per_sec: f32_at(9),
+            totals: Totals {
+                day_of_year: (day != 0).then_some(day),
+                day_ml: u32_at(15),
+                total_ml: u64::from_le_bytes(total),
+            },
+            target: has_target
+                .then(|| Band::new(f32_at(27)?, f32_at(31)?))
+                .flatten(),
+            gain: has_target.then(|| f32_at(35)).flatten(),
+            vacation_until: (vacation_until != 0).then_some(vacation_until),
+        })
+    }
+
+    /// The more recently written of two copies.
+    pub fn newest(a: Option<Self>, b: Option<Self>) -> Option<Self> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(if b.seq.wrapping_sub(a.seq) as i32 > 0 {
+                b
+            } else {
+                a
+            }),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+
+    fn stored() -> Stored {
+        Stored {
+            seq: 7,
+            ml_per_sec: Some(12.5),
+            totals: Totals {
+                day_of_year: Some(152),
+                day_ml: 1_250,
+                total_ml: 5_000_000_000,
+            },
+            target: Band::new(40.0, 50.0),
+            gain: Some(0.4),
+            vacation_until: Some(200),
+        }
+    }
+
+    // A copy in an older layout: the first `body_len` bytes of the current
+    // one, resealed
+    fn older(stored: &Stored, version: u8, body_len: usize) -> Vec<u8> {
+        let mut buf = stored.encode()[..body_len].to_vec();
+        buf[4] = version;
+        let checksum = fnv1a(&buf);
+        buf.extend_from_slice(&checksum.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn worked_example() {
+        let rate = flow_rate(250.0, 20).unwrap();
+        assert_eq!(rate, 12.5);
+        assert_eq!(secs_for_volume(500, 3, rate), 13);
+        assert_eq!(volume_ml(3 * 13, rate), 488);
+    }
+
+    #[test]
+    fn calibration_needs_water_and_time() {
+        assert_eq!(flow_rate(0.0, 20), None);
+        assert_eq!(flow_rate(250.0, 0), None);
+    }
+
+    #[test]
+    fn secs_for_volume_rounds_and_clamps() {
+        assert_eq!(secs_for_volume(500, 0, 12.5), 40);
+        assert_eq!(secs_for_volume(500, 1, 12.5), 40);
+        assert_eq!(secs_for_volume(10, 1, 12.5), 1);
+        assert_eq!(secs_for_volume(5, 1, 12.5), 0);
+        assert_eq!(secs_for_volume(u16::MAX, 1, 0.1), u16::MAX);
+    }
+
+    #[test]
+    fn resolve_turns_volumes_into_seconds() {
+        let cmd = PumpCommand {
+            volume_ml: 500,
+            pulses: 3,
+            soak_secs: 90,
+            ..PumpCommand::single(0)
+        };
+        let resolved = resolve(cmd, Some(12.5)).unwrap();
+        assert_eq!(
+            (resolved.duration_secs, resolved.pulses, resolved.soak_secs),
+            (13, 3, 90)
+        );
+        assert_eq!(resolved.volume_ml, 0);
+
+        // Not without a calibration
+        assert!(resolve(cmd, None).is_none());
+        // Seconds pass through
+        let plain = resolve(PumpCommand::single(20), None).unwrap();
+        assert_eq!(plain.duration_secs, 20);
+    }
+
+    #[test]
+    fn totals_start_afresh_each_day() {
+        let mut totals = Totals::default();
+        // Before the date is known
+        totals.add(100, None);
+        assert_eq!(totals.today_ml(None), 100);
+
+        totals.add(200, Some(10));
+        assert_eq!(totals.today_ml(Some(10)), 200);
+        totals.add(50, None);
+        assert_eq!(totals.today_ml(Some(10)), 250);
+        assert_eq!(totals.today_ml(Some(11)), 0);
+
+        totals.add(30, Some(11));
+        assert_eq!(totals.today_ml(Some(11)), 30);
+        assert_eq!(totals.total_ml, 380);
+    }
+
+    #[test]
+    fn stored_round_trips() {
+        let stored = stored();
+        assert_eq!(Stored::decode(&stored.encode()), Some(stored));
+        assert_eq!(Stored::decode(&Stored::new().encode()), Some(Stored::new()));
+    }
+
+    #[test]
+    fn stored_rejects_erased_torn_and_foreign_copies() {
+        assert_eq!(Stored::decode(&[0xFF; STORED_LEN]), None);
+        assert_eq!(Stored::decode(&[]), None);
+
+        let buf = stored().encode();
+        assert_eq!(Stored::decode(&buf[..STORED_LEN - 1]), None);
+        for i in [5, 20, 40, STORED_LEN - 1] {
+            let mut torn = buf;
+            torn[i] ^= 0x10;
+            assert_eq!(Stored::decode(&torn), None, "byte {i}");
+        }
+
+        let mut newer = buf;
+        newer[4] = VERSION + 1;
+        assert_eq!(Stored::decode(&newer), None);
+    }
+
+    #[test]
+    fn older_layouts_load_without_the_new_fields() {
+        let stored = stored();
+
+        let v2 = Stored::decode(&older(&stored, 2, V2_BODY_LEN)).unwrap();
+        assert_eq!(
+            v2,
+            Stored {
+                vacation_until: None,
+                ..stored
+            }
+        );
+
+        let v1 = Stored::decode(&older(&stored, 1, V1_BODY_LEN)).unwrap();
+        assert_eq!(
+            v1,
+            Stored {
+                target: None,
+                gain: None,
+                vacation_until: None,
+                ..stored
+            }
+        );
+    }
+
+    #[test]
+    fn newest_follows_the_write_count() {
+        let with_seq = |seq| Some(Stored { seq, ..stored() });
+        assert_eq!(Stored::newest(with_seq(3), with_seq(4)).unwrap().seq, 4);
+        assert_eq!(Stored::newest(with_seq(4), with_seq(3)).unwrap().seq, 4);
+        // Across the wrap of the counter
+        assert_eq!(
+            Stored::newest(with_seq(u32::MAX), with_seq(1)).unwrap().seq,
+            1
+        );
+        assert_eq!(
+            Stored::newest(with_seq(1), with_seq(u32::MAX)).unwrap().seq,
+            1
+        );
+
+        assert_eq!(Stored::newest(None, with_seq(2)).unwrap().seq, 2);
+        assert_eq!(Stored::newest(with_seq(2), None).unwrap().seq, 2);
+        assert_eq!(Stored::newest(None, None), None);
+    }
+}
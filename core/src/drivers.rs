@@ -0,0 +1,326 @@
+//! Known I2C chips: which address is which, and how their raw readings
+//! turn into [`Telemetry`]. The bus transactions are the firmware's.
+//!
+//! Reference values from the datasheets:
+//! - Sensirion CRC of 0xBE 0xEF is 0x92
+//! - SHT3x and SCD40: temperature 0x6666 is 25.0 °C, humidity 0x8000 is
+//!   50.0 %
+//! - AHT20: humidity 0x80000 is 50.0 %, temperature 0x60000 is 25.0 °C
+//! - BH1750: 600 counts in high resolution mode is 500 lx at the default
+//!   measurement time (MTreg 69), 1113 lx at MTreg 31
+//! - INA219: bus register 0x5DC0 is 12.0 V; a shunt register of 1000 is
+//!   10 mV, 100 mA through 0.1 Ω
+
+use crate::telemetry::Telemetry;
+
+// The chip id register of the Bosch sensors and what it reads
+pub const CHIP_ID_REGISTER: u8 = 0xD0;
+const BME280_CHIP_ID: u8 = 0x60;
+const BMP280_CHIP_ID: u8 = 0x58;
+
+// The BH1750's default measurement time register, and the shortest, which
+// takes full sun up to about 120 klx without saturating
+const BH1750_DEFAULT_MTREG: u8 = 69;
+pub const BH1750_MTREG: u8 = 31;
+
+// The 7-bit addresses a bus scan goes through
+pub const SCAN_ADDRESSES: core::ops::RangeInclusive<u8> = 0x08..=0x77;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Bme280,
+    Bmp280,
+    Sht3x,
+    Aht20,
+    Bh1750,
+    Scd40,
+    Ina219,
+    Ssd1306,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Bme280 => "BME280",
+            Kind::Bmp280 => "BMP280",
+            Kind::Sht3x => "SHT3x",
+            Kind::Aht20 => "AHT20",
+            Kind::Bh1750 => "BH1750",
+            Kind::Scd40 => "SCD40",
+            Kind::Ina219 => "INA219",
+            Kind::Ssd1306 => "SSD1306",
+        }
+    }
+}
+
+/// Whether the chip at `address` can only be told by its chip id.
+pub fn needs_chip_id(address: u8) -> bool {
+    matches!(address, 0x76 | 0x77)
+}
+
+/// The chip answering at `address`; `None` for unknown addresses.
+pub fn identify(address: u8, chip_id: Option<u8>) -> Option<Kind> {
+    match address {
+        0x76 | 0x77 => match chip_id? {
+            BME280_CHIP_ID => Some(Kind::Bme280),
+            BMP280_CHIP_ID => Some(Kind::Bmp280),
+            _ => None,
+        },
+        0x44 | 0x45 => Some(Kind::Sht3x),
+        0x38 => Some(Kind::Aht20),
+        0x23 | 0x5C => Some(Kind::Bh1750),
+        0x62 => Some(Kind::Scd40),
+        0x40..=0x4F => Some(Kind::Ina219),
+        0x3C | 0x3D => Some(Kind::Ssd1306),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    Crc,
+    // The chip has no new measurement yet
+    NotReady,
+}
+
+/// CRC-8 of Sensirion and Aosong chips: polynomial 0x31, start 0xFF.
+pub fn sensirion_crc(data: &[u8]) -> u8 {
+    let mut crc = 0xFF;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+// Words of two bytes each followed by their CRC
+fn checked_words<const N: usize>(buf: &[u8]) -> Result<[u16; N], DecodeError> {
+    let mut words = [0; N];
+    for (word, chunk) in words.iter_mut().zip(buf.chunks_exact(3)) {
+        if sensirion_crc(&chunk[..2]) != chunk[2] {
+            return Err(DecodeError::Crc);
+        }
+        *word = u16::from_be_bytes([chunk[0], chunk[1]]);
+    }
+    Ok(words)
+}
+
+fn sensirion_temperature(raw: u16) -> f32 {
+    -45.0 + 175.0 * raw as f32 / 65535.0
+}
+
+fn sensirion_humidity(raw: u16) -> f32 {
+    100.0 * raw as f32 / 65535.0
+}
+
+/// A single-shot SHT3x measurement: temperature and humidity words.
+pub fn decode_sht3x(buf: &[u8; 6], out: &mut Telemetry) -> Result<(), DecodeError> {
+    let [t, rh] = checked_words::<2>(buf)?;
+    out.push("sht3x_temp_c", sensirion_temperature(t));
+    out.push("sht3x_rh_pct", sensirion_humidity(rh));
+    Ok(())
+}
+
+/// An AHT20 measurement: status, 20-bit humidity and temperature, CRC.
+pub fn decode_aht20(buf: &[u8; 7], out: &mut Telemetry) -> Result<(), DecodeError> {
+    if buf[0] & 0x80 != 0 {
+        return Err(DecodeError::NotReady);
+    }
+    if sensirion_crc(&buf[..6]) != buf[6] {
+        return Err(DecodeError::Crc);
+    }
+    let rh = (buf[1] as u32) << 12 | (buf[2] as u32) << 4 | (buf[3] as u32) >> 4;
+    let t = (buf[3] as u32 & 0x0F) << 16 | (buf[4] as u32) << 8 | buf[5] as u32;
+    let full = (1 << 20) as f32;
+    out.push("aht20_temp_c", t as f32 / full * 200.0 - 50.0);
+    out.push("aht20_rh_pct", rh as f32 / full * 100.0);
+    Ok(())
+}
+
+/// A BH1750 high resolution count, taken with measurement time `mtreg`.
+pub fn decode_bh1750(buf: &[u8; 2], mtreg: u8, out: &mut Telemetry) -> Result<(), DecodeError> {
+    let counts = u16::from_be_bytes(*buf) as f32;
+    out.push(
+        "bh1750_lux",
+        counts / 1.2 * BH1750_DEFAULT_MTREG as f32 / mtreg as f32,
+    );
+    Ok(())
+}
+
+/// An SCD40 measurement: CO2, temperature and humidity words.
+pub fn decode_scd40(buf: &[u8; 9], out: &mut Telemetry) -> Result<(), DecodeError> {
+    let [co2, t, rh] = checked_words::<3>(buf)?;
+    // The first reading after start-up is zero
+    if co2 == 0 {
+        return Err(DecodeError::NotReady);
+    }
+    out.push("scd40_co2_ppm", co2 as f32);
+    out.push("scd40_temp_c", sensirion_temperature(t));
+    out.push("scd40_rh_pct", sensirion_humidity(rh));
+    Ok(())
+}
+
+/// The INA219 bus and shunt voltage registers, with the shunt resistance.
+pub fn decode_ina219(
+    bus: &[u8; 2],
+    shunt: &[u8; 2],
+    shunt_ohms: f32,
+    out: &mut Telemetry,
+) -> Result<(), DecodeError> {
+    // 4 mV steps from bit 3; bit 0 is the overflow flag
+    let volts = (u16::from_be_bytes(*bus) >> 3) as f32 * 0.004;
+    // 10 µV steps, signed
+    let shunt_mv = i16::from_be_bytes(*shunt) as f32 * 0.01;
+    let milliamps = shunt_mv / shunt_ohms;
+    out.push("ina219_bus_v", volts);
+    out.push("ina219_ma", milliamps);
+    out.push("ina219_mw", volts * milliamps);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(value: Option<f32>, expected: f32) {
+        let value = value.unwrap();
+        assert!((value - expected).abs() < 0.01, "{value} != {expected}");
+    }
+
+    // A word followed by its CRC
+    fn word(raw: u16) -> [u8; 3] {
+        let [hi, lo] = raw.to_be_bytes();
+        [hi, lo, sensirion_crc(&[hi, lo])]
+    }
+
+    #[test]
+    fn crc_matches_the_datasheet() {
+        assert_eq!(sensirion_crc(&[0xBE, 0xEF]), 0x92);
+    }
+
+    #[test]
+    fn bosch_sensors_are_told_apart_by_chip_id() {
+        for address in [0x76, 0x77] {
+            assert!(needs_chip_id(address));
+            assert_eq!(identify(address, Some(0x60)), Some(Kind::Bme280));
+            assert_eq!(identify(address, Some(0x58)), Some(Kind::Bmp280));
+            assert_eq!(identify(address, Some(0x55)), None);
+            assert_eq!(identify(address, None), None);
+        }
+        assert!(!needs_chip_id(0x44));
+    }
+
+    #[test]
+    fn addresses_map_to_their_chips() {
+        // The SHT3x addresses fall in the INA219 range
+        assert_eq!(identify(0x44, None), Some(Kind::Sht3x));
+        assert_eq!(identify(0x45, None), Some(Kind::Sht3x));
+        assert_eq!(identify(0x40, None), Some(Kind::Ina219));
+        assert_eq!(identify(0x4F, None), Some(Kind::Ina219));
+        assert_eq!(identify(0x38, None), Some(Kind::Aht20));
+        assert_eq!(identify(0x23, None), Some(Kind::Bh1750));
+        assert_eq!(identify(0x5C, None), Some(Kind::Bh1750));
+        assert_eq!(identify(0x62, None), Some(Kind::Scd40));
+        assert_eq!(identify(0x3C, None), Some(Kind::Ssd1306));
+        for unknown in [0x08, 0x50, 0x68, 0x75] {
+            assert_eq!(identify(unknown, None), None);
+        }
+    }
+
+    #[test]
+    fn sht3x_reads_the_reference_values() {
+        let mut buf = [0; 6];
+        buf[..3].copy_from_slice(&word(0x6666));
+        buf[3..].copy_from_slice(&word(0x8000));
+        let mut out = Telemetry::new();
+        assert_eq!(decode_sht3x(&buf, &mut out), Ok(()));
+        close(out.get("sht3x_temp_c"), 25.0);
+        close(out.get("sht3x_rh_pct"), 50.0);
+
+        buf[5] ^= 1;
+        let mut out = Telemetry::new();
+        assert_eq!(decode_sht3x(&buf, &mut out), Err(DecodeError::Crc));
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn aht20_reads_the_reference_values() {
+        let mut buf = [0x1C, 0x80, 0x00, 0x06, 0x00, 0x00, 0];
+        buf[6] = sensirion_crc(&buf[..6]);
+        let mut out = Telemetry::new();
+        assert_eq!(decode_aht20(&buf, &mut out), Ok(()));
+        close(out.get("aht20_rh_pct"), 50.0);
+        close(out.get("aht20_temp_c"), 25.0);
+
+        let mut busy = buf;
+        busy[0] |= 0x80;
+        assert_eq!(
+            decode_aht20(&busy, &mut Telemetry::new()),
+            Err(DecodeError::NotReady)
+        );
+        buf[6] ^= 1;
+        assert_eq!(
+            decode_aht20(&buf, &mut Telemetry::new()),
+            Err(DecodeError::Crc)
+        );
+    }
+
+    #[test]
+    fn bh1750_scales_by_measurement_time() {
+        let counts = 600u16.to_be_bytes();
+        let mut out = Telemetry::new();
+        decode_bh1750(&counts, BH1750_DEFAULT_MTREG, &mut out).unwrap();
+        close(out.get("bh1750_lux"), 500.0);
+
+        let mut out = Telemetry::new();
+        decode_bh1750(&counts, BH1750_MTREG, &mut out).unwrap();
+        assert!((out.get("bh1750_lux").unwrap() - 1113.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn scd40_waits_for_its_first_reading() {
+        let mut buf = [0; 9];
+        buf[3..6].copy_from_slice(&word(0x6666));
+        buf[6..].copy_from_slice(&word(0x8000));
+        buf[..3].copy_from_slice(&word(0));
+        assert_eq!(
+            decode_scd40(&buf, &mut Telemetry::new()),
+            Err(DecodeError::NotReady)
+        );
+
+        buf[..3].copy_from_slice(&word(415));
+        let mut out = Telemetry::new();
+        assert_eq!(decode_scd40(&buf, &mut out), Ok(()));
+        assert_eq!(out.get("scd40_co2_ppm"), Some(415.0));
+        close(out.get("scd40_temp_c"), 25.0);
+        close(out.get("scd40_rh_pct"), 50.0);
+
+        buf[1] ^= 1;
+        assert_eq!(
+            decode_scd40(&buf, &mut Telemetry::new()),
+            Err(DecodeError::Crc)
+        );
+    }
+
+    #[test]
+    fn ina219_reads_bus_and_signed_shunt() {
+        let bus = 0x5DC0u16.to_be_bytes();
+        let mut out = Telemetry::new();
+        decode_ina219(&bus, &1000i16.to_be_bytes(), 0.1, &mut out).unwrap();
+        close(out.get("ina219_bus_v"), 12.0);
+        close(out.get("ina219_ma"), 100.0);
+        close(out.get("ina219_mw"), 1200.0);
+
+        // Current flowing back through the shunt
+        let mut out = Telemetry::new();
+        decode_ina219(&bus, &(-1000i16).to_be_bytes(), 0.1, &mut out).unwrap();
+        close(out.get("ina219_ma"), -100.0);
+        close(out.get("ina219_mw"), -1200.0);
+    }
+}
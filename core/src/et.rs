@@ -0,0 +1,130 @@
+//! Vapour pressure and reference evapotranspiration after FAO-56
+//! (Allen et al., 1998), from the inputs the device has: air temperature,
+//! humidity, latitude and the date.
+//!
+//! Reference values the formulas reproduce:
+//! - e°(25 °C) = 3.168 kPa (FAO-56 Annex 2, Table 2.3)
+//! - Ra at 20° S on 3 September = 32.2 MJ m⁻² day⁻¹ (FAO-56 Example 8)
+//! - Hargreaves ET₀ with Tmin 14.8, Tmax 26.6 °C and Ra 32.2 = 4.0 mm/day
+
+use core::f32::consts::PI;
+
+use libm::{acosf, cosf, expf, sinf, sqrtf, tanf};
+
+// Solar constant, MJ m⁻² min⁻¹
+const SOLAR_CONSTANT: f32 = 0.0820;
+// Converts MJ m⁻² day⁻¹ to mm/day of evaporated water
+const MJ_TO_MM: f32 = 0.408;
+
+/// Saturation vapour pressure in kPa at `temperature` °C (FAO-56 eq. 11).
+pub fn saturation_vapour_pressure(temperature: f32) -> f32 {
+    0.6108 * expf(17.27 * temperature / (temperature + 237.3))
+}
+
+/// Actual vapour pressure in kPa from relative humidity (FAO-56 eq. 19
+/// with a single reading).
+pub fn actual_vapour_pressure(temperature: f32, humidity: f32) -> f32 {
+    saturation_vapour_pressure(temperature) * humidity.clamp(0.0, 100.0) / 100.0
+}
+
+/// Vapour pressure deficit in kPa: how strongly the air pulls water out of
+/// leaves and soil.
+pub fn vapour_pressure_deficit(temperature: f32, humidity: f32) -> f32 {
+    saturation_vapour_pressure(temperature) - actual_vapour_pressure(temperature, humidity)
+}
+
+/// Extraterrestrial radiation in MJ m⁻² day⁻¹ (FAO-56 eqs. 21-25).
+///
+/// `latitude` is in degrees, negative south of the equator.
+pub fn extraterrestrial_radiation(latitude: f32, day_of_year: u16) -> f32 {
+    let phi = latitude.to_radians();
+    let angle = 2.0 * PI * day_of_year as f32 / 365.0;
+    let inverse_distance = 1.0 + 0.033 * cosf(angle);
+    let declination = 0.409 * sinf(angle - 1.39);
+    // Clamped for polar day and night
+    let sunset = acosf((-tanf(phi) * tanf(declination)).clamp(-1.0, 1.0));
+
+    24.0 * 60.0 / PI
+        * SOLAR_CONSTANT
+        * inverse_distance
+        * (sunset * sinf(phi) * sinf(declination) + cosf(phi) * cosf(declination) * sinf(sunset))
+}
+
+/// Hargreaves reference evapotranspiration in mm/day (FAO-56 eq. 52).
+///
+/// Needs only the day's temperature range and `radiation` from
+/// [`extraterrestrial_radiation`].
+pub fn hargreaves_et0(t_min: f32, t_max: f32, radiation: f32) -> f32 {
+    let t_mean = (t_min + t_max) / 2.0;
+    let range = (t_max - t_min).max(0.0);
+    (0.0023 * (t_mean + 17.8) * sqrtf(range) * MJ_TO_MM * radiation).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{value} is not {expected} ± {tolerance}"
+        );
+    }
+
+    #[test]
+    fn saturation_vapour_pressure_matches_fao56_table() {
+        // FAO-56 Annex 2, Table 2.3
+        for (temperature, expected) in [
+            (1.0, 0.657),
+            (10.0, 1.228),
+            (20.0, 2.338),
+            (25.0, 3.168),
+            (30.0, 4.243),
+            (40.0, 7.378),
+        ] {
+            assert_near(saturation_vapour_pressure(temperature), expected, 0.003);
+        }
+    }
+
+    #[test]
+    fn vapour_pressure_splits_by_humidity() {
+        assert_near(actual_vapour_pressure(25.0, 50.0), 1.584, 0.002);
+        assert_near(vapour_pressure_deficit(25.0, 50.0), 1.584, 0.002);
+        assert_near(vapour_pressure_deficit(25.0, 100.0), 0.0, 1e-6);
+        // Readings past the sensor's range are clamped
+        assert_near(actual_vapour_pressure(25.0, 120.0), 3.168, 0.002);
+        assert_eq!(actual_vapour_pressure(25.0, -5.0), 0.0);
+    }
+
+    #[test]
+    fn extraterrestrial_radiation_matches_fao56_example_8() {
+        // 20° S on 3 September, day 246
+        assert_near(extraterrestrial_radiation(-20.0, 246), 32.2, 0.1);
+    }
+
+    #[test]
+    fn extraterrestrial_radiation_over_the_year() {
+        // FAO-56 Annex 2, Table 2.6, mid-month values at 50° N
+        assert_near(extraterrestrial_radiation(50.0, 15), 8.5, 0.5);
+        assert_near(extraterrestrial_radiation(50.0, 166), 41.9, 0.5);
+        // Polar night and polar day
+        assert_near(extraterrestrial_radiation(80.0, 355), 0.0, 0.01);
+        assert!(extraterrestrial_radiation(80.0, 172) > extraterrestrial_radiation(50.0, 172));
+    }
+
+    #[test]
+    fn hargreaves_matches_the_fao56_example() {
+        assert_near(hargreaves_et0(14.8, 26.6, 32.2), 4.0, 0.05);
+        // More radiation or a wider range means more ET
+        assert!(hargreaves_et0(14.8, 26.6, 40.0) > hargreaves_et0(14.8, 26.6, 32.2));
+        assert!(hargreaves_et0(10.0, 30.0, 32.2) > hargreaves_et0(15.0, 25.0, 32.2));
+    }
+
+    #[test]
+    fn hargreaves_is_never_negative() {
+        assert_eq!(hargreaves_et0(20.0, 20.0, 32.2), 0.0);
+        // A swapped range counts as none
+        assert_eq!(hargreaves_et0(26.6, 14.8, 32.2), 0.0);
+        assert_eq!(hargreaves_et0(-35.0, -25.0, 10.0), 0.0);
+    }
+}
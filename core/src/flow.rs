@@ -0,0 +1,258 @@
+//! Hall-effect flow meter: pulses to volume, and spotting a pump that runs
+//! dry or a line that leaks.
+//!
+//! Worked example at 450 pulses/l (YF-S201): 90 pulses are 200 ml; a
+//! pulse target of 200 ml is reached after 90 pulses. With a 5 s priming
+//! time and 2 ml/s minimum, fewer than 5 pulses in a 5 s window of
+//! pumping is no flow. A pulse that stops before a whole window is
+//! judged over what it ran: 3 pulses in the 3 s after priming is fine.
+
+use libm::{ceilf, roundf};
+
+// The shortest stretch of pumping worth judging; the meter's count is too
+// coarse below it
+const MIN_JUDGE_MS: u64 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FlowSettings {
+    pub pulses_per_litre: f32,
+    // How long the pump gets to prime, and the window its flow is judged
+    // over
+    pub prime_secs: u16,
+    // Less than this while pumping means a dry tank or a blocked line, ml/s
+    pub min_ml_per_sec: f32,
+    // How long the line keeps draining after the pump stops
+    pub drain_secs: u16,
+    // More than this flowing within `leak_window_secs` of the pump being
+    // off is a leak
+    pub leak_ml: u32,
+    pub leak_window_secs: u32,
+}
+
+pub fn pulses_to_ml(pulses: u32, pulses_per_litre: f32) -> u32 {
+    roundf(pulses as f32 * 1000.0 / pulses_per_litre).max(0.0) as u32
+}
+
+/// Pulses the meter gives for at least `ml`.
+pub fn ml_to_pulses(ml: u32, pulses_per_litre: f32) -> u32 {
+    ceilf(ml as f32 * pulses_per_litre / 1000.0).max(0.0) as u32
+}
+
+/// Anomalies currently seen; each stays set until the meter shows
+/// otherwise.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FlowStatus {
+    // The last pump run had too little flow
+    pub no_flow: bool,
+    // Water moved while the pump was off
+    pub leak: bool,
+}
+
+/// Judges the meter count against the pump state; fed about once a second.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlowMonitor {
+    pump_on: bool,
+    // Start time and count of the window being judged, once the pump has
+    // primed or the line has drained
+    window: Option<(u64, u32)>,
+    // Whether the pump's flow has been judged since it last switched on
+    judged: bool,
+    switched_ms: u64,
+    status: FlowStatus,
+}
+
+impl FlowMonitor {
+    pub const fn new() -> Self {
+        Self {
+            pump_on: false,
+            window: None,
+            judged: false,
+            switched_ms: 0,
+            status: FlowStatus {
+                no_flow: false,
+                leak: false,
+            },
+        }
+    }
+
+    pub fn observe(
+        &mut self,
+        now_ms: u64,
+        pump_on: bool,
+        pulses: u32,
+        settings: &FlowSettings,
+    ) -> FlowStatus {
+        if pump_on != self.pump_on {
+            // A pulse too short for a whole window is judged over its tail
+            if let (true, false, Some((start_ms, start_pulses))) =
+                (self.pump_on, self.judged, self.window)
+            {
+                let elapsed_ms = now_ms.saturating_sub(start_ms);
+                if elapsed_ms >= MIN_JUDGE_MS {
+                    self.judge(elapsed_ms, pulses.wrapping_sub(start_pulses), settings);
+                }
+            }
+            self.pump_on = pump_on;
+            self.judged = false;
+            self.switched_ms = now_ms;
+            self.window = None;
+        }
+
+        let settle_ms = if pump_on {
+            settings.prime_secs
+        } else {
+            settings.drain_secs
+        } as u64
+            * 1000;
+        if now_ms.saturating_sub(self.switched_ms) < settle_ms {
+            return self.status;
+        }
+
+        let (start_ms, start_pulses) = *self.window.get_or_insert((now_ms, pulses));
+        let elapsed_ms = now_ms.saturating_sub(start_ms);
+
+        if pump_on {
+            if elapsed_ms >= settings.prime_secs as u64 * 1000 {
+                self.judge(elapsed_ms, pulses.wrapping_sub(start_pulses), settings);
+                self.window = Some((now_ms, pulses));
+            }
+        } else {
+            let ml = pulses_to_ml(pulses.wrapping_sub(start_pulses), settings.pulses_per_litre);
+            if ml > settings.leak_ml {
+                self.status.leak = true;
+            }
+            // A quiet window clears an earlier leak
+            if elapsed_ms >= settings.leak_window_secs as u64 * 1000 {
+                self.status.leak = ml > settings.leak_ml;
+                self.window = Some((now_ms, pulses));
+            }
+        }
+
+        self.status
+    }
+
+    fn judge(&mut self, elapsed_ms: u64, pulses: u32, settings: &FlowSettings) {
+        let ml = pulses_to_ml(pulses, settings.pulses_per_litre);
+        let rate = ml as f32 * 1000.0 / elapsed_ms as f32;
+        self.status.no_flow = rate < settings.min_ml_per_sec;
+        self.judged = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: FlowSettings = FlowSettings {
+        pulses_per_litre: 450.0,
+        prime_secs: 5,
+        min_ml_per_sec: 2.0,
+        drain_secs: 10,
+        leak_ml: 50,
+        leak_window_secs: 60,
+    };
+
+    // Feeds a second at a time from `from_secs` to `to_secs`, the meter
+    // giving `per_sec` pulses a second, and returns the last status
+    fn run(
+        monitor: &mut FlowMonitor,
+        pulses: &mut u32,
+        from_secs: u64,
+        to_secs: u64,
+        pump_on: bool,
+        per_sec: u32,
+    ) -> FlowStatus {
+        let mut status = FlowStatus::default();
+        for secs in from_secs..to_secs {
+            status = monitor.observe(secs * 1000, pump_on, *pulses, &SETTINGS);
+            *pulses = pulses.wrapping_add(per_sec);
+        }
+        status
+    }
+
+    #[test]
+    fn worked_example() {
+        assert_eq!(pulses_to_ml(90, 450.0), 200);
+        assert_eq!(ml_to_pulses(200, 450.0), 90);
+        assert_eq!(ml_to_pulses(201, 450.0), 91);
+        assert_eq!(pulses_to_ml(0, 450.0), 0);
+    }
+
+    #[test]
+    fn a_long_run_is_judged_every_window() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = 0;
+        // 2 pulses a second is 4.4 ml/s
+        assert!(!run(&mut monitor, &mut pulses, 0, 30, true, 2).no_flow);
+
+        // The tank runs dry
+        assert!(run(&mut monitor, &mut pulses, 30, 42, true, 0).no_flow);
+        // And is refilled
+        assert!(!run(&mut monitor, &mut pulses, 42, 54, true, 2).no_flow);
+    }
+
+    #[test]
+    fn a_short_pulse_is_judged_over_what_it_ran() {
+        // Primed at 5 s, off at 8 s: too short for a whole window
+        for (per_sec, no_flow) in [(0, true), (1, false)] {
+            let mut monitor = FlowMonitor::new();
+            let mut pulses = 0;
+            assert!(!run(&mut monitor, &mut pulses, 0, 8, true, per_sec).no_flow);
+            let status = monitor.observe(8000, false, pulses, &SETTINGS);
+            assert_eq!(status.no_flow, no_flow, "{per_sec} pulses a second");
+        }
+    }
+
+    #[test]
+    fn a_pulse_that_never_primes_is_not_judged() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = 0;
+        run(&mut monitor, &mut pulses, 0, 5, true, 0);
+        assert!(!monitor.observe(5000, false, pulses, &SETTINGS).no_flow);
+
+        // Nor one that ran under a second after priming
+        run(&mut monitor, &mut pulses, 20, 26, true, 0);
+        assert!(!monitor.observe(25_500, false, pulses, &SETTINGS).no_flow);
+    }
+
+    #[test]
+    fn a_judged_run_keeps_its_verdict_through_the_tail() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = 0;
+        // Judged dry at 10 s; the last 2 s alone would pass
+        run(&mut monitor, &mut pulses, 0, 11, true, 0);
+        run(&mut monitor, &mut pulses, 11, 13, true, 5);
+        assert!(monitor.observe(13_000, false, pulses, &SETTINGS).no_flow);
+    }
+
+    #[test]
+    fn the_line_may_drain_after_a_run() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = 0;
+        run(&mut monitor, &mut pulses, 0, 30, true, 2);
+        // 10 pulses a second is 22 ml/s, but only while draining
+        assert!(!run(&mut monitor, &mut pulses, 30, 40, false, 10).leak);
+        assert!(!run(&mut monitor, &mut pulses, 40, 200, false, 0).leak);
+    }
+
+    #[test]
+    fn a_leak_is_raised_and_cleared() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = 0;
+        run(&mut monitor, &mut pulses, 0, 10, false, 0);
+        // 1 pulse a second passes 50 ml within 23 s
+        let status = run(&mut monitor, &mut pulses, 10, 40, false, 1);
+        assert!(status.leak);
+
+        // It stays raised until a whole quiet window has passed
+        assert!(run(&mut monitor, &mut pulses, 40, 100, false, 0).leak);
+        assert!(!run(&mut monitor, &mut pulses, 100, 200, false, 0).leak);
+    }
+
+    #[test]
+    fn the_count_may_wrap() {
+        let mut monitor = FlowMonitor::new();
+        let mut pulses = u32::MAX - 10;
+        assert!(!run(&mut monitor, &mut pulses, 0, 30, true, 2).no_flow);
+    }
+}
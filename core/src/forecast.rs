@@ -0,0 +1,237 @@
+//! Soil drying trend: a least-squares line through the recent moisture,
+//! and when it will cross the dry threshold.
+//!
+//! Readings are averaged over `interval_secs` and the last [`TREND_POINTS`]
+//! averages are kept in a ring. Worked example with 30 min averages
+//! falling 0.5 % an hour to 44 %: with the threshold at 40 %, the soil is
+//! dry in 8 hours.
+
+const SECS_PER_HOUR: f32 = 3600.0;
+
+pub const TREND_POINTS: usize = 18;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ForecastSettings {
+    // Readings are averaged over this long into one trend point
+    pub interval_secs: u32,
+    // Points needed before the trend is trusted
+    pub min_points: u8,
+    // A point this much wetter than the last means watering or rain, and
+    // starts a new trend, %
+    pub reset_rise_pct: f32,
+    // A forecast further out than this means the soil is hardly drying, h
+    pub max_hours: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Trend {
+    // Time and mean moisture of each point; a ring, `next` is the oldest
+    // once full
+    points: [(u32, f32); TREND_POINTS],
+    len: usize,
+    next: usize,
+    // The interval being averaged: start, sum, count
+    pending: Option<(u32, f32, u16)>,
+}
+
+impl Trend {
+    pub const fn new() -> Self {
+        Self {
+            points: [(0, 0.0); TREND_POINTS],
+            len: 0,
+            next: 0,
+            pending: None,
+        }
+    }
+
+    pub fn observe(&mut self, t_secs: u32, moisture: f32, settings: &ForecastSettings) {
+        let (start, sum, count) = self.pending.get_or_insert((t_secs, 0.0, 0));
+        if t_secs.saturating_sub(*start) < settings.interval_secs {
+            *sum += moisture;
+            *count += 1;
+            return;
+        }
+
+        let (start, sum, count) = (*start, *sum, *count);
+        self.pending = Some((t_secs, moisture, 1));
+        if count > 0 {
+            // The mean stands for the middle of its interval
+            self.push(
+                start + settings.interval_secs / 2,
+                sum / count as f32,
+                settings,
+            );
+        }
+    }
+
+    fn push(&mut self, t_secs: u32, moisture: f32, settings: &ForecastSettings) {
+        if let Some((_, last)) = self.latest()
+            && moisture - last >= settings.reset_rise_pct
+        {
+            self.len = 0;
+            self.next = 0;
+        }
+        self.points[self.next] = (t_secs, moisture);
+        self.next = (self.next + 1) % TREND_POINTS;
+        self.len = (self.len + 1).min(TREND_POINTS);
+    }
+
+    fn latest(&self) -> Option<(u32, f32)> {
+        (self.len > 0).then(|| self.points[(self.next + TREND_POINTS - 1) % TREND_POINTS])
+    }
+
+    /// The fitted line: moisture at the latest point and its change per
+    /// hour; `None` until `min_points` are in.
+    pub fn fit(&self, settings: &ForecastSettings) -> Option<(f32, f32)> {
+        if self.len < (settings.min_points as usize).max(2) {
+            return None;
+        }
+        let (t_last, _) = self.latest()?;
+        let points = || {
+            self.points
+                .iter()
+                .cycle()
+                .skip(self.next + TREND_POINTS - self.len)
+                .take(self.len)
+                // Hours before the latest point, small enough for f32
+                .map(move |&(t, m)| (-(t_last.saturating_sub(t) as f32) / SECS_PER_HOUR, m))
+        };
+
+        let n = self.len as f32;
+        let (sum_x, sum_y) = points().fold((0.0, 0.0), |(sx, sy), (x, y)| (sx + x, sy + y));
+        let (mean_x, mean_y) = (sum_x / n, sum_y / n);
+        let (sxx, sxy) = points().fold((0.0, 0.0), |(sxx, sxy), (x, y)| {
+            let dx = x - mean_x;
+            (sxx + dx * dx, sxy + dx * (y - mean_y))
+        });
+        if sxx <= 0.0 {
+            return None;
+        }
+        let slope = sxy / sxx;
+        Some((mean_y - slope * mean_x, slope))
+    }
+
+    /// Hours until the fitted line falls to `dry_below`: 0 if it already
+    /// has, `None` if the soil is not drying within `max_hours` or the
+    /// trend is not known yet.
+    pub fn hours_until(&self, dry_below: f32, settings: &ForecastSettings) -> Option<f32> {
+        let (now, per_hour) = self.fit(settings)?;
+        if now <= dry_below {
+            return Some(0.0);
+        }
+        let hours = (now - dry_below) / -per_hour;
+        (per_hour < 0.0 && hours <= settings.max_hours).then_some(hours)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: ForecastSettings = ForecastSettings {
+        interval_secs: 1800,
+        min_points: 4,
+        reset_rise_pct: 2.0,
+        max_hours: 72.0,
+    };
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not {expected}"
+        );
+    }
+
+    // Readings every 5 min from `from_secs` up to `to_secs`
+    fn feed(trend: &mut Trend, from_secs: u32, to_secs: u32, moisture: impl Fn(u32) -> f32) {
+        for t_secs in (from_secs..to_secs).step_by(300) {
+            trend.observe(t_secs, moisture(t_secs), &SETTINGS);
+        }
+    }
+
+    #[test]
+    fn worked_example() {
+        // Falling 0.5 % an hour to 44 % at the middle of the last interval
+        let last = 9 * 1800 + 900;
+        let moisture = |t: u32| 44.0 + 0.5 * (last as f32 - t as f32) / SECS_PER_HOUR;
+        let mut trend = Trend::new();
+        // Up to the first reading of the 11th interval
+        feed(&mut trend, 0, 10 * 1800 + 1, moisture);
+
+        let (now, per_hour) = trend.fit(&SETTINGS).unwrap();
+        // Each mean is of readings centred 150 s before its point's time
+        assert_near(now, 44.0 + 0.5 * 150.0 / SECS_PER_HOUR, 0.001);
+        assert_near(per_hour, -0.5, 0.001);
+        assert_near(trend.hours_until(40.0, &SETTINGS).unwrap(), 8.0, 0.05);
+    }
+
+    #[test]
+    fn the_trend_waits_for_enough_points() {
+        let mut trend = Trend::new();
+        // Three finished intervals
+        feed(&mut trend, 0, 3 * 1800 + 1, |t| 50.0 - t as f32 / 3600.0);
+        assert_eq!(trend.fit(&SETTINGS), None);
+        assert_eq!(trend.hours_until(40.0, &SETTINGS), None);
+        feed(&mut trend, 3 * 1800 + 300, 4 * 1800 + 1, |t| {
+            50.0 - t as f32 / 3600.0
+        });
+        assert!(trend.fit(&SETTINGS).is_some());
+    }
+
+    #[test]
+    fn readings_are_averaged_into_points() {
+        // Noise of ±1 % around a flat 45 %
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 6 * 1800 + 1, |t| {
+            45.0 + if (t / 300) % 2 == 0 { 1.0 } else { -1.0 }
+        });
+        let (now, per_hour) = trend.fit(&SETTINGS).unwrap();
+        assert_near(now, 45.0, 0.001);
+        assert_near(per_hour, 0.0, 0.001);
+    }
+
+    #[test]
+    fn soil_not_drying_has_no_forecast() {
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 6 * 1800 + 1, |_| 45.0);
+        assert_eq!(trend.hours_until(40.0, &SETTINGS), None);
+
+        // 0.05 % an hour is 100 h to go
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 6 * 1800 + 1, |t| {
+            45.0 - 0.05 * t as f32 / 3600.0
+        });
+        assert_eq!(trend.hours_until(40.0, &SETTINGS), None);
+    }
+
+    #[test]
+    fn dry_soil_is_due_now() {
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 6 * 1800 + 1, |t| 39.0 + t as f32 / 3600.0);
+        assert_eq!(trend.hours_until(45.0, &SETTINGS), Some(0.0));
+    }
+
+    #[test]
+    fn watering_starts_a_new_trend() {
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 6 * 1800, |t| 45.0 - t as f32 / 3600.0);
+        assert!(trend.fit(&SETTINGS).is_some());
+
+        // 10 % wetter after a run; the old points are dropped
+        feed(&mut trend, 6 * 1800, 8 * 1800 + 1, |_| 50.0);
+        assert_eq!(trend.fit(&SETTINGS), None);
+    }
+
+    #[test]
+    fn only_the_latest_points_are_fitted() {
+        // Fast drying, then slow for longer than the ring holds
+        let mut trend = Trend::new();
+        feed(&mut trend, 0, 10 * 1800, |t| 60.0 - 2.0 * t as f32 / 3600.0);
+        let start = 10 * 1800;
+        let end = start + (TREND_POINTS as u32 + 2) * 1800 + 1;
+        feed(&mut trend, start, end, |t| {
+            50.0 - 0.2 * (t - start) as f32 / 3600.0
+        });
+        assert_near(trend.fit(&SETTINGS).unwrap().1, -0.2, 0.001);
+    }
+}
@@ -0,0 +1,318 @@
+//! Weather rules that can veto a pump run, fed with every BME280 reading.
+
+use serde::Serialize;
+
+use crate::types::SensorData;
+
+const HOUR_SECS: u32 = 60 * 60;
+const DAY_SECS: u32 = 24 * HOUR_SECS;
+// Hourly pressure readings kept for the trend, oldest first
+pub(crate) const PRESSURE_HOURS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    Frost,
+    Humid,
+    RainLikely,
+    DailyLimit,
+}
+
+impl SkipReason {
+    pub(crate) const ALL: [SkipReason; 4] = [
+        SkipReason::Frost,
+        SkipReason::Humid,
+        SkipReason::RainLikely,
+        SkipReason::DailyLimit,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::Frost => "frost",
+            SkipReason::Humid => "humid",
+            SkipReason::RainLikely => "rain likely",
+            SkipReason::DailyLimit => "daily limit",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GuardSettings {
+    // No watering below this air temperature, °C
+    pub frost_below: f32,
+    // No watering above this relative humidity, %
+    pub humid_above: f32,
+    // A reading above this starts a 24 h heat wave, °C
+    pub heat_wave_above: f32,
+    pub runs_per_day: u8,
+    pub heat_wave_extra_runs: u8,
+    // Pressure fall over three hours that means rain is likely, hPa
+    pub rain_drop: f32,
+    pub rain_delay_secs: u32,
+}
+
+/// Guard state built from the readings; see [`Guards::admit`].
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Guards {
+    pub(crate) temperature: Option<f32>,
+    pub(crate) humidity: Option<f32>,
+    pub(crate) pressure: [Option<f32>; PRESSURE_HOURS],
+    pub(crate) pressure_since: Option<u32>,
+    pub(crate) hot_until: Option<u32>,
+    pub(crate) rain_until: Option<u32>,
+    // Days follow uptime, like the ET tracker
+    pub(crate) day_start: Option<u32>,
+    pub(crate) runs_today: u8,
+}
+
+impl Default for Guards {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Guards {
+    pub const fn new() -> Self {
+        Self {
+            temperature: None,
+            humidity: None,
+            pressure: [None; PRESSURE_HOURS],
+            pressure_since: None,
+            hot_until: None,
+            rain_until: None,
+            day_start: None,
+            runs_today: 0,
+        }
+    }
+
+    pub fn observe(&mut self, t_secs: u32, data: &SensorData, settings: &GuardSettings) {
+        self.temperature = data.temperature;
+        self.humidity = data.humidity;
+
+        if data
+            .temperature
+            .is_some_and(|t| t >= settings.heat_wave_above)
+        {
+            self.hot_until = Some(t_secs.saturating_add(DAY_SECS));
+        }
+
+        let Some(pressure) = data.pressure else {
+            return;
+        };
+
+        // Shift in one slot per hour boundary crossed
+        let since = *self.pressure_since.get_or_insert(t_secs);
+        let hours = (t_secs.saturating_sub(since) / HOUR_SECS) as usize;
+        if hours > 0 || self.pressure[PRESSURE_HOURS - 1].is_none() {
+            let shift = hours.clamp(1, PRESSURE_HOURS);
+            self.pressure.rotate_left(shift);
+            self.pressure[PRESSURE_HOURS - shift..].fill(None);
+            self.pressure[PRESSURE_HOURS - 1] = Some(pressure);
+            self.pressure_since = Some(since + hours as u32 * HOUR_SECS);
+        }
+
+        if let Some(oldest) = self.pressure[0]
+            && oldest - pressure >= settings.rain_drop
+        {
+            self.rain_until = Some(t_secs.saturating_add(settings.rain_delay_secs));
+        }
+    }
+
+    pub fn heat_wave(&self, t_secs: u32) -> bool {
+        self.hot_until.is_some_and(|until| t_secs < until)
+    }
+
+    /// Decides whether a run may start now and counts it if so. Readings
+    /// the BME280 could not take never block a run.
+    pub fn admit(&mut self, t_secs: u32, settings: &GuardSettings) -> Result<(), SkipReason> {
+        let start = *self.day_start.get_or_insert(t_secs);
+        let elapsed = t_secs.saturating_sub(start);
+        if elapsed >= DAY_SECS {
+            self.day_start = Some(start + elapsed / DAY_SECS * DAY_SECS);
+            self.runs_today = 0;
+        }
+
+        if self.temperature.is_some_and(|t| t < settings.frost_below) {
+            return Err(SkipReason::Frost);
+        }
+        if self.humidity.is_some_and(|h| h > settings.humid_above) {
+            return Err(SkipReason::Humid);
+        }
+        if self.rain_until.is_some_and(|until| t_secs < until) {
+            return Err(SkipReason::RainLikely);
+        }
+
+        let mut limit = settings.runs_per_day;
+        if self.heat_wave(t_secs) {
+            limit = limit.saturating_add(settings.heat_wave_extra_runs);
+        }
+        if self.runs_today >= limit {
+            return Err(SkipReason::DailyLimit);
+        }
+
+        self.runs_today += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: GuardSettings = GuardSettings {
+        frost_below: 2.0,
+        humid_above: 95.0,
+        heat_wave_above: 30.0,
+        runs_per_day: 2,
+        heat_wave_extra_runs: 1,
+        rain_drop: 3.0,
+        rain_delay_secs: 6 * HOUR_SECS,
+    };
+
+    fn reading(temperature: f32, humidity: f32, pressure: f32) -> SensorData {
+        SensorData {
+            temperature: Some(temperature),
+            humidity: Some(humidity),
+            pressure: Some(pressure),
+            ..SensorData::default()
+        }
+    }
+
+    fn guards_after(data: &SensorData) -> Guards {
+        let mut guards = Guards::new();
+        guards.observe(0, data, &SETTINGS);
+        guards
+    }
+
+    #[test]
+    fn frost_stops_a_run() {
+        let mut guards = guards_after(&reading(1.9, 60.0, 1013.0));
+        assert_eq!(guards.admit(0, &SETTINGS), Err(SkipReason::Frost));
+        let mut guards = guards_after(&reading(2.0, 60.0, 1013.0));
+        assert_eq!(guards.admit(0, &SETTINGS), Ok(()));
+    }
+
+    #[test]
+    fn very_humid_air_stops_a_run() {
+        let mut guards = guards_after(&reading(20.0, 96.0, 1013.0));
+        assert_eq!(guards.admit(0, &SETTINGS), Err(SkipReason::Humid));
+        let mut guards = guards_after(&reading(20.0, 95.0, 1013.0));
+        assert_eq!(guards.admit(0, &SETTINGS), Ok(()));
+    }
+
+    #[test]
+    fn missing_readings_never_block() {
+        let mut guards = guards_after(&SensorData::default());
+        assert_eq!(guards.admit(0, &SETTINGS), Ok(()));
+    }
+
+    #[test]
+    fn skipped_runs_do_not_count() {
+        let mut guards = guards_after(&reading(0.0, 60.0, 1013.0));
+        for _ in 0..5 {
+            assert_eq!(guards.admit(0, &SETTINGS), Err(SkipReason::Frost));
+        }
+        guards.observe(HOUR_SECS, &reading(10.0, 60.0, 1013.0), &SETTINGS);
+        assert_eq!(guards.admit(HOUR_SECS, &SETTINGS), Ok(()));
+        assert_eq!(guards.admit(HOUR_SECS, &SETTINGS), Ok(()));
+    }
+
+    #[test]
+    fn daily_limit_resets_with_the_day() {
+        let mut guards = guards_after(&reading(20.0, 60.0, 1013.0));
+        assert_eq!(guards.admit(0, &SETTINGS), Ok(()));
+        assert_eq!(guards.admit(HOUR_SECS, &SETTINGS), Ok(()));
+        assert_eq!(
+            guards.admit(2 * HOUR_SECS, &SETTINGS),
+            Err(SkipReason::DailyLimit)
+        );
+        assert_eq!(
+            guards.admit(DAY_SECS - 1, &SETTINGS),
+            Err(SkipReason::DailyLimit)
+        );
+        // A day after the first run, and again days later
+        assert_eq!(guards.admit(DAY_SECS, &SETTINGS), Ok(()));
+        assert_eq!(guards.admit(3 * DAY_SECS + 5, &SETTINGS), Ok(()));
+        assert_eq!(guards.admit(3 * DAY_SECS + 6, &SETTINGS), Ok(()));
+        assert_eq!(
+            guards.admit(3 * DAY_SECS + 7, &SETTINGS),
+            Err(SkipReason::DailyLimit)
+        );
+    }
+
+    #[test]
+    fn heat_wave_allows_extra_runs_for_a_day() {
+        let mut guards = guards_after(&reading(31.0, 40.0, 1013.0));
+        assert!(guards.heat_wave(0));
+        // Cooler readings do not end it early
+        guards.observe(HOUR_SECS, &reading(22.0, 50.0, 1013.0), &SETTINGS);
+        for hour in 1..4 {
+            assert_eq!(guards.admit(hour * HOUR_SECS, &SETTINGS), Ok(()));
+        }
+        assert_eq!(
+            guards.admit(4 * HOUR_SECS, &SETTINGS),
+            Err(SkipReason::DailyLimit)
+        );
+
+        assert!(guards.heat_wave(DAY_SECS - 1));
+        assert!(!guards.heat_wave(DAY_SECS));
+        // The next day has the normal limit again
+        let next = HOUR_SECS + DAY_SECS;
+        assert_eq!(guards.admit(next, &SETTINGS), Ok(()));
+        assert_eq!(guards.admit(next + 1, &SETTINGS), Ok(()));
+        assert_eq!(
+            guards.admit(next + 2, &SETTINGS),
+            Err(SkipReason::DailyLimit)
+        );
+    }
+
+    #[test]
+    fn falling_pressure_delays_runs() {
+        let mut guards = Guards::new();
+        for (hour, pressure) in [1013.0, 1012.0, 1011.0].into_iter().enumerate() {
+            guards.observe(
+                hour as u32 * HOUR_SECS,
+                &reading(20.0, 60.0, pressure),
+                &SETTINGS,
+            );
+        }
+        // Not three hours of trend yet
+        assert_eq!(guards.admit(2 * HOUR_SECS, &SETTINGS), Ok(()));
+
+        guards.observe(3 * HOUR_SECS, &reading(20.0, 60.0, 1010.0), &SETTINGS);
+        assert_eq!(
+            guards.admit(3 * HOUR_SECS, &SETTINGS),
+            Err(SkipReason::RainLikely)
+        );
+        assert_eq!(
+            guards.admit(9 * HOUR_SECS - 1, &SETTINGS),
+            Err(SkipReason::RainLikely)
+        );
+        assert_eq!(guards.admit(9 * HOUR_SECS, &SETTINGS), Ok(()));
+    }
+
+    #[test]
+    fn slowly_falling_pressure_is_no_rain() {
+        let mut guards = Guards::new();
+        for hour in 0..12 {
+            let pressure = 1013.0 - 0.5 * hour as f32;
+            guards.observe(hour * HOUR_SECS, &reading(20.0, 60.0, pressure), &SETTINGS);
+            assert_eq!(guards.rain_until, None, "at {hour} h");
+        }
+    }
+
+    #[test]
+    fn readings_within_the_hour_keep_the_trend() {
+        let mut guards = Guards::new();
+        for minute in 0..60 {
+            guards.observe(minute * 60, &reading(20.0, 60.0, 1013.0), &SETTINGS);
+        }
+        // One hourly slot so far, however many readings
+        assert_eq!(guards.pressure.iter().flatten().count(), 1);
+
+        // A gap of more than the trend drops the old readings
+        guards.observe(10 * HOUR_SECS, &reading(20.0, 60.0, 1000.0), &SETTINGS);
+        assert_eq!(guards.pressure.iter().flatten().count(), 1);
+        assert_eq!(guards.rain_until, None);
+    }
+}
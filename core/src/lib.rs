@@ -0,0 +1,35 @@
+//! Hardware-agnostic watering logic shared by the firmware and the host
+//! simulator. Peripherals are reached only through the traits in [`traits`].
+
+#![cfg_attr(not(test), no_std)]
+
+pub mod commands;
+pub mod diagnostics;
+pub mod dosing;
+pub mod drivers;
+pub mod et;
+pub mod flow;
+pub mod forecast;
+pub mod guards;
+pub mod light;
+pub mod net;
+pub mod onewire;
+pub mod ota;
+pub mod psychro;
+pub mod pump;
+pub mod quality;
+pub mod record;
+pub mod response;
+pub mod sensors;
+pub mod soil;
+pub mod sonar;
+pub mod station;
+pub mod tank;
+pub mod target;
+pub mod telemetry;
+#[cfg(test)]
+mod testing;
+pub mod traits;
+pub mod types;
+pub mod vacation;
+pub mod watering;
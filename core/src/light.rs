@@ -0,0 +1,294 @@
+//! Daily light integral (DLI) from an illuminance sensor, and dawn from
+//! the light coming up.
+//!
+//! Lux is turned into photon flux with the factor for sunlight, about
+//! 54 lx per µmol m⁻² s⁻¹, and summed over the day into mol m⁻² day⁻¹.
+//! Reference values for synthetic days:
+//! - a steady 1000 lx for 24 h is 1.60 mol
+//! - a clear day, half a sine from 6 to 18 h peaking at 100 klx, is 50.9 mol
+//! - the same day under cloud that lets 20 % through is 10.2 mol
+
+use crate::telemetry::Telemetry;
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+
+/// Photosynthetic photon flux in µmol m⁻² s⁻¹ per lux of sunlight.
+pub const PPFD_PER_LUX: f32 = 0.0185;
+
+// Longer gaps between readings are not bridged; the sensor was offline
+const MAX_GAP_SECS: u32 = 30 * 60;
+// Share of a day the readings must cover for its DLI to count
+const MIN_COVERAGE: f32 = 0.9;
+// A dull day never cuts a run by more than this
+const MIN_CLOUD_FACTOR: f32 = 0.5;
+
+/// Telemetry measurements that are ambient light, in order of preference.
+pub const LIGHT_SOURCES: [&str; 1] = ["bh1750_lux"];
+
+/// The illuminance from the first light sensor found at boot.
+pub fn lux(telemetry: &Telemetry) -> Option<f32> {
+    LIGHT_SOURCES.iter().find_map(|name| telemetry.get(name))
+}
+
+/// How much of a run to keep after a day with `dli`, against the DLI of a
+/// clear day where the sensor is.
+pub fn cloud_factor(dli: f32, sunny_dli: f32) -> f32 {
+    (dli / sunny_dli).clamp(MIN_CLOUD_FACTOR, 1.0)
+}
+
+/// Sums the light into a DLI per day.
+///
+/// Days are counted from the first reading, like the ET tracker's, so a
+/// complete day is any 24 hours of readings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LightTracker {
+    pub(crate) window_start: Option<u32>,
+    // Time and lux of the previous reading
+    pub(crate) last: Option<(u32, f32)>,
+    // mol/m² so far in the day in progress, and the time the readings cover
+    pub(crate) today_mol: f32,
+    pub(crate) covered_secs: u32,
+    // DLI of the last complete day
+    pub(crate) last_day: Option<f32>,
+}
+
+impl LightTracker {
+    pub const fn new() -> Self {
+        Self {
+            window_start: None,
+            last: None,
+            today_mol: 0.0,
+            covered_secs: 0,
+            last_day: None,
+        }
+    }
+
+    /// Adds the light since the previous reading, by the trapezoid rule.
+    pub fn observe(&mut self, t_secs: u32, lux: Option<f32>) {
+        let start = *self.window_start.get_or_insert(t_secs);
+        let elapsed = t_secs.saturating_sub(start);
+
+        if elapsed >= DAY_SECS {
+            let covered = self.covered_secs as f32 >= DAY_SECS as f32 * MIN_COVERAGE;
+            self.last_day = covered.then_some(self.today_mol);
+            self.window_start = Some(start + elapsed / DAY_SECS * DAY_SECS);
+            self.today_mol = 0.0;
+            self.covered_secs = 0;
+        }
+
+        let Some(lux) = lux.map(|lux| lux.max(0.0)) else {
+            return;
+        };
+        if let Some((last_t, last_lux)) = self.last {
+            let dt = t_secs.saturating_sub(last_t);
+            if dt <= MAX_GAP_SECS {
+                let mean_ppfd = (last_lux + lux) / 2.0 * PPFD_PER_LUX;
+                self.today_mol += mean_ppfd * dt as f32 / 1e6;
+                self.covered_secs += dt;
+            }
+        }
+        self.last = Some((t_secs, lux));
+    }
+
+    /// mol/m² so far in the day in progress; None before any light reading.
+    pub fn today(&self) -> Option<f32> {
+        self.last.map(|_| self.today_mol)
+    }
+
+    /// DLI of the last complete day; None until one was seen in full.
+    pub fn dli(&self) -> Option<f32> {
+        self.last_day
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct DawnSettings {
+    // Light at which the night ends
+    pub dawn_lux: f32,
+    // Dark at least this long before, so a passing cloud is no dawn
+    pub min_night_secs: u32,
+    // Run for dry soil at dawn; 0 for none
+    pub run_secs: u16,
+}
+
+/// Spots dawn: the light coming up after a night.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Dawn {
+    dark_since: Option<u32>,
+}
+
+impl Dawn {
+    pub const fn new() -> Self {
+        Self { dark_since: None }
+    }
+
+    /// True for the first reading at `dawn_lux` or above after a night;
+    /// readings without light leave the night running.
+    pub fn observe(&mut self, t_secs: u32, lux: Option<f32>, settings: &DawnSettings) -> bool {
+        let Some(lux) = lux else {
+            return false;
+        };
+        if lux < settings.dawn_lux {
+            self.dark_since.get_or_insert(t_secs);
+            return false;
+        }
+        self.dark_since
+            .take()
+            .is_some_and(|since| t_secs.saturating_sub(since) >= settings.min_night_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f32::consts::PI;
+
+    use super::*;
+
+    const STEP_SECS: u32 = 300;
+    const DAWN: DawnSettings = DawnSettings {
+        dawn_lux: 50.0,
+        min_night_secs: 4 * 60 * 60,
+        run_secs: 20,
+    };
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not {expected}"
+        );
+    }
+
+    // Half a sine from 6 to 18 h peaking at `peak`, dark otherwise
+    fn clear_day(peak: f32) -> impl Fn(u32) -> f32 {
+        move |t_secs| {
+            let hours = (t_secs % DAY_SECS) as f32 / 3600.0;
+            if (6.0..18.0).contains(&hours) {
+                peak * (PI * (hours - 6.0) / 12.0).sin()
+            } else {
+                0.0
+            }
+        }
+    }
+
+    // A reading every 5 min over `days`, plus the one that closes the last
+    fn track(days: u32, lux: impl Fn(u32) -> Option<f32>) -> LightTracker {
+        let mut tracker = LightTracker::new();
+        for t_secs in (0..=days * DAY_SECS).step_by(STEP_SECS as usize) {
+            tracker.observe(t_secs, lux(t_secs));
+        }
+        tracker
+    }
+
+    #[test]
+    fn reference_days() {
+        // The last 5 min of each day are counted in the next
+        let steady = track(1, |_| Some(1000.0)).dli().unwrap();
+        assert_near(
+            steady,
+            1.60 * (DAY_SECS - STEP_SECS) as f32 / DAY_SECS as f32,
+            0.005,
+        );
+
+        let clear = clear_day(100_000.0);
+        assert_near(track(1, |t| Some(clear(t))).dli().unwrap(), 50.9, 0.1);
+        let cloudy = clear_day(20_000.0);
+        assert_near(track(1, |t| Some(cloudy(t))).dli().unwrap(), 10.2, 0.05);
+    }
+
+    #[test]
+    fn each_day_is_counted_on_its_own() {
+        let clear = clear_day(100_000.0);
+        let cloudy = clear_day(20_000.0);
+        let tracker = track(2, |t| Some(if t < DAY_SECS { clear(t) } else { cloudy(t) }));
+        assert_near(tracker.dli().unwrap(), 10.2, 0.05);
+    }
+
+    #[test]
+    fn today_runs_up_through_the_day() {
+        let clear = clear_day(100_000.0);
+        let mut tracker = LightTracker::new();
+        assert_eq!(tracker.today(), None);
+        for t_secs in (0..=12 * 3600).step_by(STEP_SECS as usize) {
+            tracker.observe(t_secs, Some(clear(t_secs)));
+        }
+        // Half the clear day by noon
+        assert_near(tracker.today().unwrap(), 50.9 / 2.0, 0.1);
+        assert_eq!(tracker.dli(), None);
+    }
+
+    #[test]
+    fn a_day_with_gaps_does_not_count() {
+        // Offline from 6 to 9 h: the gap is not bridged, and the day is
+        // short of 90 %
+        let tracker = track(1, |t| {
+            (!(6 * 3600..9 * 3600).contains(&t)).then_some(1000.0)
+        });
+        assert_eq!(tracker.dli(), None);
+
+        // Short gaps are bridged; the readings reach 23:50
+        let tracker = track(1, |t| (t % 1200 != 0).then_some(1000.0));
+        assert_near(
+            tracker.dli().unwrap(),
+            1000.0 * PPFD_PER_LUX * 85_800.0 / 1e6,
+            0.001,
+        );
+    }
+
+    #[test]
+    fn negative_readings_are_dark() {
+        let tracker = track(1, |_| Some(-5.0));
+        assert_eq!(tracker.dli(), Some(0.0));
+    }
+
+    #[test]
+    fn cloud_factor_is_clamped() {
+        assert_eq!(cloud_factor(40.0, 40.0), 1.0);
+        assert_eq!(cloud_factor(60.0, 40.0), 1.0);
+        assert_eq!(cloud_factor(30.0, 40.0), 0.75);
+        assert_eq!(cloud_factor(5.0, 40.0), 0.5);
+    }
+
+    #[test]
+    fn lux_comes_from_a_light_source() {
+        let mut telemetry = Telemetry::new();
+        assert_eq!(lux(&telemetry), None);
+        telemetry.push("sht31_humidity", 40.0);
+        telemetry.push(LIGHT_SOURCES[0], 1234.0);
+        assert_eq!(lux(&telemetry), Some(1234.0));
+    }
+
+    #[test]
+    fn dawn_after_a_night() {
+        let clear = clear_day(100_000.0);
+        let mut dawn = Dawn::new();
+        let dawns: std::vec::Vec<u32> = (0..3 * DAY_SECS)
+            .step_by(STEP_SECS as usize)
+            .filter(|&t| dawn.observe(t, Some(clear(t)), &DAWN))
+            .collect();
+        // 50 lx comes a few seconds after 6 h, so the 6:05 reading
+        assert_eq!(
+            dawns,
+            [
+                6 * 3600 + 300,
+                DAY_SECS + 6 * 3600 + 300,
+                2 * DAY_SECS + 6 * 3600 + 300
+            ]
+        );
+    }
+
+    #[test]
+    fn a_passing_cloud_is_no_dawn() {
+        let mut dawn = Dawn::new();
+        // Light from the start is no dawn: no night was seen
+        assert!(!dawn.observe(0, Some(500.0), &DAWN));
+        assert!(!dawn.observe(3600, Some(10.0), &DAWN));
+        assert!(!dawn.observe(7200, Some(500.0), &DAWN));
+
+        // Readings without light leave the night running
+        assert!(!dawn.observe(10_000, Some(10.0), &DAWN));
+        assert!(!dawn.observe(20_000, None, &DAWN));
+        assert!(dawn.observe(10_000 + DAWN.min_night_secs, Some(60.0), &DAWN));
+        // And only once
+        assert!(!dawn.observe(30_000, Some(600.0), &DAWN));
+    }
+}
@@ -0,0 +1,438 @@
+use core::fmt::Write;
+
+use heapless::{String, Vec};
+use serde::Serialize;
+
+use crate::commands::{self, TaskActions};
+use crate::traits::Transport;
+use crate::types::HttpRequest;
+
+// Room for a reading with every field at its widest, see the
+// `a_full_reading_fits` test
+pub const BODY_LEN: usize = 2048;
+// Every TasksResponse field at its widest: a quoted name of up to 20
+// bytes, a colon, a value of up to 14 (a negative f32 with an exponent)
+// and a comma, with slack for whitespace the server may add
+const TASKS_FIELDS: usize = 16;
+const TASKS_FIELD_LEN: usize = 40;
+const TASKS_BODY_LEN: usize = TASKS_FIELDS * TASKS_FIELD_LEN + 2;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Get,
+    Post,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    Json,
+    OctetStream,
+}
+
+/// A request ready to go out; `path` is relative to the server URL and an
+/// empty `body` means none is sent.
+pub struct Request {
+    pub method: Method,
+    pub path: String<64>,
+    pub content_type: ContentType,
+    pub body: Vec<u8, BODY_LEN>,
+}
+
+pub struct Response {
+    pub status: u16,
+    // Bytes of body read into the caller's buffer
+    pub len: usize,
+}
+
+pub struct Endpoints<'a> {
+    pub sensor: &'a str,
+    pub alert: &'a str,
+    pub events: &'a str,
+    pub tasks: &'a str,
+}
+
+#[derive(Debug)]
+pub enum ExchangeError<E> {
+    PathTooLong,
+    Serialize,
+    // Non-2xx answer where the caller needs success to carry on
+    Status(u16),
+    Transport(E),
+}
+
+pub struct Exchange {
+    pub status: u16,
+    // Only for tasks polls answered with a parseable body
+    pub actions: Option<TaskActions>,
+}
+
+impl Exchange {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Turns a queued request into method, path and JSON body.
+pub fn build<E>(
+    request: &HttpRequest,
+    endpoints: &Endpoints<'_>,
+) -> Result<Request, ExchangeError<E>> {
+    let (method, path) = match request {
+        HttpRequest::PostSensorData(_) => (Method::Post, endpoints.sensor),
+        HttpRequest::SendAlert { .. } => (Method::Post, endpoints.alert),
+        HttpRequest::PostEvent(_) => (Method::Post, endpoints.events),
+        HttpRequest::PollTasks => (Method::Get, endpoints.tasks),
+    };
+
+    let mut built = Request {
+        method,
+        path: String::new(),
+        content_type: ContentType::Json,
+        body: Vec::new(),
+    };
+    built
+        .path
+        .push_str(path)
+        .map_err(|_| ExchangeError::PathTooLong)?;
+
+    match request {
+        HttpRequest::PostSensorData(data) => serialize(data, &mut built.body)?,
+        HttpRequest::SendAlert { message } => serialize(message, &mut built.body)?,
+        HttpRequest::PostEvent(event) => serialize(event, &mut built.body)?,
+        HttpRequest::PollTasks => {}
+    }
+
+    Ok(built)
+}
+
+/// Sends one queued request and interprets the reply.
+pub async fn exchange<T: Transport>(
+    transport: &mut T,
+    request: &HttpRequest,
+    endpoints: &Endpoints<'_>,
+) -> Result<Exchange, ExchangeError<T::Error>> {
+    let built = build(request, endpoints)?;
+
+    let is_poll = matches!(request, HttpRequest::PollTasks);
+    let mut tasks_body = [0u8; TASKS_BODY_LEN];
+    let body: &mut [u8] = if is_poll { &mut tasks_body } else { &mut [] };
+
+    let response = transport
+        .send(&built, body)
+        .await
+        .map_err(ExchangeError::Transport)?;
+
+    let actions = if is_poll && response.status == 200 {
+        commands::parse_tasks(&tasks_body[..response.len])
+    } else {
+        None
+    };
+
+    Ok(Exchange {
+        status: response.status,
+        actions,
+    })
+}
+
+/// POSTs `data` as raw chunks to `path`, each tagged with `?offset=`.
+pub async fn upload<T: Transport>(
+    transport: &mut T,
+    path: &str,
+    data: &[u8],
+) -> Result<(), ExchangeError<T::Error>> {
+    for (i, chunk) in data.chunks(BODY_LEN).enumerate() {
+        let mut request = Request {
+            method: Method::Post,
+            path: String::new(),
+            content_type: ContentType::OctetStream,
+            body: Vec::new(),
+        };
+        write!(request.path, "{}?offset={}", path, i * BODY_LEN)
+            .map_err(|_| ExchangeError::PathTooLong)?;
+        // Chunks are at most BODY_LEN long
+        request.body.extend_from_slice(chunk).ok();
+
+        let response = transport
+            .send(&request, &mut [])
+            .await
+            .map_err(ExchangeError::Transport)?;
+        if !(200..300).contains(&response.status) {
+            return Err(ExchangeError::Status(response.status));
+        }
+    }
+    Ok(())
+}
+
+fn serialize<T: Serialize, E>(
+    value: &T,
+    body: &mut Vec<u8, BODY_LEN>,
+) -> Result<(), ExchangeError<E>> {
+    body.resize_default(BODY_LEN)
+        .map_err(|_| ExchangeError::Serialize)?;
+    let len = serde_json_core::to_slice(value, body).map_err(|_| ExchangeError::Serialize)?;
+    body.truncate(len);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec as StdVec;
+
+    use super::*;
+    use crate::onewire::{MAX_PROBES, Rom};
+    use crate::quality::{Flag, Quality};
+    use crate::response::CURVE_POINTS;
+    use crate::testing::block_on;
+    use crate::types::{SensorData, TasksResponse};
+
+    const ENDPOINTS: Endpoints<'static> = Endpoints {
+        sensor: "/api/sensor",
+        alert: "/api/alert",
+        events: "/api/events",
+        tasks: "/api/tasks",
+    };
+
+    /// Answers every request with `status` and `reply`, and fails on a
+    /// body too long for the caller's buffer like the device does.
+    struct Fake {
+        status: u16,
+        reply: &'static [u8],
+        sent: StdVec<(Method, std::string::String, usize)>,
+    }
+
+    impl Fake {
+        fn new(status: u16, reply: &'static [u8]) -> Self {
+            Self {
+                status,
+                reply,
+                sent: StdVec::new(),
+            }
+        }
+    }
+
+    impl Transport for Fake {
+        type Error = &'static str;
+
+        async fn send(
+            &mut self,
+            request: &Request,
+            body: &mut [u8],
+        ) -> Result<Response, Self::Error> {
+            self.sent.push((
+                request.method,
+                request.path.as_str().into(),
+                request.body.len(),
+            ));
+            let mut len = 0;
+            if (200..300).contains(&self.status) && !body.is_empty() {
+                len = self.reply.len();
+                body.get_mut(..len)
+                    .ok_or("body too long")?
+                    .copy_from_slice(self.reply);
+            }
+            Ok(Response {
+                status: self.status,
+                len,
+            })
+        }
+    }
+
+    #[test]
+    fn builds_each_request() {
+        let data = SensorData {
+            temperature: Some(21.5),
+            ..SensorData::default()
+        };
+        let built = build::<()>(&HttpRequest::PostSensorData(data), &ENDPOINTS).unwrap();
+        assert!(built.method == Method::Post && built.content_type == ContentType::Json);
+        assert_eq!(built.path.as_str(), "/api/sensor");
+        let body = core::str::from_utf8(&built.body).unwrap();
+        assert!(body.starts_with(r#"{"temperature":21.5,"#), "{body}");
+
+        let built = build::<()>(&HttpRequest::PollTasks, &ENDPOINTS).unwrap();
+        assert!(built.method == Method::Get);
+        assert_eq!(built.path.as_str(), "/api/tasks");
+        assert!(built.body.is_empty());
+    }
+
+    #[test]
+    fn long_path_is_an_error() {
+        let long = "/a".repeat(40);
+        let endpoints = Endpoints {
+            tasks: &long,
+            ..ENDPOINTS
+        };
+        assert!(matches!(
+            build::<()>(&HttpRequest::PollTasks, &endpoints),
+            Err(ExchangeError::PathTooLong)
+        ));
+    }
+
+    #[test]
+    fn poll_reads_the_actions() {
+        let mut fake = Fake::new(200, br#"{"pump_duration":12,"pump_pulses":3}"#);
+        let exchange = block_on(exchange(&mut fake, &HttpRequest::PollTasks, &ENDPOINTS)).unwrap();
+        assert!(exchange.is_success());
+        let pump = exchange.actions.unwrap().pump.unwrap();
+        assert_eq!((pump.duration_secs, pump.pulses), (12, 3));
+    }
+
+    #[test]
+    fn a_full_reading_fits() {
+        // The longest an f32 prints
+        const WIDE: f32 = -f32::MIN_POSITIVE;
+        let mut data = SensorData {
+            water_today_l: WIDE,
+            water_total_l: WIDE,
+            moisture_response: Some([WIDE; CURVE_POINTS]),
+            quality: Quality {
+                temperature: Flag::OutOfRange,
+                humidity: Flag::OutOfRange,
+                pressure: Flag::OutOfRange,
+                soil_moisture: Flag::OutOfRange,
+                water_level: Flag::OutOfRange,
+            },
+            ..SensorData::default()
+        };
+        for field in [
+            &mut data.temperature,
+            &mut data.humidity,
+            &mut data.pressure,
+            &mut data.soil_moisture,
+            &mut data.soil_moisture_raw,
+            &mut data.water_level,
+            &mut data.lux,
+            &mut data.dew_point,
+            &mut data.absolute_humidity,
+            &mut data.heat_index,
+            &mut data.sea_level_pressure,
+            &mut data.vpd,
+            &mut data.et0,
+            &mut data.dli_today,
+            &mut data.dli,
+            &mut data.hours_until_dry,
+            &mut data.tank_l,
+            &mut data.tank_daily_use_l,
+            &mut data.tank_days_remaining,
+        ] {
+            *field = Some(WIDE);
+        }
+        while data.telemetry.push("scd40_co2_ppm", WIDE) {}
+        for i in 0..MAX_PROBES as u8 {
+            data.soil_temperatures
+                .push(Rom([0x28, i, 0, 0, 0, 0, 0, 0]), WIDE);
+        }
+        assert_eq!(data.soil_temperatures.len(), MAX_PROBES);
+
+        let built = build::<()>(&HttpRequest::PostSensorData(data), &ENDPOINTS).unwrap();
+        assert!(built.body.len() <= BODY_LEN, "{} bytes", built.body.len());
+    }
+
+    #[test]
+    fn a_full_tasks_reply_fits() {
+        let widest = TasksResponse {
+            pump_duration: u16::MAX,
+            pump_volume_ml: u16::MAX,
+            pump_pulses: u8::MAX,
+            pump_soak_secs: u16::MAX,
+            pump_zone: u8::MAX,
+            pump_cancel: false,
+            calibrate_pump: false,
+            calibration_ml: u16::MAX,
+            target_moisture_low: f32::MIN,
+            target_moisture_high: f32::MIN,
+            target_off: false,
+            vacation_until_day: u16::MAX,
+            vacation_off: false,
+            firmware_update: false,
+            upload_record: false,
+            day_of_year: u16::MAX,
+        };
+        let mut reply = [0; TASKS_BODY_LEN];
+        let len = serde_json_core::to_slice(&widest, &mut reply).unwrap();
+        // Every field is counted
+        assert_eq!(
+            reply[..len].iter().filter(|&&b| b == b':').count(),
+            TASKS_FIELDS
+        );
+        assert!(len <= TASKS_BODY_LEN, "{len} bytes");
+
+        let reply = StdVec::leak(reply[..len].to_vec());
+        let mut fake = Fake::new(200, reply);
+        let exchange = block_on(exchange(&mut fake, &HttpRequest::PollTasks, &ENDPOINTS)).unwrap();
+        let pump = exchange.actions.unwrap().pump.unwrap();
+        assert_eq!((pump.duration_secs, pump.pulses), (u16::MAX, u8::MAX));
+    }
+
+    #[test]
+    fn tasks_round_trip() {
+        let tasks = TasksResponse {
+            pump_duration: 30,
+            pump_volume_ml: 250,
+            pump_pulses: 3,
+            pump_soak_secs: 600,
+            pump_zone: 1,
+            pump_cancel: true,
+            calibrate_pump: true,
+            calibration_ml: 480,
+            target_moisture_low: 40.5,
+            target_moisture_high: 50.25,
+            target_off: true,
+            vacation_until_day: 200,
+            vacation_off: true,
+            firmware_update: true,
+            upload_record: true,
+            day_of_year: 152,
+        };
+        let mut body = [0; TASKS_BODY_LEN];
+        let len = serde_json_core::to_slice(&tasks, &mut body).unwrap();
+        let (parsed, _) = serde_json_core::from_slice::<TasksResponse>(&body[..len]).unwrap();
+        assert_eq!(parsed, tasks);
+    }
+
+    #[test]
+    fn failed_poll_has_no_actions() {
+        let mut fake = Fake::new(503, br#"{"pump_duration":12}"#);
+        let exchange = block_on(exchange(&mut fake, &HttpRequest::PollTasks, &ENDPOINTS)).unwrap();
+        assert!(!exchange.is_success());
+        assert!(exchange.actions.is_none());
+    }
+
+    #[test]
+    fn posts_ignore_the_reply() {
+        let mut fake = Fake::new(200, b"ok");
+        let request = HttpRequest::PostSensorData(SensorData::default());
+        let exchange = block_on(exchange(&mut fake, &request, &ENDPOINTS)).unwrap();
+        assert!(exchange.is_success() && exchange.actions.is_none());
+        assert_eq!(fake.sent.len(), 1);
+    }
+
+    #[test]
+    fn upload_tags_each_chunk_with_its_offset() {
+        let data = [0x5A; 2 * BODY_LEN + 100];
+        let mut fake = Fake::new(204, b"");
+        block_on(upload(&mut fake, "/api/record", &data)).unwrap();
+        let sent: StdVec<_> = fake
+            .sent
+            .iter()
+            .map(|(_, path, len)| (path.clone(), *len))
+            .collect();
+        let chunk = |offset: usize, len| (std::format!("/api/record?offset={offset}"), len);
+        assert_eq!(
+            sent,
+            [
+                chunk(0, BODY_LEN),
+                chunk(BODY_LEN, BODY_LEN),
+                chunk(2 * BODY_LEN, 100),
+            ]
+        );
+    }
+
+    #[test]
+    fn upload_stops_at_the_first_failure() {
+        let mut fake = Fake::new(500, b"");
+        let result = block_on(upload(&mut fake, "/api/record", &[0; 2 * BODY_LEN]));
+        assert!(matches!(result, Err(ExchangeError::Status(500))));
+        assert_eq!(fake.sent.len(), 1);
+    }
+}
@@ -0,0 +1,633 @@
+//! 1-Wire: the ROM search that finds every device sharing the wire, its
+//! CRC, and the DS18B20 temperature probes on it. The time slots are the
+//! firmware's, see [`OneWireBus`].
+//!
+//! Reference values from the Maxim application notes and datasheet:
+//! - CRC of the ROM 02 1C B8 01 00 00 00 is 0xA2
+//! - DS18B20 temperature 0x0191 is 25.0625 °C, 0xFF5E is -10.125 °C, and
+//!   0x0550 (85 °C) is the power-on value, read before any conversion
+
+use core::fmt;
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+use crate::drivers::DecodeError;
+use crate::traits::OneWireBus;
+
+// ROM and function commands
+pub const SEARCH_ROM: u8 = 0xF0;
+pub const MATCH_ROM: u8 = 0x55;
+pub const SKIP_ROM: u8 = 0xCC;
+pub const CONVERT_T: u8 = 0x44;
+pub const READ_SCRATCHPAD: u8 = 0xBE;
+
+pub const DS18B20_FAMILY: u8 = 0x28;
+/// A conversion at the default 12-bit resolution takes up to this long.
+pub const DS18B20_CONVERSION_MS: u64 = 750;
+// What the temperature register holds until the first conversion
+const DS18B20_POWER_ON: i16 = 0x0550;
+
+pub const MAX_PROBES: usize = 4;
+
+/// The Dallas/Maxim CRC-8: polynomial x⁸ + x⁵ + x⁴ + 1, reflected, start 0.
+/// Over data followed by its CRC it comes out 0.
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x01 != 0 {
+                (crc >> 1) ^ 0x8C
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// A device's 64-bit ROM id in wire order: family code, serial number
+/// from the low byte up, CRC.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rom(pub [u8; 8]);
+
+impl Rom {
+    pub fn family(&self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        crc8(&self.0) == 0
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        self.0[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    fn set_bit(&mut self, index: usize, value: bool) {
+        let mask = 1 << (index % 8);
+        if value {
+            self.0[index / 8] |= mask;
+        } else {
+            self.0[index / 8] &= !mask;
+        }
+    }
+
+    // Lower case hex in wire order, e.g. `28ff4c5c61160403`
+    fn hex(&self) -> [u8; 16] {
+        const DIGITS: &[u8; 16] = b"0123456789abcdef";
+        let mut out = [0; 16];
+        for (pair, byte) in out.chunks_exact_mut(2).zip(self.0) {
+            pair[0] = DIGITS[(byte >> 4) as usize];
+            pair[1] = DIGITS[(byte & 0x0F) as usize];
+        }
+        out
+    }
+}
+
+impl fmt::Display for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.hex();
+        // Only ASCII digits go in
+        f.write_str(core::str::from_utf8(&hex).unwrap_or("?"))
+    }
+}
+
+impl fmt::Debug for Rom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl Serialize for Rom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let hex = self.hex();
+        serializer.serialize_str(core::str::from_utf8(&hex).unwrap_or("?"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Error<E> {
+    Bus(E),
+    // A device answered the reset but not the search
+    NoResponse,
+    Crc,
+}
+
+pub async fn write_byte<B: OneWireBus>(bus: &mut B, byte: u8) -> Result<(), B::Error> {
+    for i in 0..8 {
+        bus.touch_bit(byte & (1 << i) != 0).await?;
+    }
+    Ok(())
+}
+
+pub async fn read_byte<B: OneWireBus>(bus: &mut B) -> Result<u8, B::Error> {
+    let mut byte = 0;
+    for i in 0..8 {
+        if bus.touch_bit(true).await? {
+            byte |= 1 << i;
+        }
+    }
+    Ok(byte)
+}
+
+/// Where a ROM search left off; each [`Search::next`] finds one more
+/// device, in the order of their ROM ids read from the low bit up.
+///
+/// Every device answers each ROM bit with the bit and its complement at
+/// once, so the wire reads the AND of them. Both reading 0 means devices
+/// differ there: the search takes the 0 branch first and comes back for
+/// the 1 branch on a later call (Maxim application note 187).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Search {
+    rom: Rom,
+    // Bit number, from 1, of the last 0 branch taken; 0 for none
+    last_discrepancy: usize,
+    done: bool,
+}
+
+impl Search {
+    pub const fn new() -> Self {
+        Self {
+            rom: Rom([0; 8]),
+            last_discrepancy: 0,
+            done: false,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// The next device's ROM; None once every device has been found, or if
+    /// none answers. After an error the search starts over.
+    pub async fn next<B: OneWireBus>(
+        &mut self,
+        bus: &mut B,
+    ) -> Result<Option<Rom>, Error<B::Error>> {
+        if self.done {
+            return Ok(None);
+        }
+        let found = self.step(bus).await;
+        if !matches!(found, Ok(Some(_))) {
+            *self = Self::new();
+            self.done = found.is_ok();
+        }
+        found
+    }
+
+    async fn step<B: OneWireBus>(&mut self, bus: &mut B) -> Result<Option<Rom>, Error<B::Error>> {
+        if !bus.reset().await.map_err(Error::Bus)? {
+            return Ok(None);
+        }
+        write_byte(bus, SEARCH_ROM).await.map_err(Error::Bus)?;
+
+        let mut last_zero = 0;
+        for bit_number in 1..=64 {
+            let index = bit_number - 1;
+            let bit = bus.touch_bit(true).await.map_err(Error::Bus)?;
+            let complement = bus.touch_bit(true).await.map_err(Error::Bus)?;
+            let direction = match (bit, complement) {
+                (true, true) => return Err(Error::NoResponse),
+                (false, false) => {
+                    let direction = if bit_number < self.last_discrepancy {
+                        self.rom.bit(index)
+                    } else {
+                        bit_number == self.last_discrepancy
+                    };
+                    if !direction {
+                        last_zero = bit_number;
+                    }
+                    direction
+                }
+                // Every device left has the same bit here
+                (bit, _) => bit,
+            };
+            self.rom.set_bit(index, direction);
+            // Devices whose bit differs drop out until the next reset
+            bus.touch_bit(direction).await.map_err(Error::Bus)?;
+        }
+
+        if !self.rom.is_valid() {
+            return Err(Error::Crc);
+        }
+        self.last_discrepancy = last_zero;
+        self.done = last_zero == 0;
+        Ok(Some(self.rom))
+    }
+}
+
+/// Has every DS18B20 on the wire start a conversion at once; false if none
+/// answered the reset.
+pub async fn start_conversion<B: OneWireBus>(bus: &mut B) -> Result<bool, B::Error> {
+    if !bus.reset().await? {
+        return Ok(false);
+    }
+    write_byte(bus, SKIP_ROM).await?;
+    write_byte(bus, CONVERT_T).await?;
+    Ok(true)
+}
+
+/// Reads the scratchpad of the device `rom`; None if nothing answered the
+/// reset.
+pub async fn read_scratchpad<B: OneWireBus>(
+    bus: &mut B,
+    rom: &Rom,
+) -> Result<Option<[u8; 9]>, B::Error> {
+    if !bus.reset().await? {
+        return Ok(None);
+    }
+    write_byte(bus, MATCH_ROM).await?;
+    for byte in rom.0 {
+        write_byte(bus, byte).await?;
+    }
+    write_byte(bus, READ_SCRATCHPAD).await?;
+    let mut scratchpad = [0; 9];
+    for byte in scratchpad.iter_mut() {
+        *byte = read_byte(bus).await?;
+    }
+    Ok(Some(scratchpad))
+}
+
+/// The temperature in a DS18B20 scratchpad: 1/16 °C steps, signed, low
+/// byte first, with the CRC in the last byte.
+pub fn decode_ds18b20(scratchpad: &[u8; 9]) -> Result<f32, DecodeError> {
+    // A wire held low reads all zeros, and their CRC is zero too
+    if crc8(scratchpad) != 0 || scratchpad.iter().all(|&b| b == 0) {
+        return Err(DecodeError::Crc);
+    }
+    let raw = i16::from_le_bytes([scratchpad[0], scratchpad[1]]);
+    if raw == DS18B20_POWER_ON {
+        return Err(DecodeError::NotReady);
+    }
+    Ok(raw as f32 / 16.0)
+}
+
+/// The latest temperature of each probe, uploaded as a JSON object keyed
+/// by ROM id.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SoilTemperatures {
+    items: [(Rom, f32); MAX_PROBES],
+    len: usize,
+}
+
+impl SoilTemperatures {
+    pub const fn new() -> Self {
+        Self {
+            items: [(Rom([0; 8]), 0.0); MAX_PROBES],
+            len: 0,
+        }
+    }
+
+    /// Adds a probe's reading; false if there is no room left.
+    pub fn push(&mut self, rom: Rom, celsius: f32) -> bool {
+        let Some(item) = self.items.get_mut(self.len) else {
+            return false;
+        };
+        *item = (rom, celsius);
+        self.len += 1;
+        true
+    }
+
+    pub fn get(&self, rom: &Rom) -> Option<f32> {
+        self.iter().find(|(r, _)| r == rom).map(|&(_, c)| c)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(Rom, f32)> {
+        self.items[..self.len].iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Serialize for SoilTemperatures {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len))?;
+        for (rom, celsius) in self.iter() {
+            map.serialize_entry(rom, celsius)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testing::block_on;
+
+    struct Device {
+        rom: Rom,
+        scratchpad: [u8; 9],
+    }
+
+    #[derive(Clone, Copy)]
+    enum Mode {
+        // Taking a command byte, ROM or function
+        Command { rom: bool },
+        // Bit of the ROM and which of its three slots: bit, complement,
+        // direction
+        Search { bit: usize, slot: u8 },
+        Match { bit: usize },
+        Scratchpad { bit: usize },
+        Idle,
+    }
+
+    /// Devices on a wire, answering each time slot as the real ones do:
+    /// the wire reads the AND of what every selected device sends.
+    struct FakeBus {
+        devices: Vec<Device>,
+        selected: Vec<bool>,
+        mode: Mode,
+        byte: u8,
+        bits: u8,
+        // Present on reset, but silent in the search
+        mute: bool,
+        conversions: usize,
+    }
+
+    impl FakeBus {
+        fn new(devices: Vec<Device>) -> Self {
+            let selected = vec![false; devices.len()];
+            Self {
+                devices,
+                selected,
+                mode: Mode::Idle,
+                byte: 0,
+                bits: 0,
+                mute: false,
+                conversions: 0,
+            }
+        }
+
+        // The wire with every selected device sending `bit` of its own
+        fn wire(&self, bit: impl Fn(&Device) -> bool) -> bool {
+            self.devices
+                .iter()
+                .zip(&self.selected)
+                .all(|(device, &selected)| !selected || bit(device))
+        }
+
+        fn command(&mut self, rom: bool, command: u8) {
+            self.mode = match (rom, command) {
+                (true, SEARCH_ROM) => Mode::Search { bit: 0, slot: 0 },
+                (true, MATCH_ROM) => Mode::Match { bit: 0 },
+                (true, SKIP_ROM) => Mode::Command { rom: false },
+                (false, READ_SCRATCHPAD) => Mode::Scratchpad { bit: 0 },
+                (false, CONVERT_T) => {
+                    self.conversions += 1;
+                    Mode::Idle
+                }
+                _ => Mode::Idle,
+            };
+        }
+    }
+
+    impl OneWireBus for FakeBus {
+        type Error = ();
+
+        async fn reset(&mut self) -> Result<bool, ()> {
+            self.selected.fill(true);
+            self.mode = Mode::Command { rom: true };
+            self.byte = 0;
+            self.bits = 0;
+            Ok(!self.devices.is_empty())
+        }
+
+        async fn touch_bit(&mut self, bit: bool) -> Result<bool, ()> {
+            match self.mode {
+                Mode::Command { rom } => {
+                    self.byte |= (bit as u8) << self.bits;
+                    self.bits += 1;
+                    if self.bits == 8 {
+                        let command = self.byte;
+                        (self.byte, self.bits) = (0, 0);
+                        self.command(rom, command);
+                    }
+                    Ok(bit)
+                }
+                Mode::Search { bit: index, slot } => {
+                    let read = match slot {
+                        _ if self.mute => true,
+                        0 => self.wire(|device| device.rom.bit(index)),
+                        1 => self.wire(|device| !device.rom.bit(index)),
+                        _ => {
+                            for (device, selected) in self.devices.iter().zip(&mut self.selected) {
+                                *selected &= device.rom.bit(index) == bit;
+                            }
+                            bit
+                        }
+                    };
+                    self.mode = match (slot, index) {
+                        (2, 63) => Mode::Idle,
+                        (2, _) => Mode::Search {
+                            bit: index + 1,
+                            slot: 0,
+                        },
+                        _ => Mode::Search {
+                            bit: index,
+                            slot: slot + 1,
+                        },
+                    };
+                    Ok(read && bit)
+                }
+                Mode::Match { bit: index } => {
+                    for (device, selected) in self.devices.iter().zip(&mut self.selected) {
+                        *selected &= device.rom.bit(index) == bit;
+                    }
+                    self.mode = match index {
+                        63 => Mode::Command { rom: false },
+                        _ => Mode::Match { bit: index + 1 },
+                    };
+                    Ok(bit)
+                }
+                Mode::Scratchpad { bit: index } => {
+                    let read =
+                        self.wire(|device| device.scratchpad[index / 8] & (1 << (index % 8)) != 0);
+                    self.mode = match index {
+                        71 => Mode::Idle,
+                        _ => Mode::Scratchpad { bit: index + 1 },
+                    };
+                    Ok(read && bit)
+                }
+                Mode::Idle => Ok(bit),
+            }
+        }
+    }
+
+    // A DS18B20 with `serial` and a valid CRC
+    fn rom(serial: [u8; 6]) -> Rom {
+        let mut rom = [DS18B20_FAMILY, 0, 0, 0, 0, 0, 0, 0];
+        rom[1..7].copy_from_slice(&serial);
+        rom[7] = crc8(&rom[..7]);
+        Rom(rom)
+    }
+
+    // A scratchpad holding `raw`, with a valid CRC
+    fn scratchpad(raw: i16) -> [u8; 9] {
+        let mut scratchpad = [0, 0, 0x4B, 0x46, 0x7F, 0xFF, 0x0C, 0x10, 0];
+        scratchpad[..2].copy_from_slice(&raw.to_le_bytes());
+        scratchpad[8] = crc8(&scratchpad[..8]);
+        scratchpad
+    }
+
+    fn device(serial: [u8; 6], raw: i16) -> Device {
+        Device {
+            rom: rom(serial),
+            scratchpad: scratchpad(raw),
+        }
+    }
+
+    fn search_all(bus: &mut FakeBus) -> Vec<Rom> {
+        let mut search = Search::new();
+        let mut found = Vec::new();
+        while let Some(rom) = block_on(search.next(bus)).unwrap() {
+            found.push(rom);
+            assert!(found.len() <= bus.devices.len(), "found too many");
+        }
+        assert!(search.is_done());
+        found
+    }
+
+    // The order the search finds ROMs in: read from the low bit up
+    fn search_order(rom: &Rom) -> Vec<bool> {
+        (0..64).map(|index| rom.bit(index)).collect()
+    }
+
+    #[test]
+    fn crc_reference_value() {
+        assert_eq!(crc8(&[0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00]), 0xA2);
+        let rom = Rom([0x02, 0x1C, 0xB8, 0x01, 0x00, 0x00, 0x00, 0xA2]);
+        assert!(rom.is_valid());
+        assert_eq!(rom.family(), 0x02);
+
+        let mut flipped = rom;
+        flipped.0[3] ^= 0x10;
+        assert!(!flipped.is_valid());
+        assert_eq!(crc8(&[]), 0);
+    }
+
+    #[test]
+    fn ds18b20_reference_values() {
+        assert_eq!(decode_ds18b20(&scratchpad(0x0191)), Ok(25.0625));
+        assert_eq!(decode_ds18b20(&scratchpad(0xFF5Eu16 as i16)), Ok(-10.125));
+        assert_eq!(
+            decode_ds18b20(&scratchpad(DS18B20_POWER_ON)),
+            Err(DecodeError::NotReady)
+        );
+
+        let mut corrupt = scratchpad(0x0191);
+        corrupt[0] ^= 0x01;
+        assert_eq!(decode_ds18b20(&corrupt), Err(DecodeError::Crc));
+        // A wire held low
+        assert_eq!(decode_ds18b20(&[0; 9]), Err(DecodeError::Crc));
+    }
+
+    #[test]
+    fn rom_ids_print_in_wire_order() {
+        let rom = Rom([0x28, 0xFF, 0x4C, 0x5C, 0x61, 0x16, 0x04, 0x03]);
+        assert_eq!(std::format!("{rom}"), "28ff4c5c61160403");
+
+        let mut temperatures = SoilTemperatures::new();
+        assert!(temperatures.is_empty());
+        assert!(temperatures.push(rom, 18.5));
+        assert_eq!(temperatures.get(&rom), Some(18.5));
+        assert_eq!(temperatures.get(&Rom::default()), None);
+        let mut json = [0; 64];
+        let len = serde_json_core::to_slice(&temperatures, &mut json).unwrap();
+        assert_eq!(&json[..len], br#"{"28ff4c5c61160403":18.5}"#);
+    }
+
+    #[test]
+    fn soil_temperatures_hold_max_probes() {
+        let mut temperatures = SoilTemperatures::new();
+        for i in 0..MAX_PROBES {
+            assert!(temperatures.push(rom([i as u8, 0, 0, 0, 0, 0]), 20.0));
+        }
+        assert!(!temperatures.push(rom([9, 0, 0, 0, 0, 0]), 20.0));
+        assert_eq!(temperatures.len(), MAX_PROBES);
+    }
+
+    #[test]
+    fn search_finds_every_device_once() {
+        let mut devices = Vec::new();
+        // Serials that differ early, late and in a single bit
+        for serial in [
+            [0x01, 0, 0, 0, 0, 0],
+            [0x00, 0, 0, 0, 0, 0x80],
+            [0x03, 0, 0, 0, 0, 0],
+            [0x5C, 0x61, 0x16, 0x04, 0x4C, 0xFF],
+        ] {
+            devices.push(device(serial, 0x0191));
+        }
+        let mut expected: Vec<Rom> = devices.iter().map(|device| device.rom).collect();
+        expected.sort_by_key(search_order);
+
+        let mut bus = FakeBus::new(devices);
+        assert_eq!(search_all(&mut bus), expected);
+    }
+
+    #[test]
+    fn search_with_one_device() {
+        let only = rom([0x5C, 0x61, 0x16, 0x04, 0x4C, 0xFF]);
+        let mut bus = FakeBus::new(vec![device([0x5C, 0x61, 0x16, 0x04, 0x4C, 0xFF], 0)]);
+        assert_eq!(search_all(&mut bus), [only]);
+    }
+
+    #[test]
+    fn search_of_an_empty_wire_is_done() {
+        let mut bus = FakeBus::new(Vec::new());
+        let mut search = Search::new();
+        assert_eq!(block_on(search.next(&mut bus)), Ok(None));
+        assert!(search.is_done());
+    }
+
+    #[test]
+    fn search_errors_start_over() {
+        let mut bus = FakeBus::new(vec![device([1, 0, 0, 0, 0, 0], 0)]);
+        bus.mute = true;
+        let mut search = Search::new();
+        assert_eq!(block_on(search.next(&mut bus)), Err(Error::NoResponse));
+        assert!(!search.is_done());
+
+        // A ROM that fails its CRC
+        let mut bus = FakeBus::new(vec![device([1, 0, 0, 0, 0, 0], 0)]);
+        bus.devices[0].rom.0[7] ^= 0xFF;
+        assert_eq!(block_on(search.next(&mut bus)), Err(Error::Crc));
+        assert!(!search.is_done());
+
+        bus.devices[0].rom = rom([1, 0, 0, 0, 0, 0]);
+        assert_eq!(search_all(&mut bus), [rom([1, 0, 0, 0, 0, 0])]);
+    }
+
+    #[test]
+    fn each_probe_is_read_by_its_rom() {
+        let mut bus = FakeBus::new(vec![
+            device([1, 0, 0, 0, 0, 0], 0x0191),
+            device([2, 0, 0, 0, 0, 0], 0xFF5Eu16 as i16),
+        ]);
+        assert_eq!(block_on(start_conversion(&mut bus)), Ok(true));
+        assert_eq!(bus.conversions, 1);
+
+        for (serial, celsius) in [(1, 25.0625), (2, -10.125)] {
+            let scratchpad = block_on(read_scratchpad(&mut bus, &rom([serial, 0, 0, 0, 0, 0])))
+                .unwrap()
+                .unwrap();
+            assert_eq!(decode_ds18b20(&scratchpad), Ok(celsius));
+        }
+
+        let mut empty = FakeBus::new(Vec::new());
+        assert_eq!(block_on(start_conversion(&mut empty)), Ok(false));
+        assert_eq!(
+            block_on(read_scratchpad(&mut empty, &rom([1, 0, 0, 0, 0, 0]))),
+            Ok(None)
+        );
+    }
+}
@@ -0,0 +1,350 @@
+//! Firmware download bookkeeping, independent of flash and network.
+//!
+//! The device side feeds every received chunk through [`Download`], which
+//! decides what to fetch next and checks the image hash and signature once
+//! complete. The updater checks the signature once more against the image
+//! as written to flash.
+
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use heapless::String;
+use serde::Deserialize;
+use sha2::{Digest, Sha256, Sha512};
+
+// One flash erase sector: the updater erases and writes whole sectors
+pub const CHUNK_SIZE: usize = 4096;
+// Size of the ACTIVE partition in memory.x
+pub const MAX_IMAGE_SIZE: u32 = 2016 * 1024;
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub version: String<16>,
+    pub size: u32,
+    pub sha256: String<64>,
+    pub signature: String<128>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OtaError {
+    UpToDate,
+    BadSize,
+    BadHex,
+    UnexpectedChunk,
+    HashMismatch,
+    BadSignature,
+    Incomplete,
+}
+
+impl OtaError {
+    pub fn message(self) -> &'static str {
+        match self {
+            OtaError::UpToDate => "already running this version",
+            OtaError::BadSize => "image size out of range",
+            OtaError::BadHex => "malformed hash or signature",
+            OtaError::UnexpectedChunk => "chunk does not match request",
+            OtaError::HashMismatch => "image hash mismatch",
+            OtaError::BadSignature => "image signature invalid",
+            OtaError::Incomplete => "image incomplete",
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct UpdatePlan {
+    pub size: u32,
+    pub sha256: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+impl Manifest {
+    /// Checks the manifest and decides whether it is worth downloading.
+    pub fn plan(&self, running_version: &str) -> Result<UpdatePlan, OtaError> {
+        if self.version.as_str() == running_version {
+            return Err(OtaError::UpToDate);
+        }
+        if self.size == 0 || self.size > MAX_IMAGE_SIZE {
+            return Err(OtaError::BadSize);
+        }
+
+        let mut plan = UpdatePlan {
+            size: self.size,
+            sha256: [0; 32],
+            signature: [0; 64],
+        };
+        decode_hex(&self.sha256, &mut plan.sha256)?;
+        decode_hex(&self.signature, &mut plan.signature)?;
+        Ok(plan)
+    }
+}
+
+pub struct Download {
+    plan: UpdatePlan,
+    written: u32,
+    hasher: Sha256,
+    // The signature is over the SHA-512 digest of the image
+    digest: Sha512,
+}
+
+impl Download {
+    pub fn new(plan: UpdatePlan) -> Self {
+        Self {
+            plan,
+            written: 0,
+            hasher: Sha256::new(),
+            digest: Sha512::new(),
+        }
+    }
+
+    pub fn written(&self) -> u32 {
+        self.written
+    }
+
+    /// Offset and length of the next chunk to fetch, `None` once complete.
+    pub fn next_chunk(&self) -> Option<(u32, usize)> {
+        let remaining = self.plan.size - self.written;
+        if remaining == 0 {
+            return None;
+        }
+        Some((self.written, (remaining as usize).min(CHUNK_SIZE)))
+    }
+
+    /// Accepts the chunk requested by [`Download::next_chunk`] and returns
+    /// the image offset it belongs at.
+    pub fn accept(&mut self, data: &[u8]) -> Result<u32, OtaError> {
+        match self.next_chunk() {
+            Some((offset, len)) if len == data.len() => {
+                self.hasher.update(data);
+                self.digest.update(data);
+                self.written += len as u32;
+                Ok(offset)
+            }
+            _ => Err(OtaError::UnexpectedChunk),
+        }
+    }
+
+    /// Checks the received image against the manifest hash and signature.
+    pub fn finish(self, public_key: &[u8; 32]) -> Result<UpdatePlan, OtaError> {
+        if self.written != self.plan.size {
+            return Err(OtaError::Incomplete);
+        }
+        if self.hasher.finalize().as_slice() != self.plan.sha256 {
+            return Err(OtaError::HashMismatch);
+        }
+        let key = VerifyingKey::from_bytes(public_key).map_err(|_| OtaError::BadSignature)?;
+        let signature = Signature::from_bytes(&self.plan.signature);
+        key.verify(&self.digest.finalize(), &signature)
+            .map_err(|_| OtaError::BadSignature)?;
+        Ok(self.plan)
+    }
+}
+
+fn decode_hex(hex: &str, out: &mut [u8]) -> Result<(), OtaError> {
+    let hex = hex.as_bytes();
+    if hex.len() != out.len() * 2 {
+        return Err(OtaError::BadHex);
+    }
+
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = (nibble(pair[0])? << 4) | nibble(pair[1])?;
+    }
+    Ok(())
+}
+
+fn nibble(c: u8) -> Result<u8, OtaError> {
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(OtaError::BadHex),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! The download run against a local file server, chunk by chunk with
+    //! `Range` requests like the device makes them.
+
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::string::String as StdString;
+    use std::thread;
+
+    use ed25519_dalek::{Signer, SigningKey};
+
+    use super::*;
+
+    const RUNNING: &str = "0.1.0";
+    const KEY: [u8; 32] = [7; 32];
+
+    /// Serves `manifest` and `image` until the test ends; ranges past the
+    /// end of the image come back short.
+    fn serve(manifest: StdString, image: Vec<u8>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut head = Vec::new();
+                let mut byte = [0];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    head.push(byte[0]);
+                }
+                let head = StdString::from_utf8(head).unwrap();
+                let path = head.split(' ').nth(1).unwrap_or("");
+                let range = head.lines().find_map(|line| {
+                    let (first, last) = line.strip_prefix("Range: bytes=")?.split_once('-')?;
+                    Some((first.parse::<usize>().ok()?, last.parse::<usize>().ok()?))
+                });
+
+                let (status, body) = match (path, range) {
+                    ("/manifest", _) => ("200 OK", manifest.as_bytes()),
+                    ("/image", Some((first, last))) => {
+                        let end = (last + 1).min(image.len());
+                        ("206 Partial Content", &image[first.min(end)..end])
+                    }
+                    _ => ("404 Not Found", &[][..]),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    body.len()
+                );
+                let _ = stream.write_all(body);
+            }
+        });
+        addr
+    }
+
+    fn get(addr: SocketAddr, path: &str, range: Option<(u32, usize)>) -> (u16, Vec<u8>) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = format!("GET {} HTTP/1.1\r\nConnection: close\r\n", path);
+        if let Some((offset, len)) = range {
+            request += &format!("Range: bytes={}-{}\r\n", offset, offset + len as u32 - 1);
+        }
+        request += "\r\n";
+        stream.write_all(request.as_bytes()).unwrap();
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply).unwrap();
+        let end = reply.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let status = StdString::from_utf8_lossy(&reply[..end])
+            .split(' ')
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        (status, reply[end + 4..].to_vec())
+    }
+
+    /// What the updater does, with a `Vec` for the DFU partition.
+    fn install(addr: SocketAddr) -> Result<Vec<u8>, OtaError> {
+        let (status, body) = get(addr, "/manifest", None);
+        assert_eq!(status, 200);
+        let (manifest, _) = serde_json_core::from_slice::<Manifest>(&body).unwrap();
+
+        let plan = manifest.plan(RUNNING)?;
+        let mut download = Download::new(plan);
+        let mut flash = vec![0xFF; plan.size as usize];
+        while let Some((offset, len)) = download.next_chunk() {
+            let (status, chunk) = get(addr, "/image", Some((offset, len)));
+            assert_eq!(status, 206);
+            let offset = download.accept(&chunk)? as usize;
+            flash[offset..offset + chunk.len()].copy_from_slice(&chunk);
+        }
+        download.finish(&SigningKey::from_bytes(&KEY).verifying_key().to_bytes())?;
+        Ok(flash)
+    }
+
+    fn image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    fn hex(bytes: &[u8]) -> StdString {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn manifest(version: &str, image: &[u8], key: &[u8; 32]) -> StdString {
+        let signature = SigningKey::from_bytes(key).sign(&Sha512::digest(image));
+        format!(
+            r#"{{"version":"{}","size":{},"sha256":"{}","signature":"{}"}}"#,
+            version,
+            image.len(),
+            hex(&Sha256::digest(image)),
+            hex(&signature.to_bytes())
+        )
+    }
+
+    #[test]
+    fn good_image() {
+        // Two whole chunks and a short one
+        let image = image(2 * CHUNK_SIZE + 1000);
+        let addr = serve(manifest("0.2.0", &image, &KEY), image.clone());
+        assert_eq!(install(addr), Ok(image));
+    }
+
+    #[test]
+    fn same_version_is_skipped() {
+        let image = image(1000);
+        let addr = serve(manifest(RUNNING, &image, &KEY), image);
+        assert_eq!(install(addr), Err(OtaError::UpToDate));
+    }
+
+    #[test]
+    fn bad_sha256() {
+        let image = image(CHUNK_SIZE + 10);
+        let mut served = image.clone();
+        served[CHUNK_SIZE + 3] ^= 1;
+        let addr = serve(manifest("0.2.0", &image, &KEY), served);
+        assert_eq!(install(addr), Err(OtaError::HashMismatch));
+    }
+
+    #[test]
+    fn bad_signature() {
+        let image = image(CHUNK_SIZE + 10);
+        let addr = serve(manifest("0.2.0", &image, &[8; 32]), image);
+        assert_eq!(install(addr), Err(OtaError::BadSignature));
+    }
+
+    #[test]
+    fn truncated_download() {
+        let image = image(2 * CHUNK_SIZE + 1000);
+        let addr = serve(
+            manifest("0.2.0", &image, &KEY),
+            image[..CHUNK_SIZE + 500].to_vec(),
+        );
+        assert_eq!(install(addr), Err(OtaError::UnexpectedChunk));
+    }
+
+    #[test]
+    fn finish_before_the_end() {
+        let image = image(CHUNK_SIZE + 10);
+        let mut plan = UpdatePlan {
+            size: image.len() as u32,
+            sha256: [0; 32],
+            signature: [0; 64],
+        };
+        plan.sha256.copy_from_slice(&Sha256::digest(&image));
+        let mut download = Download::new(plan);
+        download.accept(&image[..CHUNK_SIZE]).unwrap();
+        assert!(matches!(download.finish(&KEY), Err(OtaError::Incomplete)));
+    }
+
+    #[test]
+    fn malformed_manifest() {
+        let mut manifest = Manifest {
+            version: String::new(),
+            size: 0,
+            sha256: String::new(),
+            signature: String::new(),
+        };
+        manifest.version.push_str("0.2.0").unwrap();
+        assert!(matches!(manifest.plan(RUNNING), Err(OtaError::BadSize)));
+        manifest.size = MAX_IMAGE_SIZE + 1;
+        assert!(matches!(manifest.plan(RUNNING), Err(OtaError::BadSize)));
+        manifest.size = 1;
+        manifest.sha256.push_str("zz").unwrap();
+        assert!(matches!(manifest.plan(RUNNING), Err(OtaError::BadHex)));
+    }
+}
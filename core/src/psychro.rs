@@ -0,0 +1,215 @@
+//! Psychrometric metrics derived from a BME280 reading.
+//!
+//! Reference values the formulas reproduce:
+//! - dew point at 25 °C / 50 % = 13.9 °C (Magnus, Sonntag 1990 constants)
+//! - absolute humidity at 25 °C / 100 % = 23.0 g/m³ (CRC Handbook)
+//! - heat index at 90 °F / 60 % = 100 °F (NWS heat index table)
+//! - 950.0 hPa at 540 m and 11.5 °C = 1013.2 hPa at sea level (ICAO standard
+//!   atmosphere)
+
+use libm::{fabsf, logf, powf, sqrtf};
+
+use crate::et;
+use crate::types::SensorData;
+
+// Magnus coefficients over water
+const MAGNUS_A: f32 = 17.62;
+const MAGNUS_B: f32 = 243.12;
+// Specific gas constant of water vapour, J kg⁻¹ K⁻¹
+const R_VAPOUR: f32 = 461.5;
+// Standard atmosphere lapse rate, K/m
+const LAPSE_RATE: f32 = 0.0065;
+
+/// Dew point in °C.
+pub fn dew_point(temperature: f32, humidity: f32) -> f32 {
+    // Bone-dry air has no dew point; clamp to keep the log finite
+    let gamma = logf(humidity.clamp(0.1, 100.0) / 100.0)
+        + MAGNUS_A * temperature / (MAGNUS_B + temperature);
+    MAGNUS_B * gamma / (MAGNUS_A - gamma)
+}
+
+/// Water vapour content of the air in g/m³.
+pub fn absolute_humidity(temperature: f32, humidity: f32) -> f32 {
+    let vapour_pa = et::actual_vapour_pressure(temperature, humidity) * 1000.0;
+    vapour_pa / (R_VAPOUR * (temperature + 273.15)) * 1000.0
+}
+
+/// Apparent temperature in °C after the NWS heat index algorithm.
+///
+/// Below about 27 °C this stays close to the air temperature.
+pub fn heat_index(temperature: f32, humidity: f32) -> f32 {
+    let t = temperature * 9.0 / 5.0 + 32.0;
+    let rh = humidity.clamp(0.0, 100.0);
+
+    let simple = 0.5 * (t + 61.0 + (t - 68.0) * 1.2 + rh * 0.094);
+    let hi = if (simple + t) / 2.0 < 80.0 {
+        simple
+    } else {
+        let mut hi = -42.379 + 2.049_015_2 * t + 10.143_332 * rh
+            - 0.224_755_4 * t * rh
+            - 0.006_837_83 * t * t
+            - 0.054_817_17 * rh * rh
+            + 0.001_228_74 * t * t * rh
+            + 0.000_852_82 * t * rh * rh
+            - 0.000_001_99 * t * t * rh * rh;
+        if rh < 13.0 && (80.0..=112.0).contains(&t) {
+            hi -= (13.0 - rh) / 4.0 * sqrtf((17.0 - fabsf(t - 95.0)) / 17.0);
+        } else if rh > 85.0 && (80.0..=87.0).contains(&t) {
+            hi += (rh - 85.0) / 10.0 * (87.0 - t) / 5.0;
+        }
+        hi
+    };
+
+    (hi - 32.0) * 5.0 / 9.0
+}
+
+/// Station pressure reduced to sea level, hPa, for `altitude` in metres.
+pub fn sea_level_pressure(pressure: f32, altitude: f32, temperature: f32) -> f32 {
+    let ratio = 1.0 - LAPSE_RATE * altitude / (temperature + LAPSE_RATE * altitude + 273.15);
+    pressure * powf(ratio, -5.257)
+}
+
+/// Fills in the derived fields of `data` from its raw readings.
+pub fn derive(data: &mut SensorData, altitude: f32) {
+    if let (Some(t), Some(h)) = (data.temperature, data.humidity) {
+        data.vpd = Some(et::vapour_pressure_deficit(t, h));
+        data.dew_point = Some(dew_point(t, h));
+        data.absolute_humidity = Some(absolute_humidity(t, h));
+        data.heat_index = Some(heat_index(t, h));
+    }
+    if let (Some(t), Some(p)) = (data.temperature, data.pressure) {
+        data.sea_level_pressure = Some(sea_level_pressure(p, altitude, t));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(value: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (value - expected).abs() <= tolerance,
+            "{value} is not {expected} ± {tolerance}"
+        );
+    }
+
+    fn fahrenheit(celsius: f32) -> f32 {
+        celsius * 9.0 / 5.0 + 32.0
+    }
+
+    fn celsius(fahrenheit: f32) -> f32 {
+        (fahrenheit - 32.0) * 5.0 / 9.0
+    }
+
+    #[test]
+    fn saturation_vapour_pressure() {
+        // °C, kPa; FAO-56 Annex 2, Table 2.3
+        for (t, expected) in [(-5.0, 0.421), (5.0, 0.872), (15.0, 1.705), (35.0, 5.623)] {
+            assert_near(et::saturation_vapour_pressure(t), expected, 0.003);
+        }
+    }
+
+    #[test]
+    fn dew_point_table() {
+        // °C, %, °C
+        for (t, rh, expected) in [
+            (25.0, 50.0, 13.9),
+            (20.0, 100.0, 20.0),
+            (30.0, 70.0, 23.9),
+            (10.0, 50.0, 0.1),
+            (-10.0, 80.0, -12.8),
+        ] {
+            assert_near(dew_point(t, rh), expected, 0.15);
+        }
+        // Bone-dry air still gives a finite, very low dew point
+        let dry = dew_point(20.0, 0.0);
+        assert!(dry.is_finite() && dry < -50.0);
+    }
+
+    #[test]
+    fn vapour_pressure_deficit_table() {
+        // °C, %, kPa
+        for (t, rh, expected) in [
+            (25.0, 50.0, 1.584),
+            (20.0, 80.0, 0.468),
+            (30.0, 40.0, 2.546),
+            (35.0, 30.0, 3.936),
+            (15.0, 100.0, 0.0),
+        ] {
+            assert_near(et::vapour_pressure_deficit(t, rh), expected, 0.005);
+        }
+    }
+
+    #[test]
+    fn absolute_humidity_table() {
+        // °C, %, g/m³; CRC Handbook
+        for (t, rh, expected) in [
+            (25.0, 100.0, 23.0),
+            (20.0, 100.0, 17.3),
+            (30.0, 100.0, 30.4),
+            (10.0, 50.0, 4.7),
+        ] {
+            assert_near(absolute_humidity(t, rh), expected, 0.15);
+        }
+    }
+
+    #[test]
+    fn heat_index_table() {
+        // °F, %, °F; NWS heat index table
+        for (t, rh, expected) in [
+            (90.0, 60.0, 100.0),
+            (80.0, 40.0, 80.0),
+            (100.0, 40.0, 109.0),
+            (96.0, 65.0, 121.0),
+            (86.0, 90.0, 105.0),
+        ] {
+            let hi = fahrenheit(heat_index(celsius(t), rh));
+            assert_near(hi, expected, 1.0);
+        }
+        // Mild air is left close to its temperature
+        assert_near(heat_index(20.0, 50.0), 20.0, 1.0);
+    }
+
+    #[test]
+    fn sea_level_pressure_table() {
+        // hPa, m, °C, hPa
+        for (p, altitude, t, expected) in [
+            (950.0, 540.0, 11.5, 1013.2),
+            (1000.0, 0.0, 20.0, 1000.0),
+            (1000.0, 100.0, 15.0, 1011.9),
+        ] {
+            assert_near(sea_level_pressure(p, altitude, t), expected, 0.3);
+        }
+    }
+
+    #[test]
+    fn derive_fills_what_the_readings_allow() {
+        let mut data = SensorData {
+            temperature: Some(25.0),
+            humidity: Some(50.0),
+            pressure: Some(950.0),
+            ..SensorData::default()
+        };
+        derive(&mut data, 540.0);
+        assert_near(data.vpd.unwrap(), 1.584, 0.005);
+        assert_near(data.dew_point.unwrap(), 13.9, 0.15);
+        assert!(data.absolute_humidity.is_some());
+        assert!(data.heat_index.is_some());
+        assert!(data.sea_level_pressure.unwrap() > 1010.0);
+
+        // Without humidity only the pressure can be reduced
+        let mut data = SensorData {
+            temperature: Some(25.0),
+            pressure: Some(1000.0),
+            ..SensorData::default()
+        };
+        derive(&mut data, 0.0);
+        assert_eq!(data.vpd, None);
+        assert_eq!(data.dew_point, None);
+        assert_eq!(data.sea_level_pressure, Some(1000.0));
+
+        let mut data = SensorData::default();
+        derive(&mut data, 0.0);
+        assert_eq!(data.sea_level_pressure, None);
+    }
+}
@@ -0,0 +1,425 @@
+use serde::Serialize;
+
+use crate::traits::{Actuator, Clock};
+use crate::types::PumpCommand;
+
+// How often a running program checks with its supervisor
+const CHECK_INTERVAL_MS: u64 = 250;
+
+/// `pulses` runs of `on_secs` with a `soak_secs` pause between each, so the
+/// water can soak in instead of running off.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Program {
+    pub pulses: u8,
+    pub on_secs: u16,
+    pub soak_secs: u16,
+}
+
+impl Program {
+    /// One continuous run.
+    pub const fn single(secs: u16) -> Self {
+        Self {
+            pulses: 1,
+            on_secs: secs,
+            soak_secs: 0,
+        }
+    }
+
+    /// Seconds the pump is on over the whole program.
+    pub fn on_secs_total(&self) -> u32 {
+        self.pulses as u32 * self.on_secs as u32
+    }
+
+    /// Seconds from the first pulse to the end of the last.
+    pub fn duration_secs(&self) -> u32 {
+        self.on_secs_total() + self.pulses.saturating_sub(1) as u32 * self.soak_secs as u32
+    }
+}
+
+impl From<PumpCommand> for Program {
+    fn from(cmd: PumpCommand) -> Self {
+        Self {
+            // A program has at least one pulse
+            pulses: cmd.pulses.max(1),
+            on_secs: cmd.duration_secs,
+            soak_secs: cmd.soak_secs,
+        }
+    }
+}
+
+/// Shortens the pulses so the whole program stays within the safety limit.
+/// A pulse is never cut below a second; the program gets fewer pulses
+/// instead.
+pub fn limit(program: Program, max_secs: u16) -> Program {
+    let pulses = program
+        .pulses
+        .clamp(1, max_secs.clamp(1, u8::MAX as u16) as u8);
+    Program {
+        pulses,
+        on_secs: program.on_secs.min(max_secs / pulses as u16),
+        ..program
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    On,
+    Soak,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::On => "on",
+            Phase::Soak => "soak",
+        }
+    }
+}
+
+/// Reported at the start of every pulse and soak.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Progress {
+    // 1-based
+    pub pulse: u8,
+    pub pulses: u8,
+    pub phase: Phase,
+}
+
+/// How a program ended, with the seconds the pump was actually on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Completed { on_secs: u32 },
+    Cancelled { on_secs: u32 },
+}
+
+impl Outcome {
+    pub fn on_secs(&self) -> u32 {
+        match *self {
+            Outcome::Completed { on_secs } | Outcome::Cancelled { on_secs } => on_secs,
+        }
+    }
+}
+
+/// Watches over a running program.
+pub trait Supervisor {
+    /// True stops the whole program.
+    fn cancelled(&mut self) -> bool;
+
+    /// True ends the current pulse early, e.g. once a flow meter has seen
+    /// its volume.
+    fn pulse_done(&mut self) -> bool {
+        false
+    }
+
+    /// Called at the start of every pulse and soak.
+    fn progress(&mut self, _progress: Progress) {}
+}
+
+/// For programs nobody can stop, like those of the accelerated simulation.
+pub struct Unattended;
+
+impl Supervisor for Unattended {
+    fn cancelled(&mut self) -> bool {
+        false
+    }
+}
+
+/// Runs `program`, asking `supervisor` about four times a second whether to
+/// go on. The pump is off whenever this returns.
+pub async fn run_program<A: Actuator, C: Clock, S: Supervisor>(
+    actuator: &mut A,
+    clock: &mut C,
+    program: Program,
+    supervisor: &mut S,
+) -> Outcome {
+    let mut on_ms = 0;
+
+    for pulse in 1..=program.pulses {
+        if pulse > 1 && program.soak_secs > 0 {
+            supervisor.progress(Progress {
+                pulse,
+                pulses: program.pulses,
+                phase: Phase::Soak,
+            });
+            if wait(clock, program.soak_secs, supervisor, Phase::Soak).await == Wait::Cancelled {
+                return Outcome::Cancelled {
+                    on_secs: (on_ms / 1000) as u32,
+                };
+            }
+        }
+
+        supervisor.progress(Progress {
+            pulse,
+            pulses: program.pulses,
+            phase: Phase::On,
+        });
+        let start = clock.now_ms();
+        actuator.set_on(true);
+        let waited = wait(clock, program.on_secs, supervisor, Phase::On).await;
+        actuator.set_on(false);
+        on_ms += clock.now_ms().saturating_sub(start);
+
+        if waited == Wait::Cancelled {
+            return Outcome::Cancelled {
+                on_secs: (on_ms / 1000) as u32,
+            };
+        }
+    }
+
+    Outcome::Completed {
+        on_secs: (on_ms / 1000) as u32,
+    }
+}
+
+#[derive(PartialEq)]
+enum Wait {
+    Elapsed,
+    Cancelled,
+}
+
+// Sleeps `secs` in steps, ending a pulse early when the supervisor says so
+async fn wait<C: Clock, S: Supervisor>(
+    clock: &mut C,
+    secs: u16,
+    supervisor: &mut S,
+    phase: Phase,
+) -> Wait {
+    let mut remaining = secs as u64 * 1000;
+    while remaining > 0 {
+        if supervisor.cancelled() {
+            return Wait::Cancelled;
+        }
+        if phase == Phase::On && supervisor.pulse_done() {
+            break;
+        }
+        let step = remaining.min(CHECK_INTERVAL_MS);
+        clock.sleep_ms(step).await;
+        remaining -= step;
+    }
+    Wait::Elapsed
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::testing::block_on;
+
+    // Sleeping moves the shared time on
+    struct FakeClock<'a>(&'a Cell<u64>);
+
+    impl Clock for FakeClock<'_> {
+        fn now_ms(&self) -> u64 {
+            self.0.get()
+        }
+
+        async fn sleep_ms(&mut self, ms: u64) {
+            self.0.set(self.0.get() + ms);
+        }
+    }
+
+    // Keeps the time of every switch, on and off
+    struct FakePump<'a> {
+        now_ms: &'a Cell<u64>,
+        on: bool,
+        switches: Vec<(u64, bool)>,
+    }
+
+    impl Actuator for FakePump<'_> {
+        fn set_on(&mut self, on: bool) {
+            self.on = on;
+            self.switches.push((self.now_ms.get(), on));
+        }
+    }
+
+    struct Watch<'a> {
+        now_ms: &'a Cell<u64>,
+        cancel_at_ms: Option<u64>,
+        // Pulses end this long after they start, as with a flow meter
+        pulse_ms: Option<u64>,
+        pulse_start_ms: u64,
+        progress: Vec<Progress>,
+    }
+
+    impl Supervisor for Watch<'_> {
+        fn cancelled(&mut self) -> bool {
+            self.cancel_at_ms.is_some_and(|at| self.now_ms.get() >= at)
+        }
+
+        fn pulse_done(&mut self) -> bool {
+            self.pulse_ms
+                .is_some_and(|ms| self.now_ms.get() - self.pulse_start_ms >= ms)
+        }
+
+        fn progress(&mut self, progress: Progress) {
+            self.pulse_start_ms = self.now_ms.get();
+            self.progress.push(progress);
+        }
+    }
+
+    struct Run {
+        outcome: Outcome,
+        // Start and length of each pulse, ms
+        pulses: Vec<(u64, u64)>,
+        progress: Vec<Progress>,
+        end_ms: u64,
+    }
+
+    fn run(program: Program, cancel_at_ms: Option<u64>, pulse_ms: Option<u64>) -> Run {
+        let now_ms = Cell::new(0);
+        let mut clock = FakeClock(&now_ms);
+        let mut pump = FakePump {
+            now_ms: &now_ms,
+            on: false,
+            switches: Vec::new(),
+        };
+        let mut watch = Watch {
+            now_ms: &now_ms,
+            cancel_at_ms,
+            pulse_ms,
+            pulse_start_ms: 0,
+            progress: Vec::new(),
+        };
+        let outcome = block_on(run_program(&mut pump, &mut clock, program, &mut watch));
+
+        assert!(!pump.on, "pump left on");
+        let pulses = pump
+            .switches
+            .chunks(2)
+            .map(|pair| {
+                assert_eq!((pair[0].1, pair[1].1), (true, false));
+                (pair[0].0, pair[1].0 - pair[0].0)
+            })
+            .collect();
+        Run {
+            outcome,
+            pulses,
+            progress: watch.progress,
+            end_ms: now_ms.get(),
+        }
+    }
+
+    #[test]
+    fn limit_shortens_pulses_to_the_safety_limit() {
+        let program = Program {
+            pulses: 3,
+            on_secs: 20,
+            soak_secs: 60,
+        };
+        assert_eq!(
+            limit(program, 30),
+            Program {
+                on_secs: 10,
+                ..program
+            }
+        );
+        assert_eq!(limit(program, 60), program);
+        assert_eq!(limit(Program::single(45), 30), Program::single(30));
+    }
+
+    #[test]
+    fn limit_drops_pulses_rather_than_run_them_for_nothing() {
+        let program = Program {
+            pulses: 40,
+            on_secs: 5,
+            soak_secs: 10,
+        };
+        let limited = limit(program, 30);
+        assert_eq!(limited.pulses, 30);
+        assert_eq!(limited.on_secs, 1);
+        assert!(limited.on_secs_total() <= 30);
+
+        // Even without any time to spend, a program keeps a pulse
+        assert_eq!(limit(program, 0).pulses, 1);
+        assert_eq!(limit(program, 0).on_secs, 0);
+    }
+
+    #[test]
+    fn limited_program_runs_every_pulse_for_a_second() {
+        let program = limit(
+            Program {
+                pulses: 40,
+                on_secs: 5,
+                soak_secs: 10,
+            },
+            30,
+        );
+        let run = run(program, None, None);
+        assert_eq!(run.outcome, Outcome::Completed { on_secs: 30 });
+        assert_eq!(run.pulses.len(), 30);
+        assert!(run.pulses.iter().all(|&(_, ms)| ms == 1000));
+        assert_eq!(run.end_ms, program.duration_secs() as u64 * 1000);
+    }
+
+    #[test]
+    fn pulses_and_soaks_alternate() {
+        let program = Program {
+            pulses: 3,
+            on_secs: 10,
+            soak_secs: 60,
+        };
+        let run = run(program, None, None);
+        assert_eq!(run.outcome, Outcome::Completed { on_secs: 30 });
+        assert_eq!(
+            run.pulses,
+            [(0, 10_000), (70_000, 10_000), (140_000, 10_000)]
+        );
+
+        let phases: Vec<(u8, Phase)> = run.progress.iter().map(|p| (p.pulse, p.phase)).collect();
+        assert_eq!(
+            phases,
+            [
+                (1, Phase::On),
+                (2, Phase::Soak),
+                (2, Phase::On),
+                (3, Phase::Soak),
+                (3, Phase::On),
+            ]
+        );
+        assert!(run.progress.iter().all(|p| p.pulses == 3));
+    }
+
+    #[test]
+    fn cancel_stops_the_program_and_the_pump() {
+        let program = Program {
+            pulses: 3,
+            on_secs: 10,
+            soak_secs: 60,
+        };
+        // During the second pulse
+        let during_pulse = run(program, Some(75_000), None);
+        assert_eq!(during_pulse.outcome, Outcome::Cancelled { on_secs: 15 });
+        assert_eq!(during_pulse.pulses, [(0, 10_000), (70_000, 5_000)]);
+
+        // During a soak
+        let during_soak = run(program, Some(30_000), None);
+        assert_eq!(during_soak.outcome, Outcome::Cancelled { on_secs: 10 });
+        assert_eq!(during_soak.pulses.len(), 1);
+    }
+
+    #[test]
+    fn supervisor_can_end_a_pulse_early() {
+        let program = Program {
+            pulses: 2,
+            on_secs: 10,
+            soak_secs: 5,
+        };
+        let run = run(program, None, Some(4_000));
+        assert_eq!(run.outcome, Outcome::Completed { on_secs: 8 });
+        assert_eq!(run.pulses, [(0, 4_000), (9_000, 4_000)]);
+    }
+
+    #[test]
+    fn a_command_is_at_least_one_pulse() {
+        let cmd = PumpCommand {
+            pulses: 0,
+            ..PumpCommand::single(12)
+        };
+        let program = Program::from(cmd);
+        assert_eq!(program, Program::single(12));
+        assert_eq!(program.duration_secs(), 12);
+    }
+}
@@ -0,0 +1,332 @@
+//! Plausibility checks on every reading: range, rate of change and stuck
+//! values, field by field.
+//!
+//! A field that fails is flagged and emptied, so the watering logic only
+//! ever sees readings that passed. Worked example for the soil moisture with
+//! at most 20 % a minute: 42 % then 95 % a minute later is too fast and is
+//! dropped. If the next reading is back at 42 % it passes; if it is 94 %,
+//! the jump was real and it passes too.
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::SensorData;
+
+/// How a field of a reading fared.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    #[default]
+    Ok,
+    // The sensor gave nothing
+    Missing,
+    OutOfRange,
+    // Changed faster than the field can
+    TooFast,
+    // The exact same value too many times running
+    Stuck,
+}
+
+/// Flags for the measured fields of a reading; derived fields follow from
+/// these.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub struct Quality {
+    pub temperature: Flag,
+    pub humidity: Flag,
+    pub pressure: Flag,
+    pub soil_moisture: Flag,
+    pub water_level: Flag,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Limits {
+    pub min: f32,
+    pub max: f32,
+    // Largest plausible change per minute; None to not check
+    pub max_rate_per_min: Option<f32>,
+    // Readings in a row with the exact same value that mean a stuck
+    // sensor; 0 to not check
+    pub stuck_count: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct QualitySettings {
+    pub temperature: Limits,
+    pub humidity: Limits,
+    pub pressure: Limits,
+    pub soil_moisture: Limits,
+    pub water_level: Limits,
+}
+
+/// What one field's checks remember between readings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FieldCheck {
+    // Time and value of the last reading that passed
+    last: Option<(u32, f32)>,
+    // A reading rejected as too fast, which the next may confirm
+    jump: Option<(u32, f32)>,
+    // The latest value and how many times running it came
+    repeat: Option<(f32, u16)>,
+}
+
+impl FieldCheck {
+    pub const fn new() -> Self {
+        Self {
+            last: None,
+            jump: None,
+            repeat: None,
+        }
+    }
+
+    /// Checks one reading; the value is kept only if it passes.
+    pub fn check(&mut self, t_secs: u32, value: &mut Option<f32>, limits: &Limits) -> Flag {
+        let flag = self.flag(t_secs, *value, limits);
+        if flag != Flag::Ok {
+            *value = None;
+        }
+        flag
+    }
+
+    fn flag(&mut self, t_secs: u32, value: Option<f32>, limits: &Limits) -> Flag {
+        let Some(value) = value else {
+            return Flag::Missing;
+        };
+        if !(limits.min..=limits.max).contains(&value) {
+            return Flag::OutOfRange;
+        }
+
+        let count = match self.repeat {
+            Some((last, count)) if last == value => count.saturating_add(1),
+            _ => 1,
+        };
+        self.repeat = Some((value, count));
+        if limits.stuck_count > 0 && count >= limits.stuck_count {
+            return Flag::Stuck;
+        }
+
+        if let Some(max_rate) = limits.max_rate_per_min {
+            let near = |(t, v): (u32, f32)| {
+                let minutes = (t_secs.saturating_sub(t) as f32 / 60.0).max(1.0);
+                (value - v).abs() <= max_rate * minutes
+            };
+            let plausible = self.last.is_none_or(near) || self.jump.is_some_and(near);
+            if !plausible {
+                self.jump = Some((t_secs, value));
+                return Flag::TooFast;
+            }
+        }
+
+        self.last = Some((t_secs, value));
+        self.jump = None;
+        Flag::Ok
+    }
+}
+
+/// Checks every measured field of each reading.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Checker {
+    temperature: FieldCheck,
+    humidity: FieldCheck,
+    pressure: FieldCheck,
+    soil_moisture: FieldCheck,
+    water_level: FieldCheck,
+}
+
+impl Checker {
+    pub const fn new() -> Self {
+        Self {
+            temperature: FieldCheck::new(),
+            humidity: FieldCheck::new(),
+            pressure: FieldCheck::new(),
+            soil_moisture: FieldCheck::new(),
+            water_level: FieldCheck::new(),
+        }
+    }
+
+    /// Empties the fields of `data` that fail and fills in its flags. The
+    /// soil check goes by the unfiltered moisture, and a failed reading
+    /// empties both moisture fields.
+    pub fn check(&mut self, t_secs: u32, data: &mut SensorData, settings: &QualitySettings) {
+        let soil_moisture =
+            self.soil_moisture
+                .check(t_secs, &mut data.soil_moisture_raw, &settings.soil_moisture);
+        if soil_moisture != Flag::Ok {
+            data.soil_moisture = None;
+        }
+
+        data.quality = Quality {
+            temperature: self.temperature.check(
+                t_secs,
+                &mut data.temperature,
+                &settings.temperature,
+            ),
+            humidity: self
+                .humidity
+                .check(t_secs, &mut data.humidity, &settings.humidity),
+            pressure: self
+                .pressure
+                .check(t_secs, &mut data.pressure, &settings.pressure),
+            soil_moisture,
+            water_level: self.water_level.check(
+                t_secs,
+                &mut data.water_level,
+                &settings.water_level,
+            ),
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOIL: Limits = Limits {
+        min: 0.0,
+        max: 100.0,
+        max_rate_per_min: Some(20.0),
+        stuck_count: 0,
+    };
+    const TEMPERATURE: Limits = Limits {
+        min: -40.0,
+        max: 85.0,
+        max_rate_per_min: None,
+        stuck_count: 3,
+    };
+    const SETTINGS: QualitySettings = QualitySettings {
+        temperature: TEMPERATURE,
+        humidity: Limits {
+            min: 0.0,
+            max: 100.0,
+            max_rate_per_min: None,
+            stuck_count: 0,
+        },
+        pressure: Limits {
+            min: 300.0,
+            max: 1100.0,
+            max_rate_per_min: None,
+            stuck_count: 0,
+        },
+        soil_moisture: SOIL,
+        water_level: Limits {
+            min: 2.0,
+            max: 400.0,
+            max_rate_per_min: None,
+            stuck_count: 0,
+        },
+    };
+
+    // Flags a reading of `value` at `t_secs`, and whether it was kept
+    fn check(field: &mut FieldCheck, t_secs: u32, value: f32, limits: &Limits) -> Flag {
+        let mut value = Some(value);
+        let flag = field.check(t_secs, &mut value, limits);
+        assert_eq!(value.is_some(), flag == Flag::Ok);
+        flag
+    }
+
+    #[test]
+    fn worked_example() {
+        for (next, flag) in [(42.0, Flag::Ok), (94.0, Flag::Ok), (60.0, Flag::Ok)] {
+            let mut soil = FieldCheck::new();
+            assert_eq!(check(&mut soil, 0, 42.0, &SOIL), Flag::Ok);
+            assert_eq!(check(&mut soil, 60, 95.0, &SOIL), Flag::TooFast);
+            assert_eq!(check(&mut soil, 120, next, &SOIL), flag, "then {next}");
+        }
+    }
+
+    #[test]
+    fn an_unconfirmed_jump_stays_rejected() {
+        let mut soil = FieldCheck::new();
+        check(&mut soil, 0, 42.0, &SOIL);
+        assert_eq!(check(&mut soil, 60, 95.0, &SOIL), Flag::TooFast);
+        // Too far from both the last good reading and the jump
+        assert_eq!(check(&mut soil, 70, 70.0, &SOIL), Flag::TooFast);
+        assert_eq!(check(&mut soil, 130, 42.0, &SOIL), Flag::Ok);
+    }
+
+    #[test]
+    fn the_rate_allows_for_the_time_between_readings() {
+        let mut soil = FieldCheck::new();
+        check(&mut soil, 0, 40.0, &SOIL);
+        // 50 % in 5 minutes is 10 % a minute
+        assert_eq!(check(&mut soil, 300, 90.0, &SOIL), Flag::Ok);
+        // Readings closer than a minute are allowed a minute's change
+        assert_eq!(check(&mut soil, 310, 75.0, &SOIL), Flag::Ok);
+        assert_eq!(check(&mut soil, 320, 50.0, &SOIL), Flag::TooFast);
+    }
+
+    #[test]
+    fn out_of_range_and_missing() {
+        let mut field = FieldCheck::new();
+        assert_eq!(check(&mut field, 0, 85.1, &TEMPERATURE), Flag::OutOfRange);
+        assert_eq!(check(&mut field, 0, -40.1, &TEMPERATURE), Flag::OutOfRange);
+        assert_eq!(check(&mut field, 0, 85.0, &TEMPERATURE), Flag::Ok);
+        assert_eq!(field.check(0, &mut None, &TEMPERATURE), Flag::Missing);
+    }
+
+    #[test]
+    fn a_repeated_value_is_stuck() {
+        let mut field = FieldCheck::new();
+        assert_eq!(check(&mut field, 0, 21.5, &TEMPERATURE), Flag::Ok);
+        assert_eq!(check(&mut field, 60, 21.5, &TEMPERATURE), Flag::Ok);
+        assert_eq!(check(&mut field, 120, 21.5, &TEMPERATURE), Flag::Stuck);
+        assert_eq!(check(&mut field, 180, 21.5, &TEMPERATURE), Flag::Stuck);
+        // Any change starts the count again
+        assert_eq!(check(&mut field, 240, 21.6, &TEMPERATURE), Flag::Ok);
+        assert_eq!(check(&mut field, 300, 21.6, &TEMPERATURE), Flag::Ok);
+
+        // 0 never checks
+        let mut soil = FieldCheck::new();
+        for t in 0..100 {
+            assert_eq!(check(&mut soil, t * 60, 42.0, &SOIL), Flag::Ok);
+        }
+    }
+
+    #[test]
+    fn the_checker_empties_failed_fields() {
+        let mut checker = Checker::new();
+        let mut data = SensorData {
+            temperature: Some(120.0),
+            humidity: Some(55.0),
+            pressure: Some(1013.0),
+            soil_moisture_raw: Some(42.0),
+            soil_moisture: Some(42.0),
+            water_level: None,
+            ..SensorData::default()
+        };
+        checker.check(0, &mut data, &SETTINGS);
+        assert_eq!(
+            data.quality,
+            Quality {
+                temperature: Flag::OutOfRange,
+                humidity: Flag::Ok,
+                pressure: Flag::Ok,
+                soil_moisture: Flag::Ok,
+                water_level: Flag::Missing,
+            }
+        );
+        assert_eq!(data.temperature, None);
+        assert_eq!(data.humidity, Some(55.0));
+        assert_eq!(data.soil_moisture, Some(42.0));
+    }
+
+    #[test]
+    fn a_failed_soil_reading_empties_the_average_too() {
+        let mut checker = Checker::new();
+        let mut data = SensorData {
+            soil_moisture_raw: Some(42.0),
+            soil_moisture: Some(42.0),
+            ..SensorData::default()
+        };
+        checker.check(0, &mut data, &SETTINGS);
+
+        // The filtered value would pass, but the raw one decides
+        let mut data = SensorData {
+            soil_moisture_raw: Some(95.0),
+            soil_moisture: Some(58.0),
+            ..SensorData::default()
+        };
+        checker.check(60, &mut data, &SETTINGS);
+        assert_eq!(data.quality.soil_moisture, Flag::TooFast);
+        assert_eq!(data.soil_moisture_raw, None);
+        assert_eq!(data.soil_moisture, None);
+    }
+}
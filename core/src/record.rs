@@ -0,0 +1,985 @@
+//! Compact log of what the device saw and decided, for replay on the host.
+//!
+//! An export is a header followed by entries, oldest first. Each entry is a
+//! tag byte, the uptime in seconds and a fixed-size payload, so the oldest
+//! entries can be dropped whole when the ring fills up.
+//!
+//! The ring holds less than the day of samples the ET and light estimates
+//! are based on, so the device also logs checkpoints of its decision
+//! [`State`] for the replay to start from.
+
+use heapless::Deque;
+
+use crate::guards::{GuardSettings, Guards, PRESSURE_HOURS, SkipReason};
+use crate::light::LightTracker;
+use crate::pump::Program;
+use crate::types::{PumpCommand, SensorData};
+use crate::watering::{Decision, EtTracker, Settings, State};
+
+pub const LOG_VERSION: u8 = 8;
+// magic, version, pump max, three f32 ET settings, then the guards: four
+// f32 thresholds, two run counts and the rain delay; then the sunny DLI
+pub const HEADER_LEN: usize = 4 + 1 + 2 + 3 * 4 + 4 * 4 + 2 + 4 + 4;
+pub const MAX_ENTRY_LEN: usize = CHECKPOINT_LEN;
+
+const MAGIC: [u8; 4] = *b"WREC";
+
+const TAG_SAMPLE: u8 = 1;
+const TAG_COMMAND: u8 = 2;
+const TAG_PUMP: u8 = 3;
+const TAG_CHECKPOINT: u8 = 4;
+const TAG_SKIP: u8 = 5;
+
+// tag, time, presence flags, six f32 fields
+const SAMPLE_LEN: usize = 1 + 4 + 1 + 6 * 4;
+// tag, time, flags, pulse seconds, pulses, soak seconds, zone
+const COMMAND_LEN: usize = 1 + 4 + 1 + 2 + 1 + 2 + 1;
+// tag, time, pulse seconds, pulses, soak seconds
+const PUMP_LEN: usize = 1 + 4 + 2 + 1 + 2;
+// tag, time, reason
+const SKIP_LEN: usize = 1 + 4 + 1;
+// tag, time, then the ET tracker: presence flags, window start, four f32
+// temperatures, day; the light tracker: presence flags, window start, last
+// reading time and lux, mol so far, time covered, last day's DLI; then the
+// guards: presence flags, six f32 readings (NaN when missing), four times
+// and the run count
+const CHECKPOINT_LEN: usize = 1
+    + 4
+    + (1 + 4 + 4 * 4 + 2)
+    + (1 + 4 + 4 + 4 + 4 + 4 + 4)
+    + (1 + (2 + PRESSURE_HOURS) * 4 + 4 * 4 + 1);
+
+const HAS_TEMPERATURE: u8 = 1 << 0;
+const HAS_HUMIDITY: u8 = 1 << 1;
+const HAS_PRESSURE: u8 = 1 << 2;
+const HAS_SOIL_MOISTURE: u8 = 1 << 3;
+const HAS_WATER_LEVEL: u8 = 1 << 4;
+const HAS_LUX: u8 = 1 << 5;
+
+const TARGETED: u8 = 1 << 0;
+
+const HAS_WINDOW: u8 = 1 << 0;
+const HAS_LAST_DAY: u8 = 1 << 1;
+const HAS_DAY_OF_YEAR: u8 = 1 << 2;
+
+const HAS_LIGHT_WINDOW: u8 = 1 << 0;
+const HAS_LAST_READING: u8 = 1 << 1;
+const HAS_LAST_DLI: u8 = 1 << 2;
+
+const HAS_PRESSURE_SINCE: u8 = 1 << 0;
+const HAS_HOT_UNTIL: u8 = 1 << 1;
+const HAS_RAIN_UNTIL: u8 = 1 << 2;
+const HAS_DAY_START: u8 = 1 << 3;
+
+// Samples are most entries; there is no heap to box them on
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy)]
+pub enum Event {
+    Sample(SensorData),
+    // A pump request as received, before any limiting
+    Command(PumpCommand),
+    // The program the pump was started with
+    Pump(Program),
+    // A command the guards turned down
+    Skip(SkipReason),
+    Checkpoint(State),
+}
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub t_secs: u32,
+    pub event: Event,
+}
+
+/// Settings the decisions depend on, saved with every export so a replay
+/// uses the values the device ran with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Header {
+    pub settings: Settings,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownTag(u8),
+    UnknownReason(u8),
+    Truncated,
+}
+
+impl Header {
+    pub fn encode(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0u8; HEADER_LEN];
+        buf[..4].copy_from_slice(&MAGIC);
+        buf[4] = LOG_VERSION;
+        let settings = &self.settings;
+        buf[5..7].copy_from_slice(&settings.pump_max_secs.to_le_bytes());
+        buf[7..11].copy_from_slice(&settings.latitude.to_le_bytes());
+        buf[11..15].copy_from_slice(&settings.crop_coefficient.to_le_bytes());
+        buf[15..19].copy_from_slice(&settings.reference_et_mm.to_le_bytes());
+        let guards = &settings.guards;
+        buf[19..23].copy_from_slice(&guards.frost_below.to_le_bytes());
+        buf[23..27].copy_from_slice(&guards.humid_above.to_le_bytes());
+        buf[27..31].copy_from_slice(&guards.heat_wave_above.to_le_bytes());
+        buf[31] = guards.runs_per_day;
+        buf[32] = guards.heat_wave_extra_runs;
+        buf[33..37].copy_from_slice(&guards.rain_drop.to_le_bytes());
+        buf[37..41].copy_from_slice(&guards.rain_delay_secs.to_le_bytes());
+        buf[41..45].copy_from_slice(&settings.sunny_dli.to_le_bytes());
+        buf
+    }
+
+    /// Splits an export into its header and the encoded entries.
+    pub fn decode(buf: &[u8]) -> Result<(Self, &[u8]), DecodeError> {
+        if buf.len() < HEADER_LEN {
+            return Err(DecodeError::Truncated);
+        }
+        if buf[..4] != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        if buf[4] != LOG_VERSION {
+            return Err(DecodeError::UnsupportedVersion(buf[4]));
+        }
+        let header = Header {
+            settings: Settings {
+                pump_max_secs: u16::from_le_bytes([buf[5], buf[6]]),
+                latitude: read_f32(buf, 7),
+                crop_coefficient: read_f32(buf, 11),
+                reference_et_mm: read_f32(buf, 15),
+                sunny_dli: read_f32(buf, 41),
+                guards: GuardSettings {
+                    frost_below: read_f32(buf, 19),
+                    humid_above: read_f32(buf, 23),
+                    heat_wave_above: read_f32(buf, 27),
+                    runs_per_day: buf[31],
+                    heat_wave_extra_runs: buf[32],
+                    rain_drop: read_f32(buf, 33),
+                    rain_delay_secs: read_u32(buf, 37),
+                },
+            },
+        };
+        Ok((header, &buf[HEADER_LEN..]))
+    }
+}
+
+impl Entry {
+    pub fn encode(&self, buf: &mut [u8; MAX_ENTRY_LEN]) -> usize {
+        buf[1..5].copy_from_slice(&self.t_secs.to_le_bytes());
+
+        match self.event {
+            Event::Sample(data) => {
+                buf[0] = TAG_SAMPLE;
+                let mut flags = 0;
+                for (bit, value) in [
+                    (HAS_TEMPERATURE, data.temperature),
+                    (HAS_HUMIDITY, data.humidity),
+                    (HAS_PRESSURE, data.pressure),
+                    (HAS_SOIL_MOISTURE, data.soil_moisture),
+                    (HAS_WATER_LEVEL, data.water_level),
+                    (HAS_LUX, data.lux),
+                ] {
+                    if value.is_some() {
+                        flags |= bit;
+                    }
+                }
+                buf[5] = flags;
+                let fields = [
+                    data.temperature.unwrap_or(0.0),
+                    data.humidity.unwrap_or(0.0),
+                    data.pressure.unwrap_or(0.0),
+                    data.soil_moisture.unwrap_or(0.0),
+                    data.water_level.unwrap_or(0.0),
+                    data.lux.unwrap_or(0.0),
+                ];
+                for (i, value) in fields.iter().enumerate() {
+                    buf[6 + i * 4..10 + i * 4].copy_from_slice(&value.to_le_bytes());
+                }
+                SAMPLE_LEN
+            }
+            Event::Command(cmd) => {
+                buf[0] = TAG_COMMAND;
+                buf[5] = if cmd.targeted { TARGETED } else { 0 };
+                encode_program(cmd.duration_secs, cmd.pulses, cmd.soak_secs, &mut buf[6..]);
+                buf[11] = cmd.zone;
+                COMMAND_LEN
+            }
+            Event::Pump(program) => {
+                buf[0] = TAG_PUMP;
+                encode_program(
+                    program.on_secs,
+                    program.pulses,
+                    program.soak_secs,
+                    &mut buf[5..],
+                );
+                PUMP_LEN
+            }
+            Event::Skip(reason) => {
+                buf[0] = TAG_SKIP;
+                buf[5] = reason as u8;
+                SKIP_LEN
+            }
+            Event::Checkpoint(state) => {
+                buf[0] = TAG_CHECKPOINT;
+                let mut at = 5;
+                at += encode_tracker(&state.et, &mut buf[at..]);
+                at += encode_light(&state.light, &mut buf[at..]);
+                encode_guards(&state.guards, &mut buf[at..]);
+                CHECKPOINT_LEN
+            }
+        }
+    }
+
+    /// Decodes the entry at the start of `buf` and returns its length.
+    pub fn decode(buf: &[u8]) -> Result<(Self, usize), DecodeError> {
+        let tag = *buf.first().ok_or(DecodeError::Truncated)?;
+        let len = encoded_len(tag).ok_or(DecodeError::UnknownTag(tag))?;
+        if buf.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+
+        let t_secs = read_u32(buf, 1);
+        let flags = buf[5];
+        let event = match tag {
+            TAG_SAMPLE => {
+                let field = |i: usize| read_f32(buf, 6 + i * 4);
+                Event::Sample(SensorData {
+                    temperature: (flags & HAS_TEMPERATURE != 0).then(|| field(0)),
+                    humidity: (flags & HAS_HUMIDITY != 0).then(|| field(1)),
+                    pressure: (flags & HAS_PRESSURE != 0).then(|| field(2)),
+                    soil_moisture: (flags & HAS_SOIL_MOISTURE != 0).then(|| field(3)),
+                    water_level: (flags & HAS_WATER_LEVEL != 0).then(|| field(4)),
+                    lux: (flags & HAS_LUX != 0).then(|| field(5)),
+                    // Derived, not recorded
+                    ..SensorData::default()
+                })
+            }
+            TAG_COMMAND => {
+                let (duration_secs, pulses, soak_secs) = decode_program(&buf[6..]);
+                Event::Command(PumpCommand {
+                    duration_secs,
+                    pulses,
+                    soak_secs,
+                    // Volume requests are logged once resolved to seconds
+                    volume_ml: 0,
+                    targeted: flags & TARGETED != 0,
+                    zone: buf[11],
+                })
+            }
+            TAG_PUMP => {
+                let (on_secs, pulses, soak_secs) = decode_program(&buf[5..]);
+                Event::Pump(Program {
+                    pulses,
+                    on_secs,
+                    soak_secs,
+                })
+            }
+            TAG_SKIP => Event::Skip(
+                *SkipReason::ALL
+                    .get(buf[5] as usize)
+                    .ok_or(DecodeError::UnknownReason(buf[5]))?,
+            ),
+            _ => {
+                let mut at = 5;
+                let (et, len) = decode_tracker(&buf[at..]);
+                at += len;
+                let (light, len) = decode_light(&buf[at..]);
+                at += len;
+                Event::Checkpoint(State {
+                    et,
+                    light,
+                    guards: decode_guards(&buf[at..]),
+                })
+            }
+        };
+
+        Ok((Entry { t_secs, event }, len))
+    }
+}
+
+fn encoded_len(tag: u8) -> Option<usize> {
+    match tag {
+        TAG_SAMPLE => Some(SAMPLE_LEN),
+        TAG_COMMAND => Some(COMMAND_LEN),
+        TAG_PUMP => Some(PUMP_LEN),
+        TAG_SKIP => Some(SKIP_LEN),
+        TAG_CHECKPOINT => Some(CHECKPOINT_LEN),
+        _ => None,
+    }
+}
+
+fn encode_program(on_secs: u16, pulses: u8, soak_secs: u16, buf: &mut [u8]) {
+    buf[0..2].copy_from_slice(&on_secs.to_le_bytes());
+    buf[2] = pulses;
+    buf[3..5].copy_from_slice(&soak_secs.to_le_bytes());
+}
+
+fn decode_program(buf: &[u8]) -> (u16, u8, u16) {
+    (
+        u16::from_le_bytes([buf[0], buf[1]]),
+        buf[2],
+        u16::from_le_bytes([buf[3], buf[4]]),
+    )
+}
+
+// Returns the number of bytes written
+fn encode_tracker(tracker: &EtTracker, buf: &mut [u8]) -> usize {
+    let mut flags = 0;
+    if tracker.window_start.is_some() {
+        flags |= HAS_WINDOW;
+    }
+    if tracker.last_day.is_some() {
+        flags |= HAS_LAST_DAY;
+    }
+    if tracker.day_of_year.is_some() {
+        flags |= HAS_DAY_OF_YEAR;
+    }
+    buf[0] = flags;
+    buf[1..5].copy_from_slice(&tracker.window_start.unwrap_or(0).to_le_bytes());
+    let (last_min, last_max) = tracker.last_day.unwrap_or((0.0, 0.0));
+    let fields = [tracker.t_min, tracker.t_max, last_min, last_max];
+    for (i, value) in fields.iter().enumerate() {
+        buf[5 + i * 4..9 + i * 4].copy_from_slice(&value.to_le_bytes());
+    }
+    buf[21..23].copy_from_slice(&tracker.day_of_year.unwrap_or(0).to_le_bytes());
+    23
+}
+
+fn decode_tracker(buf: &[u8]) -> (EtTracker, usize) {
+    let flags = buf[0];
+    let tracker = EtTracker {
+        window_start: (flags & HAS_WINDOW != 0).then(|| read_u32(buf, 1)),
+        t_min: read_f32(buf, 5),
+        t_max: read_f32(buf, 9),
+        last_day: (flags & HAS_LAST_DAY != 0).then(|| (read_f32(buf, 13), read_f32(buf, 17))),
+        day_of_year: (flags & HAS_DAY_OF_YEAR != 0).then(|| u16::from_le_bytes([buf[21], buf[22]])),
+        // Taken again from the next reading, as after the server sets the day
+        day_start: None,
+    };
+    (tracker, 23)
+}
+
+// Returns the number of bytes written
+fn encode_light(tracker: &LightTracker, buf: &mut [u8]) -> usize {
+    let mut flags = 0;
+    if tracker.window_start.is_some() {
+        flags |= HAS_LIGHT_WINDOW;
+    }
+    if tracker.last.is_some() {
+        flags |= HAS_LAST_READING;
+    }
+    if tracker.last_day.is_some() {
+        flags |= HAS_LAST_DLI;
+    }
+    buf[0] = flags;
+    buf[1..5].copy_from_slice(&tracker.window_start.unwrap_or(0).to_le_bytes());
+    let (last_t, last_lux) = tracker.last.unwrap_or((0, 0.0));
+    buf[5..9].copy_from_slice(&last_t.to_le_bytes());
+    buf[9..13].copy_from_slice(&last_lux.to_le_bytes());
+    buf[13..17].copy_from_slice(&tracker.today_mol.to_le_bytes());
+    buf[17..21].copy_from_slice(&tracker.covered_secs.to_le_bytes());
+    buf[21..25].copy_from_slice(&tracker.last_day.unwrap_or(0.0).to_le_bytes());
+    25
+}
+
+fn decode_light(buf: &[u8]) -> (LightTracker, usize) {
+    let flags = buf[0];
+    let tracker = LightTracker {
+        window_start: (flags & HAS_LIGHT_WINDOW != 0).then(|| read_u32(buf, 1)),
+        last: (flags & HAS_LAST_READING != 0).then(|| (read_u32(buf, 5), read_f32(buf, 9))),
+        today_mol: read_f32(buf, 13),
+        covered_secs: read_u32(buf, 17),
+        last_day: (flags & HAS_LAST_DLI != 0).then(|| read_f32(buf, 21)),
+    };
+    (tracker, 25)
+}
+
+fn encode_guards(guards: &Guards, buf: &mut [u8]) {
+    let times = [
+        (HAS_PRESSURE_SINCE, guards.pressure_since),
+        (HAS_HOT_UNTIL, guards.hot_until),
+        (HAS_RAIN_UNTIL, guards.rain_until),
+        (HAS_DAY_START, guards.day_start),
+    ];
+    let mut flags = 0;
+    for (bit, value) in times {
+        if value.is_some() {
+            flags |= bit;
+        }
+    }
+    buf[0] = flags;
+
+    let mut at = 1;
+    let readings = [guards.temperature, guards.humidity].into_iter();
+    for value in readings.chain(guards.pressure) {
+        buf[at..at + 4].copy_from_slice(&value.unwrap_or(f32::NAN).to_le_bytes());
+        at += 4;
+    }
+    for (_, value) in times {
+        buf[at..at + 4].copy_from_slice(&value.unwrap_or(0).to_le_bytes());
+        at += 4;
+    }
+    buf[at] = guards.runs_today;
+}
+
+fn decode_guards(buf: &[u8]) -> Guards {
+    let flags = buf[0];
+    let reading = |i: usize| Some(read_f32(buf, 1 + i * 4)).filter(|v| !v.is_nan());
+    let times = 1 + (2 + PRESSURE_HOURS) * 4;
+    let time = |bit: u8, i: usize| (flags & bit != 0).then(|| read_u32(buf, times + i * 4));
+    Guards {
+        temperature: reading(0),
+        humidity: reading(1),
+        pressure: core::array::from_fn(|i| reading(2 + i)),
+        pressure_since: time(HAS_PRESSURE_SINCE, 0),
+        hot_until: time(HAS_HOT_UNTIL, 1),
+        rain_until: time(HAS_RAIN_UNTIL, 2),
+        day_start: time(HAS_DAY_START, 3),
+        runs_today: buf[times + 4 * 4],
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    ])
+}
+
+fn read_f32(buf: &[u8], offset: usize) -> f32 {
+    f32::from_bits(read_u32(buf, offset))
+}
+
+/// Iterates the encoded entries of an export body; stops at the first
+/// error.
+pub struct Entries<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Entries<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<Entry, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.is_empty() {
+            return None;
+        }
+        match Entry::decode(self.buf) {
+            Ok((entry, len)) => {
+                self.buf = &self.buf[len..];
+                Some(Ok(entry))
+            }
+            Err(e) => {
+                self.buf = &[];
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Ring of encoded entries that drops the oldest ones when full.
+pub struct Log<const N: usize> {
+    bytes: Deque<u8, N>,
+}
+
+impl<const N: usize> Default for Log<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Log<N> {
+    pub const fn new() -> Self {
+        Self {
+            bytes: Deque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn push(&mut self, entry: &Entry) {
+        let mut buf = [0u8; MAX_ENTRY_LEN];
+        let len = entry.encode(&mut buf);
+
+        while N - self.bytes.len() < len {
+            let Some(&tag) = self.bytes.front() else {
+                return;
+            };
+            // Only ever holds entries we encoded, so the tag is known
+            for _ in 0..encoded_len(tag).unwrap_or(1) {
+                self.bytes.pop_front();
+            }
+        }
+        for &byte in &buf[..len] {
+            self.bytes.push_back(byte).ok();
+        }
+    }
+
+    /// Writes the header and all entries to `out`, returning the length;
+    /// `out` needs room for `HEADER_LEN + N` bytes.
+    pub fn export(&self, header: Header, out: &mut [u8]) -> usize {
+        out[..HEADER_LEN].copy_from_slice(&header.encode());
+        let mut len = HEADER_LEN;
+        let (front, back) = self.bytes.as_slices();
+        for part in [front, back] {
+            out[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        len
+    }
+}
+
+/// A recorded decision that the current logic would have made differently.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Mismatch {
+    pub t_secs: u32,
+    // What the logic decides now, `None` for no decision at all
+    pub expected: Option<Decision>,
+    pub recorded: Option<Decision>,
+}
+
+/// Feeds recorded entries back through the decision logic.
+pub struct Replay {
+    settings: Settings,
+    state: State,
+    // Command waiting for its outcome, decided once that shows up
+    pending: Option<(u32, PumpCommand)>,
+    // Outcomes can only be judged from the first checkpoint on, and once
+    // the ring has not dropped the command behind them
+    primed: bool,
+    seen_command: bool,
+}
+
+impl Replay {
+    pub fn new(header: Header) -> Self {
+        Self {
+            settings: header.settings,
+            state: State::new(),
+            pending: None,
+            primed: false,
+            seen_command: false,
+        }
+    }
+
+    pub fn feed(&mut self, entry: &Entry) -> Option<Mismatch> {
+        let recorded = match entry.event {
+            Event::Sample(data) => {
+                self.state.observe(entry.t_secs, &data, &self.settings);
+                return None;
+            }
+            Event::Checkpoint(state) => {
+                // Only the first is adopted whole; from there on the replay
+                // derives the state itself and takes just the date, which
+                // comes from outside
+                if !self.primed {
+                    self.state = state;
+                    self.primed = true;
+                } else if let Some(day) = state.et.day_of_year() {
+                    self.state.set_day_of_year(day);
+                }
+                return None;
+            }
+            Event::Command(cmd) => {
+                self.seen_command = true;
+                // A command replacing one that was never decided is itself
+                // a difference
+                return self
+                    .pending
+                    .replace((entry.t_secs, cmd))
+                    .map(|(t_secs, cmd)| self.undecided(t_secs, cmd));
+            }
+            Event::Pump(_) | Event::Skip(_) if !self.primed || !self.seen_command => {
+                return None;
+            }
+            Event::Pump(program) => Ok(program),
+            Event::Skip(reason) => Err(reason),
+        };
+
+        let expected = self
+            .pending
+            .take()
+            .map(|(_, cmd)| self.state.decide(entry.t_secs, cmd, &self.settings));
+        (expected != Some(recorded)).then_some(Mismatch {
+            t_secs: entry.t_secs,
+            expected,
+            recorded: Some(recorded),
+        })
+    }
+
+    /// Reports a command left undecided at the end of the log.
+    pub fn finish(self) -> Option<Mismatch> {
+        self.pending
+            .map(|(t_secs, cmd)| self.undecided(t_secs, cmd))
+    }
+
+    // Decides on a copy, as the device never counted this run
+    fn undecided(&self, t_secs: u32, cmd: PumpCommand) -> Mismatch {
+        let mut state = self.state;
+        Mismatch {
+            t_secs,
+            expected: Some(state.decide(t_secs, cmd, &self.settings)),
+            recorded: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: Settings = Settings {
+        pump_max_secs: 30,
+        latitude: 50.0,
+        crop_coefficient: 0.9,
+        reference_et_mm: 4.0,
+        sunny_dli: 45.0,
+        guards: GuardSettings {
+            frost_below: 2.0,
+            humid_above: 95.0,
+            heat_wave_above: 30.0,
+            runs_per_day: 4,
+            heat_wave_extra_runs: 2,
+            rain_drop: 3.0,
+            rain_delay_secs: 6 * 60 * 60,
+        },
+    };
+
+    const HOUR: u32 = 60 * 60;
+
+    fn sample(hour: u32) -> SensorData {
+        SensorData {
+            temperature: Some(15.0 + hour as f32 % 24.0 / 2.0),
+            humidity: Some(60.0),
+            pressure: Some(1013.0 - hour as f32 * 0.1),
+            soil_moisture: Some(45.5),
+            water_level: None,
+            lux: Some(if hour % 24 < 12 { 20_000.0 } else { 0.0 }),
+            ..SensorData::default()
+        }
+    }
+
+    // A state with every part filled in: a full day of ET and light, and
+    // the guards with a run counted
+    fn state() -> State {
+        let mut state = State::new();
+        state.set_day_of_year(152);
+        for hour in 0..30 {
+            state.observe(hour * HOUR, &sample(hour), &SETTINGS);
+        }
+        state
+            .decide(30 * HOUR, PumpCommand::single(20), &SETTINGS)
+            .unwrap();
+        state
+    }
+
+    fn round_trip(event: Event) -> Entry {
+        let entry = Entry {
+            t_secs: 123_456,
+            event,
+        };
+        let mut buf = [0; MAX_ENTRY_LEN];
+        let len = entry.encode(&mut buf);
+        let (decoded, decoded_len) = Entry::decode(&buf[..len]).unwrap();
+        assert_eq!(decoded_len, len);
+        assert_eq!(decoded.t_secs, 123_456);
+
+        // The decoded entry encodes to the same bytes
+        let mut again = [0; MAX_ENTRY_LEN];
+        assert_eq!(decoded.encode(&mut again), len);
+        assert_eq!(buf[..len], again[..len]);
+        decoded
+    }
+
+    #[test]
+    fn header_round_trips() {
+        let header = Header { settings: SETTINGS };
+        let mut buf = header.encode().to_vec();
+        buf.extend_from_slice(&[1, 2, 3]);
+        let (decoded, body) = Header::decode(&buf).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(body, [1, 2, 3]);
+    }
+
+    #[test]
+    fn header_rejects_other_exports() {
+        let header = Header { settings: SETTINGS }.encode();
+        assert_eq!(
+            Header::decode(&header[..HEADER_LEN - 1]),
+            Err(DecodeError::Truncated)
+        );
+
+        let mut bad = header;
+        bad[0] = b'X';
+        assert_eq!(Header::decode(&bad), Err(DecodeError::BadMagic));
+
+        let mut old = header;
+        old[4] = LOG_VERSION - 1;
+        assert_eq!(
+            Header::decode(&old),
+            Err(DecodeError::UnsupportedVersion(LOG_VERSION - 1))
+        );
+    }
+
+    #[test]
+    fn sample_keeps_the_measured_fields() {
+        let data = sample(3);
+        let Event::Sample(decoded) = round_trip(Event::Sample(data)).event else {
+            panic!("not a sample");
+        };
+        assert_eq!(decoded.temperature, data.temperature);
+        assert_eq!(decoded.humidity, data.humidity);
+        assert_eq!(decoded.pressure, data.pressure);
+        assert_eq!(decoded.soil_moisture, data.soil_moisture);
+        assert_eq!(decoded.water_level, None);
+        assert_eq!(decoded.lux, data.lux);
+
+        let Event::Sample(empty) = round_trip(Event::Sample(SensorData::default())).event else {
+            panic!("not a sample");
+        };
+        assert_eq!(empty.temperature, None);
+        assert_eq!(empty.lux, None);
+    }
+
+    #[test]
+    fn commands_runs_and_skips_round_trip() {
+        let cmd = PumpCommand {
+            duration_secs: 12,
+            pulses: 3,
+            soak_secs: 600,
+            volume_ml: 0,
+            targeted: true,
+            zone: 2,
+        };
+        let Event::Command(decoded) = round_trip(Event::Command(cmd)).event else {
+            panic!("not a command");
+        };
+        assert_eq!(
+            (
+                decoded.duration_secs,
+                decoded.pulses,
+                decoded.soak_secs,
+                decoded.zone
+            ),
+            (12, 3, 600, 2)
+        );
+        assert!(decoded.targeted);
+
+        let program = Program {
+            pulses: 2,
+            on_secs: 15,
+            soak_secs: 300,
+        };
+        assert!(matches!(
+            round_trip(Event::Pump(program)).event,
+            Event::Pump(decoded) if decoded == program
+        ));
+
+        for reason in SkipReason::ALL {
+            assert!(matches!(
+                round_trip(Event::Skip(reason)).event,
+                Event::Skip(decoded) if decoded == reason
+            ));
+        }
+    }
+
+    #[test]
+    fn checkpoint_restores_the_state() {
+        let state = state();
+        let Event::Checkpoint(decoded) = round_trip(Event::Checkpoint(state)).event else {
+            panic!("not a checkpoint");
+        };
+        // Set to 152 a day of uptime earlier
+        assert_eq!(decoded.et.day_of_year(), Some(153));
+        assert_eq!(decoded.et0_mm(&SETTINGS), state.et0_mm(&SETTINGS));
+        assert_eq!(decoded.light.dli(), state.light.dli());
+        assert_eq!(decoded.light.today(), state.light.today());
+        assert_eq!(decoded.guards, state.guards);
+
+        // Both decide the next run alike
+        let (mut a, mut b) = (state, decoded);
+        let cmd = PumpCommand::single(20);
+        assert_eq!(
+            a.decide(31 * HOUR, cmd, &SETTINGS),
+            b.decide(31 * HOUR, cmd, &SETTINGS)
+        );
+    }
+
+    #[test]
+    fn light_tracker_round_trips() {
+        let light = state().light;
+        let mut buf = [0; 25];
+        assert_eq!(encode_light(&light, &mut buf), 25);
+        let (decoded, len) = decode_light(&buf);
+        assert_eq!(len, 25);
+        assert_eq!(decoded.window_start, light.window_start);
+        assert_eq!(decoded.last, light.last);
+        assert_eq!(decoded.today_mol, light.today_mol);
+        assert_eq!(decoded.covered_secs, light.covered_secs);
+        assert_eq!(decoded.last_day, light.last_day);
+
+        let (empty, _) = decode_light(&{
+            let mut buf = [0; 25];
+            encode_light(&LightTracker::new(), &mut buf);
+            buf
+        });
+        assert_eq!(empty.window_start, None);
+        assert_eq!(empty.last, None);
+        assert_eq!(empty.last_day, None);
+    }
+
+    #[test]
+    fn unknown_tags_and_short_entries_are_errors() {
+        assert_eq!(Entry::decode(&[]).err(), Some(DecodeError::Truncated));
+        assert_eq!(
+            Entry::decode(&[9, 0, 0]).err(),
+            Some(DecodeError::UnknownTag(9))
+        );
+        assert_eq!(
+            Entry::decode(&[TAG_SAMPLE, 0, 0]).err(),
+            Some(DecodeError::Truncated)
+        );
+        assert_eq!(
+            Entry::decode(&[TAG_SKIP, 0, 0, 0, 0, 9]).err(),
+            Some(DecodeError::UnknownReason(9))
+        );
+    }
+
+    #[test]
+    fn full_log_drops_the_oldest_entries_whole() {
+        // Room for two samples and a bit
+        let mut log = Log::<{ 2 * SAMPLE_LEN + 5 }>::new();
+        for hour in 0..3 {
+            log.push(&Entry {
+                t_secs: hour * HOUR,
+                event: Event::Sample(sample(hour)),
+            });
+        }
+        assert_eq!(log.len(), 2 * SAMPLE_LEN);
+
+        let mut out = [0; HEADER_LEN + 2 * SAMPLE_LEN + 5];
+        let len = log.export(Header { settings: SETTINGS }, &mut out);
+        let (_, body) = Header::decode(&out[..len]).unwrap();
+        let times: Vec<u32> = Entries::new(body).map(|e| e.unwrap().t_secs).collect();
+        assert_eq!(times, [HOUR, 2 * HOUR]);
+    }
+
+    // The entries a device with `state` would log for `cmd` at `t_secs`
+    fn decided(state: &mut State, t_secs: u32, cmd: PumpCommand) -> [Entry; 2] {
+        let outcome = match state.decide(t_secs, cmd, &SETTINGS) {
+            Ok(program) => Event::Pump(program),
+            Err(reason) => Event::Skip(reason),
+        };
+        [
+            Entry {
+                t_secs,
+                event: Event::Command(cmd),
+            },
+            Entry {
+                t_secs,
+                event: outcome,
+            },
+        ]
+    }
+
+    #[test]
+    fn replay_agrees_with_the_device() {
+        let mut device = state();
+        let mut replay = Replay::new(Header { settings: SETTINGS });
+        assert!(
+            replay
+                .feed(&Entry {
+                    t_secs: 30 * HOUR,
+                    event: Event::Checkpoint(device),
+                })
+                .is_none()
+        );
+
+        for hour in 31..40 {
+            let entry = Entry {
+                t_secs: hour * HOUR,
+                event: Event::Sample(sample(hour)),
+            };
+            device.observe(entry.t_secs, &sample(hour), &SETTINGS);
+            assert!(replay.feed(&entry).is_none());
+            for entry in decided(&mut device, hour * HOUR, PumpCommand::single(20)) {
+                assert_eq!(replay.feed(&entry), None, "at {hour} h");
+            }
+        }
+        assert_eq!(replay.finish(), None);
+    }
+
+    #[test]
+    fn replay_reports_a_different_outcome() {
+        let mut device = state();
+        let mut replay = Replay::new(Header { settings: SETTINGS });
+        replay.feed(&Entry {
+            t_secs: 30 * HOUR,
+            event: Event::Checkpoint(device),
+        });
+
+        let [command, outcome] = decided(&mut device, 31 * HOUR, PumpCommand::single(20));
+        let Event::Pump(program) = outcome.event else {
+            panic!("run skipped");
+        };
+        let tampered = Program {
+            on_secs: program.on_secs + 1,
+            ..program
+        };
+        replay.feed(&command);
+        let mismatch = replay
+            .feed(&Entry {
+                t_secs: 31 * HOUR,
+                event: Event::Pump(tampered),
+            })
+            .unwrap();
+        assert_eq!(mismatch.expected, Some(Ok(program)));
+        assert_eq!(mismatch.recorded, Some(Ok(tampered)));
+    }
+
+    #[test]
+    fn replay_reports_undecided_commands() {
+        let mut replay = Replay::new(Header { settings: SETTINGS });
+        replay.feed(&Entry {
+            t_secs: 30 * HOUR,
+            event: Event::Checkpoint(state()),
+        });
+        let command = |t_secs| Entry {
+            t_secs,
+            event: Event::Command(PumpCommand::single(20)),
+        };
+
+        // A second command before the first was decided
+        assert!(replay.feed(&command(31 * HOUR)).is_none());
+        let mismatch = replay.feed(&command(32 * HOUR)).unwrap();
+        assert_eq!(mismatch.t_secs, 31 * HOUR);
+        assert_eq!(mismatch.recorded, None);
+
+        // And one left at the end of the log
+        let mismatch = replay.finish().unwrap();
+        assert_eq!(mismatch.t_secs, 32 * HOUR);
+        assert!(matches!(mismatch.expected, Some(Ok(_))));
+    }
+
+    #[test]
+    fn replay_waits_for_a_checkpoint() {
+        let mut replay = Replay::new(Header { settings: SETTINGS });
+        let outcome = Entry {
+            t_secs: HOUR,
+            event: Event::Skip(SkipReason::Frost),
+        };
+        // Without a state to decide from, outcomes are not judged
+        assert!(replay.feed(&outcome).is_none());
+    }
+}
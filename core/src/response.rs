@@ -0,0 +1,295 @@
+//! Checks that the soil moisture rises after each pump run, and learns by
+//! how much.
+//!
+//! The rise is sampled at [`CURVE_POINTS`] even steps over the response
+//! window and divided by the run's on-time, so runs of any length add to
+//! the same curve. Worked example with a 30 min window and a learned
+//! 0.2 %/s at its end: a 20 s run is expected to raise the moisture by 4 %,
+//! and a rise below 1 % (a quarter of that) is no response.
+
+use serde::Serialize;
+
+pub const CURVE_POINTS: usize = 6;
+
+// How much of the expected rise still counts as a response
+const MISSING_FRACTION: f32 = 0.25;
+// Weight of each new run in the learned curve
+const LEARN_RATE: f32 = 0.3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ResponseSettings {
+    // How long after a run the moisture gets to rise
+    pub window_secs: u32,
+    // Shorter runs are not judged
+    pub min_on_secs: u32,
+    // Less rise than this is no response until a curve has been learned, %
+    pub min_rise_pct: f32,
+    // Soil this wet before the run may not rise at all, %
+    pub saturated_pct: f32,
+}
+
+/// Learned moisture rise, % per second of watering, at 1/6 to 6/6 of the
+/// response window.
+pub type Curve = [f32; CURVE_POINTS];
+
+/// The result of watching one run.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct Check {
+    pub on_secs: u32,
+    pub rise_pct: f32,
+    // From the learned curve; None while nothing has been learned
+    pub expected_pct: Option<f32>,
+    pub responded: bool,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Pending {
+    start_secs: u32,
+    on_secs: u32,
+    before: f32,
+    // Highest rise seen up to each point of the curve
+    rise: Curve,
+}
+
+/// Fed with every moisture reading and told about every run.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ResponseTracker {
+    moisture: Option<f32>,
+    pending: Option<Pending>,
+    curve: Option<Curve>,
+}
+
+impl ResponseTracker {
+    pub const fn new() -> Self {
+        Self {
+            moisture: None,
+            pending: None,
+            curve: None,
+        }
+    }
+
+    pub fn curve(&self) -> Option<Curve> {
+        self.curve
+    }
+
+    /// Starts watching a run that ended at `t_secs`, measured from the last
+    /// reading. A run while another is watched replaces it unjudged.
+    pub fn watered(&mut self, t_secs: u32, on_secs: u32, settings: &ResponseSettings) {
+        self.pending = self
+            .moisture
+            .filter(|&before| on_secs >= settings.min_on_secs && before < settings.saturated_pct)
+            .map(|before| Pending {
+                start_secs: t_secs,
+                on_secs,
+                before,
+                rise: [0.0; CURVE_POINTS],
+            });
+    }
+
+    /// Takes a reading; returns the check once the window of a watched run
+    /// is over.
+    pub fn observe(
+        &mut self,
+        t_secs: u32,
+        moisture: f32,
+        settings: &ResponseSettings,
+    ) -> Option<Check> {
+        self.moisture = Some(moisture);
+        let pending = self.pending.as_mut()?;
+
+        let elapsed = t_secs.saturating_sub(pending.start_secs);
+        // The first point ends at 1/6 of the window, and covers all before
+        let point = (elapsed as u64 * CURVE_POINTS as u64)
+            .div_ceil(settings.window_secs.max(1) as u64)
+            .clamp(1, CURVE_POINTS as u64) as usize
+            - 1;
+        // A later point has seen at least what an earlier one did
+        let rise = moisture - pending.before;
+        for r in &mut pending.rise[point..] {
+            *r = r.max(rise);
+        }
+        if elapsed < settings.window_secs {
+            return None;
+        }
+
+        let pending = self.pending.take()?;
+        let rise_pct = pending.rise[CURVE_POINTS - 1];
+        let expected_pct = self
+            .curve
+            .map(|curve| curve[CURVE_POINTS - 1] * pending.on_secs as f32);
+        let threshold = expected_pct.map_or(settings.min_rise_pct, |e| e * MISSING_FRACTION);
+        let responded = rise_pct >= threshold;
+
+        if responded {
+            let per_sec = pending.rise.map(|r| r / pending.on_secs as f32);
+            self.curve = Some(match self.curve {
+                Some(curve) => {
+                    let mut learned = curve;
+                    for (l, new) in learned.iter_mut().zip(per_sec) {
+                        *l += (new - *l) * LEARN_RATE;
+                    }
+                    learned
+                }
+                None => per_sec,
+            });
+        }
+
+        Some(Check {
+            on_secs: pending.on_secs,
+            rise_pct,
+            expected_pct,
+            responded,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: ResponseSettings = ResponseSettings {
+        window_secs: 1800,
+        min_on_secs: 5,
+        min_rise_pct: 2.0,
+        saturated_pct: 80.0,
+    };
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    // A run of `on_secs` ending at `t_secs`, then readings every 5 min
+    // rising linearly to `before + rise` at the end of the window
+    fn trace(
+        tracker: &mut ResponseTracker,
+        t_secs: u32,
+        on_secs: u32,
+        before: f32,
+        rise: f32,
+    ) -> Option<Check> {
+        tracker.observe(t_secs, before, &SETTINGS);
+        tracker.watered(t_secs, on_secs, &SETTINGS);
+        let mut check = None;
+        for step in 1..=6 {
+            let moisture = before + rise * step as f32 / 6.0;
+            check = tracker.observe(t_secs + step * 300, moisture, &SETTINGS);
+            if step < 6 {
+                assert_eq!(check, None, "judged early at step {step}");
+            }
+        }
+        check
+    }
+
+    #[test]
+    fn worked_example() {
+        let mut tracker = ResponseTracker::new();
+        // 0.2 %/s is learned from a 20 s run rising 4 %
+        let check = trace(&mut tracker, 0, 20, 30.0, 4.0).unwrap();
+        assert!(check.responded);
+        assert_eq!(check.expected_pct, None);
+        assert_near(tracker.curve().unwrap()[CURVE_POINTS - 1], 0.2);
+
+        // Then 4 % is expected, and under 1 % is no response
+        let check = trace(&mut tracker, 10_000, 20, 30.0, 0.9).unwrap();
+        assert_near(check.expected_pct.unwrap(), 4.0);
+        assert!(!check.responded);
+        let check = trace(&mut tracker, 20_000, 20, 30.0, 1.1).unwrap();
+        assert!(check.responded);
+    }
+
+    #[test]
+    fn the_first_run_is_judged_against_the_minimum_rise() {
+        let mut tracker = ResponseTracker::new();
+        let check = trace(&mut tracker, 0, 20, 30.0, 1.5).unwrap();
+        assert_eq!(
+            check,
+            Check {
+                on_secs: 20,
+                rise_pct: 1.5,
+                expected_pct: None,
+                responded: false,
+            }
+        );
+        // Nothing is learned from a run that did not respond
+        assert_eq!(tracker.curve(), None);
+    }
+
+    #[test]
+    fn the_curve_follows_the_rise_through_the_window() {
+        let mut tracker = ResponseTracker::new();
+        trace(&mut tracker, 0, 10, 30.0, 6.0).unwrap();
+        let curve = tracker.curve().unwrap();
+        for (point, learned) in curve.iter().enumerate() {
+            assert_near(*learned, 0.1 * (point + 1) as f32);
+        }
+    }
+
+    #[test]
+    fn each_point_keeps_the_highest_rise_so_far() {
+        // The moisture peaks at 1/3 of the window and drains away
+        let mut tracker = ResponseTracker::new();
+        tracker.observe(0, 30.0, &SETTINGS);
+        tracker.watered(0, 10, &SETTINGS);
+        let mut check = None;
+        for (step, moisture) in [33.0, 35.0, 34.0, 33.0, 32.0, 31.0].into_iter().enumerate() {
+            check = tracker.observe((step as u32 + 1) * 300, moisture, &SETTINGS);
+        }
+        assert_near(check.unwrap().rise_pct, 5.0);
+        let curve = tracker.curve().unwrap();
+        assert_near(curve[0], 0.3);
+        for learned in &curve[1..] {
+            assert_near(*learned, 0.5);
+        }
+    }
+
+    #[test]
+    fn later_runs_blend_into_the_curve() {
+        let mut tracker = ResponseTracker::new();
+        trace(&mut tracker, 0, 20, 30.0, 4.0).unwrap();
+        // 0.3 %/s moves the learned 0.2 %/s by 0.3 of the difference
+        trace(&mut tracker, 10_000, 20, 30.0, 6.0).unwrap();
+        assert_near(tracker.curve().unwrap()[CURVE_POINTS - 1], 0.23);
+    }
+
+    #[test]
+    fn a_dry_run_keeps_the_curve() {
+        let mut tracker = ResponseTracker::new();
+        trace(&mut tracker, 0, 20, 30.0, 4.0).unwrap();
+        let learned = tracker.curve();
+        let check = trace(&mut tracker, 10_000, 20, 30.0, 0.0).unwrap();
+        assert!(!check.responded);
+        assert_eq!(tracker.curve(), learned);
+    }
+
+    #[test]
+    fn short_runs_and_saturated_soil_are_not_judged() {
+        let mut tracker = ResponseTracker::new();
+        assert_eq!(trace(&mut tracker, 0, 4, 30.0, 0.0), None);
+        assert_eq!(trace(&mut tracker, 10_000, 20, 85.0, 0.0), None);
+    }
+
+    #[test]
+    fn a_run_before_any_reading_is_not_judged() {
+        let mut tracker = ResponseTracker::new();
+        tracker.watered(0, 20, &SETTINGS);
+        assert_eq!(tracker.observe(1800, 30.0, &SETTINGS), None);
+        assert_eq!(tracker.observe(3600, 30.0, &SETTINGS), None);
+    }
+
+    #[test]
+    fn a_new_run_replaces_the_watched_one() {
+        let mut tracker = ResponseTracker::new();
+        tracker.observe(0, 30.0, &SETTINGS);
+        tracker.watered(0, 20, &SETTINGS);
+        tracker.observe(900, 31.0, &SETTINGS);
+        tracker.watered(900, 10, &SETTINGS);
+        // The first run's window is over but only the second is watched
+        assert_eq!(tracker.observe(1800, 34.0, &SETTINGS), None);
+        let check = tracker.observe(2700, 34.0, &SETTINGS).unwrap();
+        assert_eq!(check.on_secs, 10);
+        assert_near(check.rise_pct, 3.0);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::soil;
+use crate::sonar;
+use crate::telemetry::Telemetry;
+use crate::traits::{EnvSensor, RangeSensor, SensorDriver, SoilProbe};
+use crate::types::SensorData;
+
+/// Takes one reading from every sensor.
+///
+/// Each sensor that fails leaves only its own fields empty. The sonar
+/// distance is compensated with the air readings of the same sample.
+/// Plausibility checks, filtering the soil moisture and derived metrics are
+/// left to the caller; both moisture fields hold the unfiltered reading.
+pub async fn sample<E, S, R>(env: &mut E, soil: &mut S, range: &mut R) -> SensorData
+where
+    E: EnvSensor,
+    S: SoilProbe,
+    R: RangeSensor,
+{
+    let env = env.read().await.unwrap_or_default();
+    let soil_moisture = soil.read_raw().await.ok().map(soil::moisture_percent);
+    let water_level = range
+        .echo_us()
+        .await
+        .ok()
+        .map(|echo_us| sonar::distance_cm(echo_us, env.temperature, env.humidity));
+
+    SensorData {
+        temperature: env.temperature,
+        humidity: env.humidity,
+        pressure: env.pressure,
+        soil_moisture,
+        soil_moisture_raw: soil_moisture,
+        water_level,
+        ..SensorData::default()
+    }
+}
+
+/// Reads every driver found at boot; one that fails leaves only its own
+/// measurements out.
+pub async fn read_drivers<D: SensorDriver>(drivers: &mut [D]) -> Telemetry {
+    let mut telemetry = Telemetry::new();
+    for driver in drivers {
+        // The driver reports its own errors
+        driver.read(&mut telemetry).await.ok();
+    }
+    telemetry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::block_on;
+    use crate::traits::EnvReading;
+
+    struct Env(Option<EnvReading>);
+
+    impl EnvSensor for Env {
+        type Error = ();
+
+        async fn read(&mut self) -> Result<EnvReading, ()> {
+            self.0.ok_or(())
+        }
+    }
+
+    struct Soil(Option<u16>);
+
+    impl SoilProbe for Soil {
+        type Error = ();
+
+        async fn read_raw(&mut self) -> Result<u16, ()> {
+            self.0.ok_or(())
+        }
+    }
+
+    struct Range(Option<f32>);
+
+    impl RangeSensor for Range {
+        type Error = ();
+
+        async fn echo_us(&mut self) -> Result<f32, ()> {
+            self.0.ok_or(())
+        }
+    }
+
+    const AIR: EnvReading = EnvReading {
+        temperature: Some(20.0),
+        humidity: Some(0.0),
+        pressure: Some(1013.0),
+    };
+
+    #[test]
+    fn failed_soil_read_keeps_the_rest() {
+        let data = block_on(sample(
+            &mut Env(Some(AIR)),
+            &mut Soil(None),
+            &mut Range(Some(1457.0)),
+        ));
+        assert_eq!(data.temperature, Some(20.0));
+        assert_eq!(data.humidity, Some(0.0));
+        assert_eq!(data.pressure, Some(1013.0));
+        assert_eq!(data.soil_moisture, None);
+        assert_eq!(data.soil_moisture_raw, None);
+        let level = data.water_level.unwrap();
+        assert!((level - 25.0).abs() < 0.05, "{level}");
+    }
+
+    #[test]
+    fn failed_env_read_keeps_the_rest() {
+        let data = block_on(sample(
+            &mut Env(None),
+            &mut Soil(Some(soil::SOIL_WET)),
+            &mut Range(None),
+        ));
+        assert_eq!(data.temperature, None);
+        assert_eq!(data.pressure, None);
+        assert_eq!(data.soil_moisture, Some(100.0));
+        assert_eq!(data.soil_moisture_raw, Some(100.0));
+        assert_eq!(data.water_level, None);
+    }
+}
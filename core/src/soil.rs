@@ -0,0 +1,111 @@
+pub const SOIL_DRY: u16 = 3550; // air = 0% moisture
+pub const SOIL_WET: u16 = 150; // water = 100% moisture
+
+/// Maps a raw capacitive probe reading to 0-100 % moisture.
+pub fn moisture_percent(raw: u16) -> f32 {
+    let clamped = raw.clamp(SOIL_WET, SOIL_DRY);
+    ((SOIL_DRY - clamped) as f32 / (SOIL_DRY - SOIL_WET) as f32) * 100.0
+}
+
+/// Mean of `samples` with the `trim` lowest and highest left out, so a few
+/// spikes do not move it; `None` if nothing is left. Sorts `samples`.
+///
+/// Worked example: 1990, 2000, 2010, 2020 and a spike of 3500, trimmed by
+/// one each side, give 2010.
+pub fn trimmed_mean(samples: &mut [u16], trim: usize) -> Option<u16> {
+    samples.sort_unstable();
+    let kept = samples.get(trim..samples.len().checked_sub(trim)?)?;
+    if kept.is_empty() {
+        return None;
+    }
+    let sum: u32 = kept.iter().map(|&s| s as u32).sum();
+    Some((sum / kept.len() as u32) as u16)
+}
+
+/// Exponential moving average of the moisture.
+///
+/// Each reading moves the average `alpha` of the way towards it: 1 passes
+/// readings through, smaller values smooth more. With `alpha` 0.3, a step
+/// from 40 % to 50 % reads 43 %, 45.1 % and 46.6 % over the next three
+/// readings.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ema {
+    value: Option<f32>,
+}
+
+impl Ema {
+    pub const fn new() -> Self {
+        Self { value: None }
+    }
+
+    /// Takes a reading and returns the new average; the first reading is
+    /// taken as it is.
+    pub fn update(&mut self, reading: f32, alpha: f32) -> f32 {
+        let value = match self.value {
+            Some(value) => value + (reading - value) * alpha.clamp(0.0, 1.0),
+            None => reading,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn moisture_spans_the_calibration() {
+        assert_near(moisture_percent(SOIL_DRY), 0.0);
+        assert_near(moisture_percent(SOIL_WET), 100.0);
+        assert_near(moisture_percent(1850), 50.0);
+        // Beyond the ends is clamped
+        assert_near(moisture_percent(4095), 0.0);
+        assert_near(moisture_percent(0), 100.0);
+    }
+
+    #[test]
+    fn trimmed_mean_worked_example() {
+        let mut samples = [2020, 3500, 1990, 2010, 2000];
+        assert_eq!(trimmed_mean(&mut samples, 1), Some(2010));
+        assert_eq!(samples, [1990, 2000, 2010, 2020, 3500]);
+    }
+
+    #[test]
+    fn trimmed_mean_edges() {
+        assert_eq!(trimmed_mean(&mut [2000, 2003], 0), Some(2001));
+        assert_eq!(trimmed_mean(&mut [0, 4095, 2000], 1), Some(2000));
+        // Nothing left after trimming
+        assert_eq!(trimmed_mean(&mut [1, 2, 3, 4], 2), None);
+        assert_eq!(trimmed_mean(&mut [1, 2, 3], 2), None);
+        assert_eq!(trimmed_mean(&mut [], 0), None);
+        // Large readings do not overflow the sum
+        assert_eq!(trimmed_mean(&mut [u16::MAX; 16], 4), Some(u16::MAX));
+    }
+
+    #[test]
+    fn ema_worked_example() {
+        let mut ema = Ema::new();
+        assert_eq!(ema.update(40.0, 0.3), 40.0);
+        assert_near(ema.update(50.0, 0.3), 43.0);
+        assert_near(ema.update(50.0, 0.3), 45.1);
+        assert_near(ema.update(50.0, 0.3), 46.57);
+    }
+
+    #[test]
+    fn ema_alpha_is_clamped() {
+        let mut ema = Ema::new();
+        ema.update(40.0, 1.0);
+        assert_eq!(ema.update(50.0, 1.0), 50.0);
+        assert_eq!(ema.update(60.0, 2.0), 60.0);
+        assert_eq!(ema.update(70.0, 0.0), 60.0);
+        assert_eq!(ema.update(70.0, -1.0), 60.0);
+    }
+}
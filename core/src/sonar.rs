@@ -0,0 +1,103 @@
+//! Ultrasonic distance from the echo time, with the speed of sound taken
+//! from the air temperature and humidity.
+//!
+//! Reference values: sound travels at 331.4 m/s in dry air at 0 °C and
+//! 343.5 m/s at 20 °C, the speed behind the usual 58 µs per cm. An echo of
+//! 1457 µs is 25.0 cm at 20 °C, but 24.1 cm at 0 °C and 25.9 cm at 40 °C.
+
+// Speed of sound in dry air at 0 °C, m/s, and its rise per °C and per %
+// relative humidity
+const SPEED_AT_ZERO: f32 = 331.4;
+const SPEED_PER_DEGREE: f32 = 0.606;
+const SPEED_PER_HUMIDITY_PCT: f32 = 0.0124;
+
+/// Air temperature assumed while none is known, °C.
+pub const DEFAULT_TEMPERATURE_C: f32 = 20.0;
+
+// Readings outside what the BME280 can measure are not believed
+const MIN_TEMPERATURE_C: f32 = -40.0;
+const MAX_TEMPERATURE_C: f32 = 85.0;
+
+/// Speed of sound in m/s; dry air if the humidity is not known.
+pub fn speed_of_sound(temperature: f32, humidity: Option<f32>) -> f32 {
+    SPEED_AT_ZERO
+        + SPEED_PER_DEGREE * temperature
+        + SPEED_PER_HUMIDITY_PCT * humidity.unwrap_or(0.0).clamp(0.0, 100.0)
+}
+
+fn speed_in(temperature: Option<f32>, humidity: Option<f32>) -> f32 {
+    let temperature = temperature
+        .filter(|t| (MIN_TEMPERATURE_C..=MAX_TEMPERATURE_C).contains(t))
+        .unwrap_or(DEFAULT_TEMPERATURE_C);
+    speed_of_sound(temperature, humidity)
+}
+
+/// Distance to the reflecting surface for a round-trip `echo_us`.
+pub fn distance_cm(echo_us: f32, temperature: Option<f32>, humidity: Option<f32>) -> f32 {
+    let speed = speed_in(temperature, humidity);
+    // m/s is 1e-4 cm/µs, and the sound travels there and back
+    echo_us * speed * 1e-4 / 2.0
+}
+
+/// The round-trip echo time for `distance_cm`, the inverse of
+/// [`distance_cm`].
+pub fn echo_us(distance_cm: f32, temperature: Option<f32>, humidity: Option<f32>) -> f32 {
+    let speed = speed_in(temperature, humidity);
+    distance_cm * 2.0 / (speed * 1e-4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() < tolerance,
+            "{actual} is not {expected}"
+        );
+    }
+
+    #[test]
+    fn speed_of_sound_reference_values() {
+        assert_near(speed_of_sound(0.0, None), 331.4, 0.01);
+        assert_near(speed_of_sound(20.0, None), 343.5, 0.1);
+        // Humid air is a little faster, and the humidity is clamped
+        assert_near(speed_of_sound(20.0, Some(100.0)), 344.76, 0.01);
+        assert_eq!(
+            speed_of_sound(20.0, Some(150.0)),
+            speed_of_sound(20.0, Some(100.0))
+        );
+        assert_eq!(speed_of_sound(20.0, Some(-5.0)), speed_of_sound(20.0, None));
+    }
+
+    #[test]
+    fn worked_example() {
+        assert_near(distance_cm(1457.0, Some(20.0), None), 25.0, 0.05);
+        assert_near(distance_cm(1457.0, Some(0.0), None), 24.1, 0.05);
+        assert_near(distance_cm(1457.0, Some(40.0), None), 25.9, 0.05);
+        // About 58 µs per cm at 20 °C
+        assert_near(echo_us(1.0, Some(20.0), None), 58.2, 0.1);
+    }
+
+    #[test]
+    fn unknown_or_implausible_air_is_20_degrees() {
+        let at_20 = distance_cm(1457.0, Some(20.0), None);
+        assert_eq!(distance_cm(1457.0, None, None), at_20);
+        assert_eq!(distance_cm(1457.0, Some(-60.0), None), at_20);
+        assert_eq!(distance_cm(1457.0, Some(120.0), None), at_20);
+        assert_ne!(distance_cm(1457.0, Some(85.0), None), at_20);
+    }
+
+    #[test]
+    fn echo_time_inverts_distance() {
+        for (cm, temperature, humidity) in [
+            (2.0, Some(-10.0), None),
+            (25.0, Some(20.0), Some(50.0)),
+            (400.0, Some(35.0), Some(90.0)),
+            (120.0, None, Some(30.0)),
+        ] {
+            let echo = echo_us(cm, temperature, humidity);
+            assert_near(distance_cm(echo, temperature, humidity), cm, cm * 1e-5);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+//! Everything a station makes of a reading, in the order it does it.
+//!
+//! The firmware and the simulator both put every sample through a
+//! [`Station`], so a simulated run takes the decisions the device would.
+//! Logging, reporting and storing are left to the caller: the station only
+//! says what came of a reading in an [`Observed`].
+
+use crate::forecast::{ForecastSettings, Trend};
+use crate::light::{self, Dawn, DawnSettings};
+use crate::psychro;
+use crate::quality::{Checker, QualitySettings};
+use crate::response::{Check, ResponseSettings, ResponseTracker};
+use crate::soil::Ema;
+use crate::tank::{Refill, TankSettings, TankStatus, TankTracker};
+use crate::target::{TargetController, TargetSettings};
+use crate::types::{PumpCommand, SensorData};
+use crate::vacation::{self, Plan, Zone};
+use crate::watering::{Decision, Settings, State};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct StationSettings {
+    pub watering: Settings,
+    pub quality: QualitySettings,
+    // Weight of each reading in the soil moisture average, see `soil::Ema`
+    pub soil_ema_alpha: f32,
+    pub altitude_m: f32,
+    pub response: ResponseSettings,
+    pub target: TargetSettings,
+    pub tank: TankSettings,
+    pub forecast: ForecastSettings,
+    pub dawn: DawnSettings,
+    // Outside target mode, soil below this is dry: it gets a dawn run and
+    // is what the forecast counts down to, %
+    pub dry_below: f32,
+    // Water left in the tank at the end of a vacation, l
+    pub vacation_reserve_l: f32,
+    // What the vacation budget is shared between; runs without a zone are
+    // the first's
+    pub vacation_zones: &'static [Zone],
+}
+
+/// What came of a reading, for the caller to act on.
+#[derive(Clone, Copy, Default)]
+pub struct Observed {
+    // The moisture check of a run, once its window is over
+    pub check: Option<Check>,
+    // The check moved the target gain
+    pub gain_learned: bool,
+    pub refill: Option<Refill>,
+    pub tank: TankStatus,
+    pub dawn: bool,
+    // A run the station wants on its own: below the target band, or dry
+    // soil at dawn. Not cut to the vacation budget yet, see
+    // [`Station::scale`]
+    pub command: Option<PumpCommand>,
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Station {
+    pub checker: Checker,
+    pub soil_filter: Ema,
+    pub state: State,
+    pub response: ResponseTracker,
+    pub targeting: TargetController,
+    pub dawn: Dawn,
+    pub tank: TankTracker,
+    pub trend: Trend,
+    pub vacation_until: Option<u16>,
+}
+
+impl Station {
+    pub const fn new() -> Self {
+        Self {
+            checker: Checker::new(),
+            soil_filter: Ema::new(),
+            state: State::new(),
+            response: ResponseTracker::new(),
+            targeting: TargetController::new(),
+            dawn: Dawn::new(),
+            tank: TankTracker::new(),
+            trend: Trend::new(),
+            vacation_until: None,
+        }
+    }
+
+    /// Checks and filters a fresh sample and derives the air metrics; the
+    /// result is what the record log keeps.
+    pub fn refine(&mut self, t_secs: u32, data: &mut SensorData, settings: &StationSettings) {
+        data.lux = light::lux(&data.telemetry);
+        self.checker.check(t_secs, data, &settings.quality);
+        data.soil_moisture = data
+            .soil_moisture_raw
+            .map(|moisture| self.soil_filter.update(moisture, settings.soil_ema_alpha));
+        psychro::derive(data, settings.altitude_m);
+    }
+
+    /// Folds a refined sample into every tracker and fills in what they make
+    /// of it.
+    pub fn observe(
+        &mut self,
+        t_secs: u32,
+        data: &mut SensorData,
+        settings: &StationSettings,
+    ) -> Observed {
+        self.state.observe(t_secs, data, &settings.watering);
+        data.et0 = self.state.et0_mm(&settings.watering);
+        data.dli_today = self.state.light.today();
+        data.dli = self.state.light.dli();
+
+        let check = data
+            .soil_moisture
+            .and_then(|moisture| self.response.observe(t_secs, moisture, &settings.response));
+        data.moisture_response = self.response.curve();
+        let gain_learned =
+            check.is_some_and(|check| self.targeting.learn(&check, &settings.target));
+
+        let refill = self.tank.observe(t_secs, data.water_level, &settings.tank);
+        let tank = self.tank.status(&settings.tank);
+        data.tank_l = tank.volume_l;
+        data.tank_daily_use_l = tank.daily_use_l;
+        data.tank_days_remaining = tank.days_remaining;
+
+        if let Some(moisture) = data.soil_moisture {
+            self.trend.observe(t_secs, moisture, &settings.forecast);
+        }
+        data.hours_until_dry = self
+            .trend
+            .hours_until(self.dry_below(settings), &settings.forecast);
+
+        let dawn = self.dawn.observe(t_secs, data.lux, &settings.dawn);
+        let command = data.soil_moisture.and_then(|moisture| {
+            if self.targeting.band().is_some() {
+                return self.targeting.decide(t_secs, moisture, &settings.target);
+            }
+            // Target mode keeps the soil in its band on its own and gets no
+            // dawn run
+            let dry = moisture < settings.dry_below;
+            (dawn && dry && settings.dawn.run_secs > 0)
+                .then(|| PumpCommand::single(settings.dawn.run_secs))
+        });
+
+        Observed {
+            check,
+            gain_learned,
+            refill,
+            tank,
+            dawn,
+            command,
+        }
+    }
+
+    /// The moisture the soil counts as dry below: the bottom of the target
+    /// band in target mode.
+    pub fn dry_below(&self, settings: &StationSettings) -> f32 {
+        self.targeting
+            .band()
+            .map_or(settings.dry_below, |band| band.low)
+    }
+
+    /// Today's water budget; None outside a vacation or while the date is
+    /// not known. Ends the vacation once its end day is reached.
+    pub fn plan_vacation(&mut self, settings: &StationSettings) -> Option<Plan> {
+        let until_day = self.vacation_until?;
+        let today = self.state.et.day_of_year()?;
+        let tank = self.tank.status(&settings.tank);
+        let plan = vacation::plan(
+            until_day,
+            today,
+            tank.volume_l,
+            tank.daily_use_l,
+            settings.vacation_reserve_l,
+            settings.vacation_zones,
+        );
+        if plan.is_none() {
+            self.vacation_until = None;
+        }
+        plan
+    }
+
+    /// `cmd` cut to today's vacation budget; None if nothing of it is left.
+    /// Commands pass unchanged outside a vacation.
+    pub fn scale(&mut self, cmd: PumpCommand, settings: &StationSettings) -> Option<PumpCommand> {
+        match self.plan_vacation(settings) {
+            Some(plan) => vacation::scale(cmd, &plan),
+            None => Some(cmd),
+        }
+    }
+
+    /// Passes `cmd` through the weather guards, then sizes the run.
+    pub fn decide(
+        &mut self,
+        t_secs: u32,
+        cmd: PumpCommand,
+        settings: &StationSettings,
+    ) -> Decision {
+        self.state.decide(t_secs, cmd, &settings.watering)
+    }
+
+    /// A run of `on_secs` ended at `t_secs`; the soil is watched for its
+    /// response from here.
+    pub fn watered(&mut self, t_secs: u32, on_secs: u32, settings: &StationSettings) {
+        self.response.watered(t_secs, on_secs, &settings.response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guards::GuardSettings;
+    use crate::quality::Limits;
+    use crate::tank::{TankGeometry, TankShape};
+    use crate::target::Band;
+
+    const LIMITS: Limits = Limits {
+        min: -1000.0,
+        max: 1000.0,
+        max_rate_per_min: None,
+        stuck_count: 0,
+    };
+
+    const SETTINGS: StationSettings = StationSettings {
+        watering: Settings {
+            pump_max_secs: 30,
+            latitude: 50.0,
+            crop_coefficient: 1.0,
+            reference_et_mm: 4.0,
+            sunny_dli: 0.0,
+            guards: GuardSettings {
+                frost_below: 2.0,
+                humid_above: 95.0,
+                heat_wave_above: 30.0,
+                runs_per_day: 4,
+                heat_wave_extra_runs: 2,
+                rain_drop: 3.0,
+                rain_delay_secs: 6 * 60 * 60,
+            },
+        },
+        quality: QualitySettings {
+            temperature: LIMITS,
+            humidity: LIMITS,
+            pressure: LIMITS,
+            soil_moisture: LIMITS,
+            water_level: LIMITS,
+        },
+        soil_ema_alpha: 1.0,
+        altitude_m: 0.0,
+        response: ResponseSettings {
+            window_secs: 30 * 60,
+            min_on_secs: 5,
+            min_rise_pct: 1.0,
+            saturated_pct: 90.0,
+        },
+        target: TargetSettings {
+            initial_gain: 0.5,
+            min_gain: 0.05,
+            max_gain: 5.0,
+            max_step: 0.25,
+            min_interval_secs: 30 * 60,
+            min_secs: 5,
+            max_secs: 30,
+        },
+        tank: TankSettings {
+            geometry: TankGeometry {
+                shape: TankShape::Box {
+                    length_cm: 30.0,
+                    width_cm: 20.0,
+                },
+                height_cm: 30.0,
+                sensor_offset_cm: 5.0,
+            },
+            noise_l: 0.2,
+            refill_rise_l: 2.0,
+            reminder_days: 3.0,
+        },
+        forecast: ForecastSettings {
+            interval_secs: 30 * 60,
+            min_points: 4,
+            reset_rise_pct: 2.0,
+            max_hours: 7.0 * 24.0,
+        },
+        dawn: DawnSettings {
+            dawn_lux: 50.0,
+            min_night_secs: 4 * 60 * 60,
+            run_secs: 20,
+        },
+        dry_below: 40.0,
+        vacation_reserve_l: 1.0,
+        vacation_zones: &[Zone {
+            weight: 1.0,
+            use_share: 1.0,
+        }],
+    };
+
+    const HOUR: u32 = 60 * 60;
+
+    fn reading(moisture: f32, lux: f32) -> SensorData {
+        SensorData {
+            soil_moisture: Some(moisture),
+            lux: Some(lux),
+            ..SensorData::default()
+        }
+    }
+
+    // A night of `moisture` soil, then the first light; what the station
+    // makes of that reading
+    fn dawn(station: &mut Station, moisture: f32) -> Observed {
+        for hour in 0..6 {
+            station.observe(hour * HOUR, &mut reading(moisture, 0.0), &SETTINGS);
+        }
+        station.observe(6 * HOUR, &mut reading(moisture, 80.0), &SETTINGS)
+    }
+
+    #[test]
+    fn dry_soil_gets_a_run_at_dawn() {
+        let mut station = Station::new();
+        let observed = dawn(&mut station, 30.0);
+        assert!(observed.dawn);
+        let cmd = observed.command.unwrap();
+        assert_eq!((cmd.duration_secs, cmd.pulses), (20, 1));
+    }
+
+    #[test]
+    fn no_dawn_run_for_wet_soil_or_with_none_set() {
+        assert!(dawn(&mut Station::new(), 50.0).command.is_none());
+
+        let settings = StationSettings {
+            dawn: DawnSettings {
+                run_secs: 0,
+                ..SETTINGS.dawn
+            },
+            ..SETTINGS
+        };
+        let mut station = Station::new();
+        for hour in 0..6 {
+            station.observe(hour * HOUR, &mut reading(30.0, 0.0), &settings);
+        }
+        let observed = station.observe(6 * HOUR, &mut reading(30.0, 80.0), &settings);
+        assert!(observed.dawn);
+        assert!(observed.command.is_none());
+    }
+
+    #[test]
+    fn target_mode_waters_below_the_band_and_not_at_dawn() {
+        let mut station = Station::new();
+        station.targeting.set_band(Band::new(20.0, 30.0));
+        let observed = dawn(&mut station, 25.0);
+        assert!(observed.dawn);
+        assert!(observed.command.is_none());
+        assert_eq!(station.dry_below(&SETTINGS), 20.0);
+
+        let observed = station.observe(7 * HOUR, &mut reading(15.0, 1000.0), &SETTINGS);
+        assert!(observed.command.unwrap().targeted);
+    }
+
+    #[test]
+    fn refine_reads_the_light_sensor_and_filters_the_soil() {
+        let mut station = Station::new();
+        let mut data = SensorData {
+            soil_moisture_raw: Some(42.0),
+            ..SensorData::default()
+        };
+        data.telemetry.push("bh1750_lux", 1234.0);
+        station.refine(0, &mut data, &SETTINGS);
+        assert_eq!(data.lux, Some(1234.0));
+        assert_eq!(data.soil_moisture, Some(42.0));
+    }
+
+    #[test]
+    fn vacation_ends_on_its_last_day() {
+        let mut station = Station::new();
+        station.vacation_until = Some(12);
+        // The date is not known yet: the vacation waits
+        assert!(station.plan_vacation(&SETTINGS).is_none());
+        assert_eq!(station.vacation_until, Some(12));
+
+        station.state.set_day_of_year(10);
+        assert_eq!(station.plan_vacation(&SETTINGS).unwrap().days_left, 2);
+
+        station.state.set_day_of_year(12);
+        let cmd = station.scale(PumpCommand::single(20), &SETTINGS).unwrap();
+        assert_eq!(cmd.duration_secs, 20);
+        assert_eq!(station.vacation_until, None);
+    }
+
+    #[test]
+    fn vacation_ends_without_date_updates() {
+        let mut station = Station::new();
+        station.vacation_until = Some(12);
+        station.state.set_day_of_year(10);
+        // The server is not heard from again; the day follows uptime
+        for hour in 0..=48 {
+            station.observe(hour * HOUR, &mut reading(50.0, 0.0), &SETTINGS);
+            if hour == 47 {
+                assert_eq!(station.plan_vacation(&SETTINGS).unwrap().days_left, 1);
+            }
+        }
+        assert_eq!(station.state.et.day_of_year(), Some(12));
+        assert!(station.plan_vacation(&SETTINGS).is_none());
+        assert_eq!(station.vacation_until, None);
+    }
+}
@@ -0,0 +1,316 @@
+//! Tank volume from the sonar distance, the water used per day, and refill
+//! detection.
+//!
+//! Worked example: a cylinder of 30 cm diameter holds 7.07 l per 10 cm.
+//! With 40 cm of water when full and the sonar 5 cm above that surface, a
+//! reading of 25 cm leaves 20 cm of water, 14.1 l. Using 2 l a day, that
+//! is 7 days of water.
+
+use core::f32::consts::PI;
+
+use serde::Serialize;
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+// Weight of each new day in the learned daily use
+const LEARN_RATE: f32 = 0.3;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TankShape {
+    Cylinder { diameter_cm: f32 },
+    Box { length_cm: f32, width_cm: f32 },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TankGeometry {
+    pub shape: TankShape,
+    // Water depth of a full tank
+    pub height_cm: f32,
+    // Sonar distance to the surface of a full tank
+    pub sensor_offset_cm: f32,
+}
+
+impl TankGeometry {
+    pub fn area_cm2(&self) -> f32 {
+        match self.shape {
+            TankShape::Cylinder { diameter_cm } => PI * diameter_cm * diameter_cm / 4.0,
+            TankShape::Box {
+                length_cm,
+                width_cm,
+            } => length_cm * width_cm,
+        }
+    }
+
+    pub fn capacity_l(&self) -> f32 {
+        self.area_cm2() * self.height_cm / 1000.0
+    }
+
+    /// Litres left for a sonar reading of `distance_cm`; readings above
+    /// the full surface or below the bottom are clamped.
+    pub fn volume_l(&self, distance_cm: f32) -> f32 {
+        let depth =
+            (self.height_cm - (distance_cm - self.sensor_offset_cm)).clamp(0.0, self.height_cm);
+        self.area_cm2() * depth / 1000.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TankSettings {
+    pub geometry: TankGeometry,
+    // Changes smaller than this are sonar noise, l
+    pub noise_l: f32,
+    // A rise of this much is a refill, l
+    pub refill_rise_l: f32,
+    // Remind to refill with fewer days of water than this left, or with
+    // the tank empty
+    pub reminder_days: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct Refill {
+    pub from_l: f32,
+    pub to_l: f32,
+}
+
+/// What the tank readings say so far.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct TankStatus {
+    pub volume_l: Option<f32>,
+    pub daily_use_l: Option<f32>,
+    pub days_remaining: Option<f32>,
+    pub refill_due: bool,
+}
+
+/// Fed with every sonar reading.
+///
+/// Use is counted whenever the level falls more than `noise_l` below the
+/// last level it settled at, so jitter does not add up. Days follow
+/// uptime, like the guards.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TankTracker {
+    level_l: Option<f32>,
+    day_start: Option<u32>,
+    used_today_l: f32,
+    daily_use_l: Option<f32>,
+}
+
+impl TankTracker {
+    pub const fn new() -> Self {
+        Self {
+            level_l: None,
+            day_start: None,
+            used_today_l: 0.0,
+            daily_use_l: None,
+        }
+    }
+
+    /// Takes a reading; `None` while the sonar is not reading. Returns the
+    /// refill it shows, if any.
+    pub fn observe(
+        &mut self,
+        t_secs: u32,
+        distance_cm: Option<f32>,
+        settings: &TankSettings,
+    ) -> Option<Refill> {
+        let start = *self.day_start.get_or_insert(t_secs);
+        let elapsed = t_secs.saturating_sub(start);
+        if elapsed >= DAY_SECS {
+            // Only whole days with the level known teach anything
+            let days = elapsed / DAY_SECS;
+            let used = self.used_today_l / days as f32;
+            if self.level_l.is_some() {
+                self.daily_use_l = Some(match self.daily_use_l {
+                    Some(daily) => daily + (used - daily) * LEARN_RATE,
+                    None => used,
+                });
+            }
+            self.day_start = Some(start + days * DAY_SECS);
+            self.used_today_l = 0.0;
+        }
+
+        let volume = settings.geometry.volume_l(distance_cm?);
+        let Some(level) = self.level_l else {
+            self.level_l = Some(volume);
+            return None;
+        };
+
+        if volume >= level + settings.refill_rise_l {
+            self.level_l = Some(volume);
+            return Some(Refill {
+                from_l: level,
+                to_l: volume,
+            });
+        }
+        if volume <= level - settings.noise_l {
+            self.used_today_l += level - volume;
+            self.level_l = Some(volume);
+        }
+        None
+    }
+
+    pub fn status(&self, settings: &TankSettings) -> TankStatus {
+        let days_remaining = self
+            .level_l
+            .zip(self.daily_use_l)
+            .filter(|&(_, daily)| daily > 0.0)
+            .map(|(level, daily)| level / daily);
+        TankStatus {
+            volume_l: self.level_l,
+            daily_use_l: self.daily_use_l,
+            days_remaining,
+            refill_due: days_remaining.is_some_and(|days| days < settings.reminder_days)
+                || self.level_l.is_some_and(|level| level <= settings.noise_l),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: TankSettings = TankSettings {
+        geometry: TankGeometry {
+            shape: TankShape::Cylinder { diameter_cm: 30.0 },
+            height_cm: 40.0,
+            sensor_offset_cm: 5.0,
+        },
+        noise_l: 0.2,
+        refill_rise_l: 2.0,
+        reminder_days: 3.0,
+    };
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 0.01,
+            "{actual} is not {expected}"
+        );
+    }
+
+    // Distance to the surface with `litres` in the example tank
+    fn distance_cm(litres: f32) -> f32 {
+        let geometry = SETTINGS.geometry;
+        geometry.sensor_offset_cm + geometry.height_cm - litres * 1000.0 / geometry.area_cm2()
+    }
+
+    // A reading every hour for `days` from `from_secs`, with a run using
+    // `daily_l` at 8:00 each day; returns the time of the next day's start
+    // and the refills seen
+    fn use_water(
+        tank: &mut TankTracker,
+        from_secs: u32,
+        start_l: f32,
+        daily_l: f32,
+        days: u32,
+    ) -> (u32, usize) {
+        let mut refills = 0;
+        for hour in 0..days * 24 {
+            let litres = start_l - daily_l * ((hour + 16) / 24) as f32;
+            let t_secs = from_secs + hour * 3600;
+            refills += tank
+                .observe(t_secs, Some(distance_cm(litres)), &SETTINGS)
+                .is_some() as usize;
+        }
+        (from_secs + days * DAY_SECS, refills)
+    }
+
+    #[test]
+    fn worked_example() {
+        let geometry = SETTINGS.geometry;
+        assert_near(geometry.area_cm2() * 10.0 / 1000.0, 7.07);
+        assert_near(geometry.volume_l(25.0), 14.14);
+
+        let mut tank = TankTracker::new();
+        // Down to the 14.14 l a reading of 25 cm leaves
+        let (t_secs, _) = use_water(&mut tank, 0, 20.14, 2.0, 3);
+        tank.observe(t_secs, Some(25.0), &SETTINGS);
+        let status = tank.status(&SETTINGS);
+        assert_near(status.daily_use_l.unwrap(), 2.0);
+        assert_near(status.days_remaining.unwrap(), 7.07);
+        assert!(!status.refill_due);
+    }
+
+    #[test]
+    fn shapes_and_clamping() {
+        let boxed = TankGeometry {
+            shape: TankShape::Box {
+                length_cm: 50.0,
+                width_cm: 20.0,
+            },
+            height_cm: 30.0,
+            sensor_offset_cm: 5.0,
+        };
+        assert_near(boxed.capacity_l(), 30.0);
+        assert_near(boxed.volume_l(20.0), 15.0);
+        // Above the full surface, and below the bottom
+        assert_near(boxed.volume_l(2.0), 30.0);
+        assert_near(boxed.volume_l(60.0), 0.0);
+    }
+
+    #[test]
+    fn jitter_is_not_use() {
+        let mut tank = TankTracker::new();
+        for hour in 0..=48 {
+            let jitter = if hour % 2 == 0 { 0.09 } else { -0.09 };
+            tank.observe(hour * 3600, Some(distance_cm(15.0 + jitter)), &SETTINGS);
+        }
+        assert_eq!(tank.status(&SETTINGS).daily_use_l, Some(0.0));
+    }
+
+    #[test]
+    fn daily_use_is_learned_gradually() {
+        let mut tank = TankTracker::new();
+        let (t_secs, _) = use_water(&mut tank, 0, 28.0, 2.0, 2);
+        assert_near(tank.status(&SETTINGS).daily_use_l.unwrap(), 2.0);
+
+        // A hot day using 4 l moves it by 0.3 of the difference
+        use_water(&mut tank, t_secs, 24.0, 4.0, 1);
+        tank.observe(t_secs + DAY_SECS + 60, None, &SETTINGS);
+        assert_near(tank.status(&SETTINGS).daily_use_l.unwrap(), 2.6);
+    }
+
+    #[test]
+    fn a_refill_is_reported_and_not_counted() {
+        let mut tank = TankTracker::new();
+        let (t_secs, refills) = use_water(&mut tank, 0, 10.0, 2.0, 1);
+        assert_eq!(refills, 0);
+
+        let refill = tank
+            .observe(t_secs + 60, Some(distance_cm(25.0)), &SETTINGS)
+            .unwrap();
+        assert_near(refill.from_l, 8.0);
+        assert_near(refill.to_l, 25.0);
+        // A small rise is not one
+        assert_eq!(
+            tank.observe(t_secs + 120, Some(distance_cm(26.0)), &SETTINGS),
+            None
+        );
+        use_water(&mut tank, t_secs + 3600, 25.0, 2.0, 1);
+        tank.observe(t_secs + DAY_SECS + 7200, None, &SETTINGS);
+        assert_near(tank.status(&SETTINGS).daily_use_l.unwrap(), 2.0);
+    }
+
+    #[test]
+    fn a_low_or_empty_tank_is_due_a_refill() {
+        let mut tank = TankTracker::new();
+        let (t_secs, _) = use_water(&mut tank, 0, 7.0, 2.0, 1);
+        tank.observe(t_secs + 60, Some(distance_cm(4.9)), &SETTINGS);
+        let status = tank.status(&SETTINGS);
+        assert_near(status.days_remaining.unwrap(), 2.5);
+        assert!(status.refill_due);
+
+        // Empty before any use has been learned
+        let mut tank = TankTracker::new();
+        tank.observe(0, Some(distance_cm(0.0)), &SETTINGS);
+        let status = tank.status(&SETTINGS);
+        assert_eq!(status.days_remaining, None);
+        assert!(status.refill_due);
+    }
+
+    #[test]
+    fn days_without_readings_teach_nothing() {
+        let mut tank = TankTracker::new();
+        for hour in 0..=72 {
+            tank.observe(hour * 3600, None, &SETTINGS);
+        }
+        assert_eq!(tank.status(&SETTINGS), TankStatus::default());
+    }
+}
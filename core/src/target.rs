@@ -0,0 +1,268 @@
+//! Target-moisture mode: whenever the soil drops below a band, water for
+//! as long as the learned gain says it takes to get back to its middle.
+//!
+//! The gain is the moisture rise per second of pumping, taken from each
+//! run's [`Check`](crate::response::Check) and moved at most `max_step` of
+//! the way there per run. Worked example: band 40-50 %, a reading of 38 %
+//! and a gain of 0.5 %/s give a 14 s run to reach 45 %. If that run raises
+//! the moisture by 14 % (1 %/s), a `max_step` of 0.25 only moves the gain
+//! to 0.625 %/s, and the next run from 38 % is 12 s.
+
+use libm::ceilf;
+use serde::Serialize;
+
+use crate::response::Check;
+use crate::types::PumpCommand;
+
+/// The soil moisture to keep to, %.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize)]
+pub struct Band {
+    pub low: f32,
+    pub high: f32,
+}
+
+impl Band {
+    /// `None` unless 0 < `low` < `high` <= 100.
+    pub fn new(low: f32, high: f32) -> Option<Self> {
+        (low > 0.0 && low < high && high <= 100.0).then_some(Self { low, high })
+    }
+
+    pub fn middle(&self) -> f32 {
+        (self.low + self.high) / 2.0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TargetSettings {
+    // Gain before anything is learned, % per pump second
+    pub initial_gain: f32,
+    // Bounds on the learned gain; the lower one caps how long a run can
+    // get before `max_secs` does
+    pub min_gain: f32,
+    pub max_gain: f32,
+    // Largest change of the gain per run, as a fraction of it
+    pub max_step: f32,
+    // Time after a run before the next, at least the response window so
+    // each run is learned from first
+    pub min_interval_secs: u32,
+    // Shortest run, at least the response check's `min_on_secs` so every
+    // run is learned from
+    pub min_secs: u16,
+    // Pump safety limit
+    pub max_secs: u16,
+}
+
+/// Run length that brings `moisture` to the middle of `band` at `gain`,
+/// within `min_secs` and `max_secs`.
+pub fn duration_secs(moisture: f32, band: &Band, gain: f32, settings: &TargetSettings) -> u16 {
+    let short_pct = (band.middle() - moisture).max(0.0);
+    ceilf(short_pct / gain).clamp(settings.min_secs as f32, settings.max_secs as f32) as u16
+}
+
+/// `gain` moved towards the one measured by a run, by at most `max_step`
+/// of itself and within the gain bounds.
+pub fn update_gain(gain: f32, rise_pct: f32, on_secs: u32, settings: &TargetSettings) -> f32 {
+    if on_secs == 0 {
+        return gain;
+    }
+    let measured = rise_pct / on_secs as f32;
+    let step = gain * settings.max_step;
+    measured
+        .clamp(gain - step, gain + step)
+        .clamp(settings.min_gain, settings.max_gain)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TargetController {
+    band: Option<Band>,
+    // None until the first run has been learned from
+    gain: Option<f32>,
+    last_run_secs: Option<u32>,
+}
+
+impl TargetController {
+    pub const fn new() -> Self {
+        Self {
+            band: None,
+            gain: None,
+            last_run_secs: None,
+        }
+    }
+
+    /// Restores what was kept across a reboot.
+    pub fn restore(&mut self, band: Option<Band>, gain: Option<f32>) {
+        self.band = band;
+        self.gain = gain;
+    }
+
+    pub fn band(&self) -> Option<Band> {
+        self.band
+    }
+
+    /// Sets the band, or turns target mode off with `None`.
+    pub fn set_band(&mut self, band: Option<Band>) {
+        self.band = band;
+    }
+
+    pub fn learned_gain(&self) -> Option<f32> {
+        self.gain
+    }
+
+    pub fn gain(&self, settings: &TargetSettings) -> f32 {
+        self.gain.unwrap_or(settings.initial_gain)
+    }
+
+    /// The run to make at `t_secs`, if the soil is below the band and the
+    /// last run has had time to soak in.
+    pub fn decide(
+        &mut self,
+        t_secs: u32,
+        moisture: f32,
+        settings: &TargetSettings,
+    ) -> Option<PumpCommand> {
+        let band = self.band?;
+        if moisture >= band.low {
+            return None;
+        }
+        if let Some(last) = self.last_run_secs
+            && t_secs.saturating_sub(last) < settings.min_interval_secs
+        {
+            return None;
+        }
+
+        self.last_run_secs = Some(t_secs);
+        let secs = duration_secs(moisture, &band, self.gain(settings), settings);
+        Some(PumpCommand {
+            targeted: true,
+            ..PumpCommand::single(secs)
+        })
+    }
+
+    /// Learns from a run's moisture response; true if the gain changed.
+    /// Runs that got no response say nothing about the soil and are
+    /// ignored.
+    pub fn learn(&mut self, check: &Check, settings: &TargetSettings) -> bool {
+        if !check.responded {
+            return false;
+        }
+        let gain = update_gain(self.gain(settings), check.rise_pct, check.on_secs, settings);
+        let changed = self.gain != Some(gain);
+        self.gain = Some(gain);
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: TargetSettings = TargetSettings {
+        initial_gain: 0.5,
+        min_gain: 0.05,
+        max_gain: 5.0,
+        max_step: 0.25,
+        min_interval_secs: 1800,
+        min_secs: 5,
+        max_secs: 120,
+    };
+
+    fn check(on_secs: u32, rise_pct: f32, responded: bool) -> Check {
+        Check {
+            on_secs,
+            rise_pct,
+            expected_pct: None,
+            responded,
+        }
+    }
+
+    #[test]
+    fn worked_example() {
+        let band = Band::new(40.0, 50.0).unwrap();
+        assert_eq!(duration_secs(38.0, &band, 0.5, &SETTINGS), 14);
+        let gain = update_gain(0.5, 14.0, 14, &SETTINGS);
+        assert_eq!(gain, 0.625);
+        assert_eq!(duration_secs(38.0, &band, gain, &SETTINGS), 12);
+    }
+
+    #[test]
+    fn band_bounds() {
+        assert!(Band::new(40.0, 50.0).is_some());
+        assert!(Band::new(0.0, 50.0).is_none());
+        assert!(Band::new(50.0, 50.0).is_none());
+        assert!(Band::new(60.0, 50.0).is_none());
+        assert!(Band::new(40.0, 101.0).is_none());
+    }
+
+    #[test]
+    fn runs_stay_within_the_limits() {
+        let band = Band::new(40.0, 50.0).unwrap();
+        assert_eq!(duration_secs(44.9, &band, 0.5, &SETTINGS), 5);
+        assert_eq!(duration_secs(60.0, &band, 0.5, &SETTINGS), 5);
+        assert_eq!(duration_secs(0.0, &band, 0.05, &SETTINGS), 120);
+    }
+
+    #[test]
+    fn the_gain_stays_within_its_bounds() {
+        assert_eq!(update_gain(0.5, 0.0, 10, &SETTINGS), 0.375);
+        assert_eq!(update_gain(0.06, 0.0, 10, &SETTINGS), 0.05);
+        assert_eq!(update_gain(4.5, 100.0, 10, &SETTINGS), 5.0);
+        assert_eq!(update_gain(0.5, 10.0, 0, &SETTINGS), 0.5);
+    }
+
+    #[test]
+    fn runs_wait_for_the_last_to_soak_in() {
+        let mut target = TargetController::new();
+        assert!(target.decide(0, 30.0, &SETTINGS).is_none());
+
+        target.set_band(Band::new(40.0, 50.0));
+        assert!(target.decide(0, 40.0, &SETTINGS).is_none());
+        let cmd = target.decide(0, 38.0, &SETTINGS).unwrap();
+        assert!(cmd.targeted);
+        assert_eq!(cmd.duration_secs, 14);
+        assert!(target.decide(1799, 38.0, &SETTINGS).is_none());
+        assert!(target.decide(1800, 38.0, &SETTINGS).is_some());
+    }
+
+    #[test]
+    fn runs_without_a_response_are_not_learned_from() {
+        let mut target = TargetController::new();
+        assert!(!target.learn(&check(14, 0.0, false), &SETTINGS));
+        assert_eq!(target.learned_gain(), None);
+        assert!(target.learn(&check(14, 14.0, true), &SETTINGS));
+        assert_eq!(target.learned_gain(), Some(0.625));
+        // The same gain again is no change
+        target.restore(target.band(), Some(0.625));
+        assert!(!target.learn(&check(16, 10.0, true), &SETTINGS));
+    }
+
+    #[test]
+    fn the_gain_converges_on_the_soil() {
+        // Soil that rises 1.2 % per pump second and dries 0.5 % an hour
+        const SOIL_GAIN: f32 = 1.2;
+        let band = Band::new(40.0, 50.0).unwrap();
+        let mut target = TargetController::new();
+        target.set_band(Some(band));
+        let mut moisture = 39.0;
+        let mut runs = 0;
+
+        for hour in 0..24 * 30 {
+            let t_secs = hour * 3600;
+            if let Some(cmd) = target.decide(t_secs, moisture, &SETTINGS) {
+                let secs = cmd.duration_secs as u32;
+                let rise = SOIL_GAIN * secs as f32;
+                moisture += rise;
+                target.learn(&check(secs, rise, true), &SETTINGS);
+                runs += 1;
+                // Once learned, each run lands in the band
+                if runs > 5 {
+                    assert!(band.low <= moisture && moisture <= band.high, "{moisture}%");
+                }
+            }
+            moisture -= 0.5;
+        }
+
+        assert!(runs > 10, "{runs} runs");
+        let gain = target.learned_gain().unwrap();
+        assert!((gain - SOIL_GAIN).abs() < 0.01, "gain {gain}");
+    }
+}
@@ -0,0 +1,102 @@
+//! Named measurements from whatever sensors were found at boot, uploaded
+//! as a JSON object next to the fixed fields.
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+
+pub const MAX_MEASUREMENTS: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Measurement {
+    // Chip and quantity with its unit, e.g. `bh1750_lux`
+    pub name: &'static str,
+    pub value: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Telemetry {
+    items: [Measurement; MAX_MEASUREMENTS],
+    len: usize,
+}
+
+impl Telemetry {
+    pub const fn new() -> Self {
+        Self {
+            items: [Measurement {
+                name: "",
+                value: 0.0,
+            }; MAX_MEASUREMENTS],
+            len: 0,
+        }
+    }
+
+    /// Adds a measurement; false if there is no room left.
+    pub fn push(&mut self, name: &'static str, value: f32) -> bool {
+        let Some(item) = self.items.get_mut(self.len) else {
+            return false;
+        };
+        *item = Measurement { name, value };
+        self.len += 1;
+        true
+    }
+
+    pub fn get(&self, name: &str) -> Option<f32> {
+        self.iter().find(|m| m.name == name).map(|m| m.value)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Measurement> {
+        self.items[..self.len].iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Serialize for Telemetry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len))?;
+        for m in self.iter() {
+            map.serialize_entry(m.name, &m.value)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_stops_at_capacity() {
+        let mut telemetry = Telemetry::new();
+        assert!(telemetry.is_empty());
+        for i in 0..MAX_MEASUREMENTS {
+            assert!(telemetry.push("x", i as f32));
+        }
+        assert!(!telemetry.push("full", 1.0));
+        assert_eq!(telemetry.len(), MAX_MEASUREMENTS);
+        assert_eq!(telemetry.get("full"), None);
+        // The first of a repeated name is found
+        assert_eq!(telemetry.get("x"), Some(0.0));
+    }
+
+    #[test]
+    fn serializes_as_an_object() {
+        let mut json = [0; 64];
+        let len = serde_json_core::to_slice(&Telemetry::new(), &mut json).unwrap();
+        assert_eq!(&json[..len], b"{}");
+
+        let mut telemetry = Telemetry::new();
+        telemetry.push("bh1750_lux", 1234.5);
+        telemetry.push("ina219_bus_v", 12.0);
+        let len = serde_json_core::to_slice(&telemetry, &mut json).unwrap();
+        assert_eq!(
+            &json[..len],
+            br#"{"bh1750_lux":1234.5,"ina219_bus_v":12.0}"#
+        );
+    }
+}
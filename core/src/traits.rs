@@ -0,0 +1,78 @@
+use crate::drivers::Kind;
+use crate::net::{Request, Response};
+use crate::telemetry::Telemetry;
+
+/// Air conditions from the BME280 or a stand-in, pressure in hPa; each
+/// None if it could not be read.
+#[derive(Clone, Copy, Default)]
+pub struct EnvReading {
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub pressure: Option<f32>,
+}
+
+#[allow(async_fn_in_trait)]
+pub trait EnvSensor {
+    type Error;
+
+    async fn read(&mut self) -> Result<EnvReading, Self::Error>;
+}
+
+#[allow(async_fn_in_trait)]
+pub trait SoilProbe {
+    type Error;
+
+    /// Raw ADC count, see [`crate::soil`] for the mapping to moisture.
+    async fn read_raw(&mut self) -> Result<u16, Self::Error>;
+}
+
+#[allow(async_fn_in_trait)]
+pub trait RangeSensor {
+    type Error;
+
+    /// Round-trip echo time in µs, see [`crate::sonar`] for the distance.
+    async fn echo_us(&mut self) -> Result<f32, Self::Error>;
+}
+
+/// A sensor found at boot that adds named measurements to [`Telemetry`].
+#[allow(async_fn_in_trait)]
+pub trait SensorDriver {
+    type Error;
+
+    fn kind(&self) -> Kind;
+
+    async fn read(&mut self, out: &mut Telemetry) -> Result<(), Self::Error>;
+}
+
+/// A 1-Wire master, one time slot at a time; see [`crate::onewire`].
+#[allow(async_fn_in_trait)]
+pub trait OneWireBus {
+    type Error;
+
+    /// Reset pulse; true if any device answered with a presence pulse.
+    async fn reset(&mut self) -> Result<bool, Self::Error>;
+
+    /// One time slot: writes `bit` and returns what the wire read. Writing
+    /// a 1 lets a device pull it low, which is how bits are read.
+    async fn touch_bit(&mut self, bit: bool) -> Result<bool, Self::Error>;
+}
+
+pub trait Actuator {
+    fn set_on(&mut self, on: bool);
+}
+
+#[allow(async_fn_in_trait)]
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+
+    async fn sleep_ms(&mut self, ms: u64);
+}
+
+#[allow(async_fn_in_trait)]
+pub trait Transport {
+    type Error: core::fmt::Debug;
+
+    /// Sends `request` and, for 2xx responses, reads the body into `body`.
+    /// An empty `body` means the caller does not care about it.
+    async fn send(&mut self, request: &Request, body: &mut [u8]) -> Result<Response, Self::Error>;
+}
@@ -0,0 +1,239 @@
+use heapless::String;
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::CrashRecord;
+use crate::guards::SkipReason;
+use crate::onewire::SoilTemperatures;
+use crate::pump::Phase;
+use crate::quality::Quality;
+use crate::response::{Check, Curve};
+use crate::tank::Refill;
+use crate::telemetry::Telemetry;
+use crate::vacation::Plan;
+
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SensorData {
+    // Measured fields are None while their sensor is offline or the reading
+    // failed a check; `quality` says which
+    pub temperature: Option<f32>,
+    pub humidity: Option<f32>,
+    pub pressure: Option<f32>,
+    // Filtered, see `soil::Ema`; what the watering logic goes by
+    pub soil_moisture: Option<f32>,
+    // The same reading before filtering
+    pub soil_moisture_raw: Option<f32>,
+    // Sonar distance to the water surface, cm
+    pub water_level: Option<f32>,
+    // From the light sensor found at boot, see `light`
+    pub lux: Option<f32>,
+    pub quality: Quality,
+    // Derived from the BME280, see `psychro`; None while it is offline
+    pub dew_point: Option<f32>,
+    pub absolute_humidity: Option<f32>, // g/m³
+    pub heat_index: Option<f32>,
+    pub sea_level_pressure: Option<f32>, // hPa, for ALTITUDE_M
+    // Vapour pressure deficit, kPa
+    pub vpd: Option<f32>,
+    // Reference evapotranspiration, mm/day; None until a full day is seen
+    pub et0: Option<f32>,
+    // Daily light integral, mol/m²: so far today, and of the last full day
+    pub dli_today: Option<f32>,
+    pub dli: Option<f32>,
+    // Water delivered by the pump, counted only once it is calibrated
+    pub water_today_l: f32,
+    pub water_total_l: f32,
+    // Learned moisture rise per second of watering, see `response`
+    pub moisture_response: Option<Curve>,
+    // Until the soil trend crosses the dry threshold; None while it is not
+    // drying or the trend is not known yet, see `forecast`
+    pub hours_until_dry: Option<f32>,
+    // From the sonar and the tank geometry, see `tank`; None until known
+    pub tank_l: Option<f32>,
+    pub tank_daily_use_l: Option<f32>,
+    pub tank_days_remaining: Option<f32>,
+    // From the sensors found on the I2C bus at boot, see `drivers`
+    #[serde(skip_deserializing)]
+    pub telemetry: Telemetry,
+    // DS18B20 probes on the 1-Wire bus, °C by ROM id, see `onewire`
+    #[serde(skip_deserializing)]
+    pub soil_temperatures: SoilTemperatures,
+}
+
+// Sensor data is most requests; there is no heap to box it on
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
+pub enum HttpRequest {
+    PostSensorData(SensorData),
+    SendAlert { message: String<64> },
+    PostEvent(SystemEvent),
+    PollTasks,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SystemEvent {
+    Boot(CrashRecord),
+    WateringSkipped {
+        reason: SkipReason,
+        requested_secs: u16,
+    },
+    WateringProgress {
+        pulse: u8,
+        pulses: u8,
+        phase: Phase,
+    },
+    WateringFinished {
+        on_secs: u32,
+        cancelled: bool,
+        // Metered with a flow meter fitted, otherwise from the calibration;
+        // None while neither is there
+        volume_ml: Option<u32>,
+    },
+    PumpCalibrated {
+        ml_per_sec: f32,
+    },
+    // The moisture response to a run, once its window is over
+    WateringVerified(Check),
+    TankRefilled(Refill),
+    // The water budget, when vacation mode is set and on each new day
+    VacationPlan(Plan),
+}
+
+#[derive(Clone, Copy)]
+pub struct PumpCommand {
+    // Seconds per pulse; a plain command is a single pulse
+    pub duration_secs: u16,
+    pub pulses: u8,
+    // Pause between pulses
+    pub soak_secs: u16,
+    // Millilitres over all pulses instead of `duration_secs`, 0 if not
+    // used; see `dosing::resolve`
+    pub volume_ml: u16,
+    // Sized by target-moisture mode, so not scaled by ET again
+    pub targeted: bool,
+    // Whose water it is when a vacation budget is shared, see
+    // `vacation::Zone`
+    pub zone: u8,
+}
+
+impl PumpCommand {
+    /// One continuous run of `secs`.
+    pub const fn single(secs: u16) -> Self {
+        Self {
+            duration_secs: secs,
+            pulses: 1,
+            soak_secs: 0,
+            volume_ml: 0,
+            targeted: false,
+            zone: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Deserialize)]
+#[cfg_attr(test, derive(Serialize, PartialEq, Debug))]
+pub struct TasksResponse {
+    #[serde(default)]
+    pub pump_duration: u16, // 0 = no action, >0 = run pump for N seconds
+    #[serde(default)]
+    pub pump_volume_ml: u16, // run until this much is delivered, needs a calibrated pump
+    #[serde(default)]
+    pub pump_pulses: u8, // repeat the run this often, 0 or 1 = once
+    #[serde(default)]
+    pub pump_soak_secs: u16, // pause between repeats
+    #[serde(default)]
+    pub pump_zone: u8, // zone the run waters, for the vacation budget; 0 = the first
+    #[serde(default)]
+    pub pump_cancel: bool, // stop a running program
+    #[serde(default)]
+    pub calibrate_pump: bool, // run the pump for CALIBRATION_SECS into a measuring jug
+    #[serde(default)]
+    pub calibration_ml: u16, // what the last calibration run delivered, 0 = not sent
+    #[serde(default)]
+    pub target_moisture_low: f32, // soil moisture band to water to, %, 0 = not sent
+    #[serde(default)]
+    pub target_moisture_high: f32,
+    #[serde(default)]
+    pub target_off: bool, // leave target-moisture mode
+    #[serde(default)]
+    pub vacation_until_day: u16, // 1-365, day of year a vacation ends, 0 = not sent
+    #[serde(default)]
+    pub vacation_off: bool, // end vacation mode early
+    #[serde(default)]
+    pub firmware_update: bool, // fetch the OTA manifest and install if newer
+    #[serde(default)]
+    pub upload_record: bool, // POST the record log to RECORD_ENDPOINT
+    #[serde(default)]
+    pub day_of_year: u16, // 1-366 for the ET estimate, 0 = not sent
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Bme280Init,
+    Bme280Read,
+    SoilRead,
+    SoilTempRead,
+    DisplayInit,
+    DisplayFlush,
+    NoFlow,
+    Leak,
+    NoResponse,
+    RefillTank,
+}
+
+impl Fault {
+    pub const ALL: [Fault; 10] = [
+        Fault::Bme280Init,
+        Fault::Bme280Read,
+        Fault::SoilRead,
+        Fault::SoilTempRead,
+        Fault::DisplayInit,
+        Fault::DisplayFlush,
+        Fault::NoFlow,
+        Fault::Leak,
+        Fault::NoResponse,
+        Fault::RefillTank,
+    ];
+
+    pub fn message(self) -> &'static str {
+        match self {
+            Fault::Bme280Init => "BME280 init failed",
+            Fault::Bme280Read => "BME280 not responding",
+            Fault::SoilRead => "Soil ADC read failed",
+            Fault::SoilTempRead => "Soil temp read failed",
+            Fault::DisplayInit => "OLED init failed",
+            Fault::DisplayFlush => "OLED not responding",
+            Fault::NoFlow => "No flow: dry/blocked",
+            Fault::Leak => "Leak: flow, pump off",
+            Fault::NoResponse => "No soil response",
+            Fault::RefillTank => "Tank low: refill",
+        }
+    }
+}
+
+// Tasks supervised by the watchdog
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskId {
+    Sensor,
+    Pump,
+    Http,
+    Display,
+}
+
+impl TaskId {
+    pub const ALL: [TaskId; 4] = [TaskId::Sensor, TaskId::Pump, TaskId::Http, TaskId::Display];
+
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TaskId::Sensor => "sensor",
+            TaskId::Pump => "pump",
+            TaskId::Http => "http",
+            TaskId::Display => "display",
+        }
+    }
+}
@@ -0,0 +1,398 @@
+//! Vacation mode: make the water in the tank last until a given day.
+//!
+//! What is left above the reserve is spread evenly over the days to go,
+//! and every run is shortened so the day's use fits that budget. Worked
+//! example: 12 l in the tank with a 2 l reserve and 5 days to go allow 2 l
+//! a day; at a learned use of 2.5 l a day every run is cut to 80 %, so a
+//! 20 s run becomes 16 s. The plan is made afresh with every run, so
+//! overspending one day leaves less for the next.
+//!
+//! With several zones, the cut is shared by weight: each zone keeps
+//! `min(1, k * weight)` of its runs, with `k` chosen so the kept use adds
+//! up to the budget. With the 2.5 l split evenly between a zone of weight
+//! 2 and one of weight 1, the first keeps all of its 1.25 l and the second
+//! 0.75 l, 60 % of its runs.
+
+use heapless::Vec;
+use libm::roundf;
+use serde::Serialize;
+
+use crate::types::PumpCommand;
+
+const YEAR_DAYS: u16 = 365;
+
+pub const MAX_ZONES: usize = 4;
+
+/// A group of plants the server waters with its own runs. There is one
+/// pump, so a zone only says whose water a run is.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Zone {
+    // Priority when the budget is short; 0 keeps nothing until every
+    // other zone is whole
+    pub weight: f32,
+    // Share of the daily use that goes to this zone, the shares adding up
+    // to 1
+    pub use_share: f32,
+}
+
+/// Days from `today` until `until_day`, across the turn of the year. A year
+/// that has reached day 366 is a leap year.
+pub fn days_until(today: u16, until_day: u16) -> u16 {
+    let year_days = YEAR_DAYS.max(today);
+    (until_day + year_days - today) % year_days
+}
+
+/// The budget for the rest of a vacation.
+#[derive(Clone, PartialEq, Debug, Serialize)]
+pub struct Plan {
+    pub until_day: u16,
+    pub days_left: u16,
+    // None while the tank level or the usual use is unknown; runs are then
+    // left as they are
+    pub available_l: Option<f32>,
+    pub daily_budget_l: Option<f32>,
+    // Share of each run that is kept, 0-1, per zone
+    pub scales: Vec<f32, MAX_ZONES>,
+}
+
+/// The plan for today; `None` once the vacation is over.
+pub fn plan(
+    until_day: u16,
+    today: u16,
+    tank_l: Option<f32>,
+    daily_use_l: Option<f32>,
+    reserve_l: f32,
+    zones: &[Zone],
+) -> Option<Plan> {
+    let days_left = days_until(today, until_day);
+    if days_left == 0 {
+        return None;
+    }
+
+    let available_l = tank_l.map(|tank| (tank - reserve_l).max(0.0));
+    let daily_budget_l = available_l.map(|available| available / days_left as f32);
+    let scales = match (daily_budget_l, daily_use_l.filter(|&used| used > 0.0)) {
+        (Some(budget), Some(used)) => zone_scales(budget, used, zones),
+        _ => zones.iter().take(MAX_ZONES).map(|_| 1.0).collect(),
+    };
+
+    Some(Plan {
+        until_day,
+        days_left,
+        available_l,
+        daily_budget_l,
+        scales,
+    })
+}
+
+/// Share of each zone's runs that fits `budget_l` of `daily_use_l`:
+/// `min(1, k * weight)`, with `k` as large as the budget allows. Zones
+/// made whole are taken out of the budget and `k` found again for the
+/// rest, until none is.
+pub fn zone_scales(budget_l: f32, daily_use_l: f32, zones: &[Zone]) -> Vec<f32, MAX_ZONES> {
+    let zones = &zones[..zones.len().min(MAX_ZONES)];
+    let use_l = |zone: &Zone| zone.use_share.max(0.0) * daily_use_l;
+    // A zone that uses nothing costs nothing
+    let mut scales: Vec<f32, MAX_ZONES> = zones
+        .iter()
+        .map(|zone| if use_l(zone) > 0.0 { 0.0 } else { 1.0 })
+        .collect();
+    let mut left_l = budget_l.max(0.0);
+
+    loop {
+        let open = || {
+            zones
+                .iter()
+                .zip(scales.iter())
+                .filter(|&(_, &scale)| scale < 1.0)
+                .map(|(zone, _)| zone)
+        };
+        let weighted_l: f32 = open().map(|zone| zone.weight.max(0.0) * use_l(zone)).sum();
+        if weighted_l <= 0.0 {
+            // Only zones of no weight are left; they share what remains
+            let open_l: f32 = open().map(use_l).sum();
+            let share = (left_l.max(0.0) / open_l).min(1.0);
+            for scale in scales.iter_mut().filter(|scale| **scale < 1.0) {
+                *scale = share;
+            }
+            return scales;
+        }
+        let k = left_l / weighted_l;
+        let mut whole = false;
+        for (zone, scale) in zones.iter().zip(scales.iter_mut()) {
+            if *scale < 1.0 && k * zone.weight >= 1.0 {
+                *scale = 1.0;
+                left_l -= use_l(zone);
+                whole = true;
+            }
+        }
+        if !whole {
+            for (zone, scale) in zones.iter().zip(scales.iter_mut()) {
+                if *scale < 1.0 {
+                    *scale = k * zone.weight.max(0.0);
+                }
+            }
+            return scales;
+        }
+    }
+}
+
+/// `cmd` shortened to its zone's share of the plan; `None` if nothing of
+/// it is left. A zone the plan does not know gets the smallest share.
+pub fn scale(cmd: PumpCommand, plan: &Plan) -> Option<PumpCommand> {
+    let share = plan
+        .scales
+        .get(cmd.zone as usize)
+        .copied()
+        .unwrap_or_else(|| plan.scales.iter().copied().fold(1.0, f32::min));
+    let secs = roundf(cmd.duration_secs as f32 * share) as u16;
+    (secs > 0).then_some(PumpCommand {
+        duration_secs: secs,
+        ..cmd
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE: &[Zone] = &[Zone {
+        weight: 1.0,
+        use_share: 1.0,
+    }];
+    const TWO: &[Zone] = &[
+        Zone {
+            weight: 2.0,
+            use_share: 0.5,
+        },
+        Zone {
+            weight: 1.0,
+            use_share: 0.5,
+        },
+    ];
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-4,
+            "{actual} is not {expected}"
+        );
+    }
+
+    fn assert_scales(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (&a, &e) in actual.iter().zip(expected) {
+            assert_near(a, e);
+        }
+    }
+
+    // The water the scales keep of `daily_use_l`
+    fn kept_l(scales: &[f32], daily_use_l: f32, zones: &[Zone]) -> f32 {
+        scales
+            .iter()
+            .zip(zones)
+            .map(|(scale, zone)| scale * zone.use_share * daily_use_l)
+            .sum()
+    }
+
+    fn plan_with(scales: &[f32]) -> Plan {
+        Plan {
+            until_day: 200,
+            days_left: 5,
+            available_l: Some(10.0),
+            daily_budget_l: Some(2.0),
+            scales: Vec::from_slice(scales).unwrap(),
+        }
+    }
+
+    fn zone(cmd: PumpCommand, zone: u8) -> PumpCommand {
+        PumpCommand { zone, ..cmd }
+    }
+
+    #[test]
+    fn worked_example() {
+        let plan = plan(200, 195, Some(12.0), Some(2.5), 2.0, ONE).unwrap();
+        assert_eq!(plan.days_left, 5);
+        assert_eq!(plan.available_l, Some(10.0));
+        assert_eq!(plan.daily_budget_l, Some(2.0));
+        assert_scales(&plan.scales, &[0.8]);
+        assert_eq!(
+            scale(PumpCommand::single(20), &plan).unwrap().duration_secs,
+            16
+        );
+
+        // Two zones of weight 2 and 1 sharing the same budget
+        let plan = super::plan(200, 195, Some(12.0), Some(2.5), 2.0, TWO).unwrap();
+        assert_scales(&plan.scales, &[1.0, 0.6]);
+    }
+
+    #[test]
+    fn days_count_across_the_new_year() {
+        assert_eq!(days_until(360, 5), 10);
+        assert_eq!(days_until(5, 360), 355);
+        assert_eq!(days_until(100, 100), 0);
+        assert_eq!(plan(100, 100, Some(10.0), Some(1.0), 0.0, ONE), None);
+    }
+
+    #[test]
+    fn a_leap_day_counts_as_the_last_of_its_year() {
+        assert_eq!(days_until(366, 365), 365);
+        assert_eq!(days_until(366, 1), 1);
+        assert_eq!(days_until(365, 366), 1);
+        assert_eq!(days_until(366, 366), 0);
+        let plan = plan(1, 366, Some(10.0), Some(1.0), 0.0, ONE).unwrap();
+        assert_eq!(plan.days_left, 1);
+    }
+
+    #[test]
+    fn runs_are_never_lengthened() {
+        let plan = plan(200, 195, Some(50.0), Some(2.0), 2.0, TWO).unwrap();
+        assert_scales(&plan.scales, &[1.0, 1.0]);
+        assert_eq!(
+            scale(PumpCommand::single(20), &plan).unwrap().duration_secs,
+            20
+        );
+    }
+
+    #[test]
+    fn runs_pass_while_the_tank_or_the_use_is_unknown() {
+        for (tank, used) in [
+            (None, Some(2.0)),
+            (Some(10.0), None),
+            (Some(10.0), Some(0.0)),
+        ] {
+            let plan = plan(200, 195, tank, used, 2.0, TWO).unwrap();
+            assert_scales(&plan.scales, &[1.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn below_the_reserve_nothing_is_left() {
+        let plan = plan(200, 195, Some(1.5), Some(2.0), 2.0, ONE).unwrap();
+        assert_eq!(plan.available_l, Some(0.0));
+        assert_scales(&plan.scales, &[0.0]);
+        assert!(scale(PumpCommand::single(20), &plan).is_none());
+    }
+
+    #[test]
+    fn the_scales_spend_the_budget() {
+        let zones = &[
+            Zone {
+                weight: 3.0,
+                use_share: 0.2,
+            },
+            Zone {
+                weight: 2.0,
+                use_share: 0.3,
+            },
+            Zone {
+                weight: 1.0,
+                use_share: 0.5,
+            },
+        ];
+        for budget in [0.2, 0.5, 1.0, 1.5, 1.9] {
+            let scales = zone_scales(budget, 2.0, zones);
+            assert_near(kept_l(&scales, 2.0, zones), budget);
+            // Heavier zones keep at least as much of their runs
+            assert!(scales[0] >= scales[1] && scales[1] >= scales[2]);
+            assert!(scales.iter().all(|&s| (0.0..=1.0).contains(&s)));
+        }
+        // 0.5 l of 0.4, 0.6 and 1 l: k = 0.5 / 3.4
+        assert_scales(
+            &zone_scales(0.5, 2.0, zones),
+            &[3.0 * 0.5 / 3.4, 2.0 * 0.5 / 3.4, 0.5 / 3.4],
+        );
+        // 1.2 l: the first is whole and the rest share 0.8 l, k = 0.8 / 2.2
+        assert_scales(
+            &zone_scales(1.2, 2.0, zones),
+            &[1.0, 2.0 * 0.8 / 2.2, 0.8 / 2.2],
+        );
+        // 1.5 l: then the second is whole too, and the last gets 0.5 l
+        assert_scales(&zone_scales(1.5, 2.0, zones), &[1.0, 1.0, 0.5]);
+    }
+
+    #[test]
+    fn equal_weights_cut_every_zone_alike() {
+        let zones = &[
+            Zone {
+                weight: 1.0,
+                use_share: 0.25,
+            },
+            Zone {
+                weight: 1.0,
+                use_share: 0.75,
+            },
+        ];
+        assert_scales(&zone_scales(1.0, 4.0, zones), &[0.25, 0.25]);
+    }
+
+    #[test]
+    fn zones_of_no_weight_get_what_is_left() {
+        let zones = &[
+            Zone {
+                weight: 1.0,
+                use_share: 0.5,
+            },
+            Zone {
+                weight: 0.0,
+                use_share: 0.5,
+            },
+        ];
+        assert_scales(&zone_scales(0.8, 2.0, zones), &[0.8, 0.0]);
+        assert_scales(&zone_scales(1.5, 2.0, zones), &[1.0, 0.5]);
+        assert_scales(&zone_scales(3.0, 2.0, zones), &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn a_zone_that_uses_nothing_is_whole() {
+        let zones = &[
+            Zone {
+                weight: 1.0,
+                use_share: 1.0,
+            },
+            Zone {
+                weight: 1.0,
+                use_share: 0.0,
+            },
+        ];
+        assert_scales(&zone_scales(1.0, 2.0, zones), &[0.5, 1.0]);
+    }
+
+    #[test]
+    fn each_run_gets_its_zones_share() {
+        let plan = plan_with(&[1.0, 0.6]);
+        let cmd = PumpCommand::single(20);
+        assert_eq!(scale(zone(cmd, 0), &plan).unwrap().duration_secs, 20);
+        let scaled = scale(zone(cmd, 1), &plan).unwrap();
+        assert_eq!((scaled.duration_secs, scaled.zone), (12, 1));
+        // An unknown zone gets the smallest share
+        assert_eq!(scale(zone(cmd, 7), &plan).unwrap().duration_secs, 12);
+    }
+
+    #[test]
+    fn scale_keeps_the_rest_of_the_command() {
+        let cmd = PumpCommand {
+            pulses: 3,
+            soak_secs: 600,
+            targeted: true,
+            ..PumpCommand::single(10)
+        };
+        let scaled = scale(cmd, &plan_with(&[0.5])).unwrap();
+        assert_eq!(
+            (
+                scaled.duration_secs,
+                scaled.pulses,
+                scaled.soak_secs,
+                scaled.targeted
+            ),
+            (5, 3, 600, true)
+        );
+        // Rounded to the nearest second, and dropped at none
+        assert_eq!(
+            scale(PumpCommand::single(3), &plan_with(&[0.5]))
+                .unwrap()
+                .duration_secs,
+            2
+        );
+        assert!(scale(PumpCommand::single(1), &plan_with(&[0.4])).is_none());
+    }
+}
@@ -0,0 +1,283 @@
+use crate::et;
+use crate::guards::{GuardSettings, Guards, SkipReason};
+use crate::light::{self, LightTracker};
+use crate::pump::{self, Program};
+use crate::types::{PumpCommand, SensorData};
+
+const DAY_SECS: u32 = 24 * 60 * 60;
+const YEAR_DAYS: u32 = 365;
+
+// Bounds on the ET scaling of a run, so a freak reading can't drown or
+// starve the plant
+const MIN_ET_FACTOR: f32 = 0.25;
+const MAX_ET_FACTOR: f32 = 2.0;
+
+/// Site and plant settings the run length depends on.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    pub pump_max_secs: u16,
+    pub latitude: f32,
+    // FAO-56 Kc of the plant, 1.0 for grass
+    pub crop_coefficient: f32,
+    // Crop ET in mm/day at which a run is used at its nominal length
+    pub reference_et_mm: f32,
+    // DLI of a clear day where the light sensor is, mol/m²; 0 to not cut
+    // runs after dull days
+    pub sunny_dli: f32,
+    pub guards: GuardSettings,
+}
+
+/// The program to run, or why it was vetoed.
+pub type Decision = Result<Program, SkipReason>;
+
+/// When and how long to water on our own, without a server command.
+#[derive(Clone, Copy)]
+pub struct Policy {
+    // Water once soil moisture drops below this percentage
+    pub dry_below: f32,
+    pub duration_secs: u16,
+    // Give the water time to soak in before judging the soil again
+    pub min_interval_ms: u64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            dry_below: 40.0,
+            duration_secs: 20,
+            min_interval_ms: 2 * 60 * 60 * 1000,
+        }
+    }
+}
+
+/// Threshold controller: one fixed run whenever the soil reads dry.
+pub struct Controller {
+    policy: Policy,
+    last_watered_ms: Option<u64>,
+}
+
+impl Controller {
+    pub const fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            last_watered_ms: None,
+        }
+    }
+
+    /// The run to make; none while the soil moisture is not known.
+    pub fn decide(&mut self, now_ms: u64, data: &SensorData) -> Option<PumpCommand> {
+        if data.soil_moisture? >= self.policy.dry_below {
+            return None;
+        }
+        if let Some(last) = self.last_watered_ms
+            && now_ms.saturating_sub(last) < self.policy.min_interval_ms
+        {
+            return None;
+        }
+
+        self.last_watered_ms = Some(now_ms);
+        Some(PumpCommand::single(self.policy.duration_secs))
+    }
+}
+
+/// Tracks the daily temperature range for the Hargreaves estimate, and the
+/// calendar day.
+///
+/// Days are counted from the first reading, so they follow uptime rather
+/// than midnight; only the range matters. The calendar day moves on every
+/// 24 h of uptime from when the server last named it, so it keeps going
+/// while the server can't be reached.
+#[derive(Clone, Copy, Default)]
+pub struct EtTracker {
+    pub(crate) window_start: Option<u32>,
+    // Range of the day in progress
+    pub(crate) t_min: f32,
+    pub(crate) t_max: f32,
+    // Range of the last complete day
+    pub(crate) last_day: Option<(f32, f32)>,
+    pub(crate) day_of_year: Option<u16>,
+    // Uptime at which `day_of_year` began, taken from the first reading
+    // after it was set
+    pub(crate) day_start: Option<u32>,
+}
+
+impl EtTracker {
+    pub const fn new() -> Self {
+        Self {
+            window_start: None,
+            t_min: f32::MAX,
+            t_max: f32::MIN,
+            last_day: None,
+            day_of_year: None,
+            day_start: None,
+        }
+    }
+
+    pub fn observe(&mut self, t_secs: u32, temperature: Option<f32>) {
+        let start = *self.window_start.get_or_insert(t_secs);
+        let elapsed = t_secs.saturating_sub(start);
+
+        if elapsed >= DAY_SECS {
+            if self.t_min <= self.t_max {
+                self.last_day = Some((self.t_min, self.t_max));
+            }
+            self.window_start = Some(start + elapsed / DAY_SECS * DAY_SECS);
+            self.t_min = f32::MAX;
+            self.t_max = f32::MIN;
+        }
+
+        if let Some(t) = temperature {
+            self.t_min = self.t_min.min(t);
+            self.t_max = self.t_max.max(t);
+        }
+
+        if let Some(day) = self.day_of_year {
+            let start = *self.day_start.get_or_insert(t_secs);
+            let days = t_secs.saturating_sub(start) / DAY_SECS;
+            if days > 0 {
+                self.day_of_year = Some(advance_day(day, days));
+                self.day_start = Some(start + days * DAY_SECS);
+            }
+        }
+    }
+
+    /// The calendar day, which the device only learns from the server.
+    pub fn set_day_of_year(&mut self, day_of_year: u16) {
+        self.day_of_year = Some(day_of_year);
+        self.day_start = None;
+    }
+
+    pub fn day_of_year(&self) -> Option<u16> {
+        self.day_of_year
+    }
+
+    /// Reference ET₀ in mm/day, once a full day and the date are known.
+    pub fn et0_mm(&self, latitude: f32) -> Option<f32> {
+        let (t_min, t_max) = self.last_day?;
+        let radiation = et::extraterrestrial_radiation(latitude, self.day_of_year?);
+        Some(et::hargreaves_et0(t_min, t_max, radiation))
+    }
+}
+
+/// The day of the year `days` after `day_of_year`. Day 366 only comes when
+/// the server sends it, so otherwise 365 is followed by 1.
+fn advance_day(day_of_year: u16, days: u32) -> u16 {
+    let year_days = YEAR_DAYS.max(day_of_year as u32);
+    ((day_of_year.max(1) as u32 - 1 + days) % year_days + 1) as u16
+}
+
+/// The program for `cmd`: pulses scaled by crop ET when it is known and
+/// cut after a dull day, then limited so the whole program stays within the
+/// pump maximum. Targeted runs are already sized from the soil and are only
+/// limited.
+pub fn plan_run(
+    cmd: PumpCommand,
+    settings: &Settings,
+    et0_mm: Option<f32>,
+    dli: Option<f32>,
+) -> Program {
+    let program = Program::from(cmd);
+    let et_factor = match et0_mm {
+        Some(et0) if settings.reference_et_mm > 0.0 => Some(
+            (et0 * settings.crop_coefficient / settings.reference_et_mm)
+                .clamp(MIN_ET_FACTOR, MAX_ET_FACTOR),
+        ),
+        _ => None,
+    };
+    let light_factor = match dli {
+        Some(dli) if settings.sunny_dli > 0.0 => Some(light::cloud_factor(dli, settings.sunny_dli)),
+        _ => None,
+    };
+    let scaled = match (et_factor, light_factor) {
+        _ if cmd.targeted => program,
+        (None, None) => program,
+        (et, light) => {
+            let factor = et.unwrap_or(1.0) * light.unwrap_or(1.0);
+            Program {
+                on_secs: (program.on_secs as f32 * factor + 0.5) as u16,
+                ..program
+            }
+        }
+    };
+    pump::limit(scaled, settings.pump_max_secs)
+}
+
+/// Everything the run decision depends on besides the command itself.
+#[derive(Clone, Copy, Default)]
+pub struct State {
+    pub et: EtTracker,
+    pub light: LightTracker,
+    pub guards: Guards,
+}
+
+impl State {
+    pub const fn new() -> Self {
+        Self {
+            et: EtTracker::new(),
+            light: LightTracker::new(),
+            guards: Guards::new(),
+        }
+    }
+
+    pub fn observe(&mut self, t_secs: u32, data: &SensorData, settings: &Settings) {
+        self.et.observe(t_secs, data.temperature);
+        self.light.observe(t_secs, data.lux);
+        self.guards.observe(t_secs, data, &settings.guards);
+    }
+
+    pub fn set_day_of_year(&mut self, day_of_year: u16) {
+        self.et.set_day_of_year(day_of_year);
+    }
+
+    pub fn et0_mm(&self, settings: &Settings) -> Option<f32> {
+        self.et.et0_mm(settings.latitude)
+    }
+
+    /// Passes `cmd` through the weather guards, then sizes the run.
+    pub fn decide(&mut self, t_secs: u32, cmd: PumpCommand, settings: &Settings) -> Decision {
+        self.guards.admit(t_secs, &settings.guards)?;
+        Ok(plan_run(
+            cmd,
+            settings,
+            self.et0_mm(settings),
+            self.light.dli(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn days_advance_across_the_new_year() {
+        assert_eq!(advance_day(10, 2), 12);
+        assert_eq!(advance_day(365, 1), 1);
+        assert_eq!(advance_day(366, 1), 1);
+        assert_eq!(advance_day(300, 100), 35);
+    }
+
+    #[test]
+    fn the_day_follows_uptime_from_when_it_was_set() {
+        let mut tracker = EtTracker::new();
+        tracker.observe(0, None);
+        tracker.observe(3 * DAY_SECS, None);
+        // Unknown days stay unknown
+        assert_eq!(tracker.day_of_year(), None);
+
+        tracker.set_day_of_year(365);
+        tracker.observe(3 * DAY_SECS + 100, None);
+        tracker.observe(4 * DAY_SECS + 99, None);
+        assert_eq!(tracker.day_of_year(), Some(365));
+        tracker.observe(4 * DAY_SECS + 100, None);
+        assert_eq!(tracker.day_of_year(), Some(1));
+        tracker.observe(6 * DAY_SECS + 100, None);
+        assert_eq!(tracker.day_of_year(), Some(3));
+
+        // The server's day wins, and the count starts again from it
+        tracker.set_day_of_year(2);
+        tracker.observe(7 * DAY_SECS, None);
+        tracker.observe(7 * DAY_SECS + 50, None);
+        assert_eq!(tracker.day_of_year(), Some(2));
+    }
+}
@@ -2,9 +2,20 @@ MEMORY {
     /*
      * The RP2350 has either external or internal flash.
      *
-     * 2 MiB is a safe default here, although a Pico 2 has 4 MiB.
+     * The Pico 2 W has 4 MiB, split for the embassy-boot bootloader
+     * (see bootloader/memory.x, which must agree with this layout):
+     *
+     *   BOOTLOADER        48K  the bootloader image
+     *   BOOTLOADER_STATE   4K  swap progress and boot confirmation
+     *   FLASH (ACTIVE)  2016K  this application
+     *   DFU             2020K  staging area for OTA images, one sector larger
+     *   STORAGE            8K  calibration and water totals, two copies
      */
-    FLASH : ORIGIN = 0x10000000, LENGTH = 4096K
+    BOOTLOADER       : ORIGIN = 0x10000000, LENGTH = 48K
+    BOOTLOADER_STATE : ORIGIN = 0x1000C000, LENGTH = 4K
+    FLASH            : ORIGIN = 0x1000D000, LENGTH = 2016K
+    DFU              : ORIGIN = 0x10205000, LENGTH = 2020K
+    STORAGE          : ORIGIN = 0x103FE000, LENGTH = 8K
     /*
      * RAM consists of 8 banks, SRAM0-SRAM7, with a striped mapping.
      * This is usually good for performance, as it distributes load on
@@ -21,6 +32,17 @@ MEMORY {
     SRAM9 : ORIGIN = 0x20081000, LENGTH = 4K
 }
 
+/* Partition offsets from the start of flash, used by the firmware updater */
+__bootloader_state_start = ORIGIN(BOOTLOADER_STATE) - ORIGIN(BOOTLOADER);
+__bootloader_state_end = ORIGIN(BOOTLOADER_STATE) + LENGTH(BOOTLOADER_STATE) - ORIGIN(BOOTLOADER);
+
+__bootloader_dfu_start = ORIGIN(DFU) - ORIGIN(BOOTLOADER);
+__bootloader_dfu_end = ORIGIN(DFU) + LENGTH(DFU) - ORIGIN(BOOTLOADER);
+
+/* Used by src/storage.rs; out of the bootloader's reach */
+__storage_start = ORIGIN(STORAGE) - ORIGIN(BOOTLOADER);
+__storage_end = ORIGIN(STORAGE) + LENGTH(STORAGE) - ORIGIN(BOOTLOADER);
+
 SECTIONS {
     /* ### Boot ROM info
      *
@@ -71,5 +93,18 @@ SECTIONS {
 
 } INSERT AFTER .uninit;
 
+SECTIONS {
+    /* ### Crash record
+     *
+     * Kept in SRAM9 so neither the runtime nor the bootloader, which only
+     * uses striped RAM, clears it across resets.
+     */
+    .crash_record (NOLOAD) : ALIGN(4)
+    {
+        KEEP(*(.crash_record));
+    } > SRAM9
+
+} INSERT AFTER .uninit;
+
 PROVIDE(start_to_end = __end_block_addr - __start_block_addr);
 PROVIDE(end_to_start = __start_block_addr - __end_block_addr);
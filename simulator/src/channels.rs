@@ -0,0 +1,13 @@
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use watering_core::types::{HttpRequest, PumpCommand};
+
+// HTTP request queue (capacity 4 - buffer a few requests)
+pub static HTTP_CHANNEL: Channel<CriticalSectionRawMutex, HttpRequest, 4> = Channel::new();
+
+// Pump command channel (capacity 1 - only latest command matters)
+pub static PUMP_CHANNEL: Channel<CriticalSectionRawMutex, PumpCommand, 1> = Channel::new();
+
+// Stops the running pump program
+pub static PUMP_CANCEL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
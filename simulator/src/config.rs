@@ -0,0 +1,157 @@
+use watering_core::forecast::ForecastSettings;
+use watering_core::guards::GuardSettings;
+use watering_core::light::DawnSettings;
+use watering_core::quality::{Limits, QualitySettings};
+use watering_core::response::ResponseSettings;
+use watering_core::station::StationSettings;
+use watering_core::tank::{TankGeometry, TankSettings, TankShape};
+use watering_core::target::TargetSettings;
+use watering_core::vacation::Zone;
+use watering_core::watering::Settings;
+
+pub const SENSOR_INTERVAL_MS: u64 = 5 * 1000; // 5 seconds
+pub const POLL_INTERVAL_SECS: u64 = 10;
+
+// Local development server
+pub const SERVER_ADDR: &str = "127.0.0.1:8080";
+pub const TASKS_ENDPOINT: &str = "/tasks";
+pub const SENSOR_ENDPOINT: &str = "/sensor";
+pub const EVENTS_ENDPOINT: &str = "/events";
+pub const API_KEY: &str = "simulator";
+
+pub const PUMP_MAX_DURATION_SECS: u16 = 30;
+pub const PUMP_ML_PER_SEC: f32 = 20.0; // calibration of the fake pump
+
+pub const SIM_ALTITUDE_M: f32 = 0.0;
+pub const SIM_SETTINGS: Settings = Settings {
+    pump_max_secs: PUMP_MAX_DURATION_SECS,
+    latitude: 50.0,
+    crop_coefficient: 1.0,
+    reference_et_mm: 4.0,
+    // A clear model day is 50.9 mol/m²
+    sunny_dli: 45.0,
+    guards: GuardSettings {
+        frost_below: 2.0,
+        humid_above: 95.0,
+        heat_wave_above: 30.0,
+        runs_per_day: 4,
+        heat_wave_extra_runs: 2,
+        rain_drop: 3.0,
+        rain_delay_secs: 6 * 60 * 60,
+    },
+};
+
+pub const SIM_RESPONSE: ResponseSettings = ResponseSettings {
+    window_secs: 30 * 60,
+    min_on_secs: 5,
+    min_rise_pct: 1.0,
+    saturated_pct: 90.0,
+};
+
+pub const SIM_FORECAST: ForecastSettings = ForecastSettings {
+    interval_secs: 30 * 60,
+    min_points: 4,
+    reset_rise_pct: 2.0,
+    max_hours: 7.0 * 24.0,
+};
+
+// Matches the tank of the soil model
+pub const SIM_TANK: TankSettings = TankSettings {
+    geometry: TankGeometry {
+        shape: TankShape::Box {
+            length_cm: 30.0,
+            width_cm: 20.0,
+        },
+        height_cm: 20.0 * 1000.0 / 600.0,
+        sensor_offset_cm: 5.0,
+    },
+    noise_l: 0.2,
+    refill_rise_l: 2.0,
+    reminder_days: 3.0,
+};
+
+// The device's limits, with stuck counts for 10 minute samples
+pub const SIM_QUALITY: QualitySettings = QualitySettings {
+    temperature: Limits {
+        min: -40.0,
+        max: 85.0,
+        max_rate_per_min: Some(3.0),
+        stuck_count: 12,
+    },
+    humidity: Limits {
+        min: 0.0,
+        max: 100.0,
+        max_rate_per_min: Some(20.0),
+        stuck_count: 12,
+    },
+    pressure: Limits {
+        min: 300.0,
+        max: 1100.0,
+        max_rate_per_min: Some(2.0),
+        stuck_count: 12,
+    },
+    soil_moisture: Limits {
+        min: 0.0,
+        max: 100.0,
+        max_rate_per_min: Some(20.0),
+        stuck_count: 0,
+    },
+    water_level: Limits {
+        min: 2.0,
+        max: 400.0,
+        max_rate_per_min: None,
+        stuck_count: 0,
+    },
+};
+
+// The device's 0.3 a minute has all but settled by the next 10 minute
+// sample
+pub const SIM_SOIL_EMA_ALPHA: f32 = 0.97;
+
+pub const SIM_VACATION_RESERVE_L: f32 = 1.0;
+// The model has one bed
+pub const SIM_VACATION_ZONES: &[Zone] = &[Zone {
+    weight: 1.0,
+    use_share: 1.0,
+}];
+
+pub const SIM_TARGET: TargetSettings = TargetSettings {
+    initial_gain: 0.3,
+    min_gain: 0.05,
+    max_gain: 5.0,
+    max_step: 0.25,
+    min_interval_secs: 30 * 60,
+    min_secs: 5,
+    max_secs: PUMP_MAX_DURATION_SECS,
+};
+
+// The device's dawn run; with 10 minute samples dawn is read within 10
+// minutes of the light reaching 50 lx
+pub const SIM_DAWN: DawnSettings = DawnSettings {
+    dawn_lux: 50.0,
+    min_night_secs: 4 * 60 * 60,
+    run_secs: 20,
+};
+
+pub const SIM_STATION: StationSettings = StationSettings {
+    watering: SIM_SETTINGS,
+    quality: SIM_QUALITY,
+    soil_ema_alpha: SIM_SOIL_EMA_ALPHA,
+    altitude_m: SIM_ALTITUDE_M,
+    response: SIM_RESPONSE,
+    target: SIM_TARGET,
+    tank: SIM_TANK,
+    forecast: SIM_FORECAST,
+    dawn: SIM_DAWN,
+    // The threshold controller's, `Policy::default().dry_below`
+    dry_below: 40.0,
+    vacation_reserve_l: SIM_VACATION_RESERVE_L,
+    vacation_zones: SIM_VACATION_ZONES,
+};
+
+// Accelerated runs (`simulate`)
+pub const SIM_SAMPLE_INTERVAL_MS: u64 = 10 * 60 * 1000; // 10 minutes
+pub const SIM_DEFAULT_DAYS: u64 = 28;
+pub const SIM_DEFAULT_START_DAY: u16 = 152; // 1 June
+// Record log kept by `simulate --record`, enough for several months
+pub const SIM_RECORD_CAPACITY: usize = 512 * 1024;
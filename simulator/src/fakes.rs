@@ -0,0 +1,98 @@
+use std::convert::Infallible;
+use std::sync::Mutex;
+
+use embassy_time::{Instant, Timer};
+use log::info;
+use watering_core::soil::{SOIL_DRY, SOIL_WET};
+use watering_core::sonar;
+use watering_core::traits::{Actuator, Clock, EnvReading, EnvSensor, RangeSensor, SoilProbe};
+
+// Raw counts the soil moves per reading while drying or being watered
+const DRYING_PER_READ: f32 = 5.0;
+const WETTING_PER_READ: f32 = 80.0;
+// Tank drop per reading while the pump runs
+const TANK_CM_PER_READ: f32 = 0.2;
+
+/// Garden state shared by the fake sensors and the fake pump.
+struct World {
+    soil_raw: f32,
+    water_distance_cm: f32,
+    pump_on: bool,
+}
+
+static WORLD: Mutex<World> = Mutex::new(World {
+    soil_raw: 2000.0,
+    water_distance_cm: 10.0,
+    pump_on: false,
+});
+
+pub struct FakeEnv;
+
+impl EnvSensor for FakeEnv {
+    type Error = Infallible;
+
+    async fn read(&mut self) -> Result<EnvReading, Infallible> {
+        Ok(EnvReading {
+            temperature: Some(22.5),
+            humidity: Some(55.0),
+            pressure: Some(1013.0),
+        })
+    }
+}
+
+pub struct FakeSoil;
+
+impl SoilProbe for FakeSoil {
+    type Error = Infallible;
+
+    async fn read_raw(&mut self) -> Result<u16, Infallible> {
+        let mut world = WORLD.lock().unwrap();
+        let step = if world.pump_on {
+            -WETTING_PER_READ
+        } else {
+            DRYING_PER_READ
+        };
+        world.soil_raw = (world.soil_raw + step).clamp(SOIL_WET as f32, SOIL_DRY as f32);
+        Ok(world.soil_raw as u16)
+    }
+}
+
+pub struct FakeRange;
+
+impl RangeSensor for FakeRange {
+    type Error = Infallible;
+
+    async fn echo_us(&mut self) -> Result<f32, Infallible> {
+        let mut world = WORLD.lock().unwrap();
+        if world.pump_on {
+            world.water_distance_cm += TANK_CM_PER_READ;
+        }
+        // In the air FakeEnv reports
+        Ok(sonar::echo_us(
+            world.water_distance_cm,
+            Some(22.5),
+            Some(55.0),
+        ))
+    }
+}
+
+pub struct FakePump;
+
+impl Actuator for FakePump {
+    fn set_on(&mut self, on: bool) {
+        info!("Pump relay {}", if on { "closed" } else { "open" });
+        WORLD.lock().unwrap().pump_on = on;
+    }
+}
+
+pub struct SimClock;
+
+impl Clock for SimClock {
+    fn now_ms(&self) -> u64 {
+        Instant::now().as_millis()
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        Timer::after_millis(ms).await;
+    }
+}
@@ -0,0 +1,130 @@
+//! Runs the watering tasks on the host with fake sensors and a fake pump,
+//! talking plain HTTP to a server on localhost.
+//!
+//! `watering-sim simulate [--days N] [--start-day N] [--seed N] [--target LOW-HIGH] [--vacation DAY] [--out FILE] [--record FILE]`
+//! instead runs the watering logic against a soil model at accelerated time
+//! and writes a CSV trace.
+//!
+//! `watering-sim replay FILE` checks a device record log against the
+//! current decision logic.
+
+mod channels;
+mod config;
+mod fakes;
+mod physics;
+mod replay;
+mod sim;
+mod tasks;
+mod transport;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use embassy_executor::Executor;
+use static_cell::StaticCell;
+use watering_core::record::{HEADER_LEN, Header, Log};
+use watering_core::target::Band;
+
+use crate::config::{SIM_DEFAULT_DAYS, SIM_DEFAULT_START_DAY, SIM_RECORD_CAPACITY, SIM_SETTINGS};
+
+const USAGE: &str = "usage: watering-sim [simulate [--days N] [--start-day N] [--seed N] [--target LOW-HIGH] [--vacation DAY] [--out FILE] [--record FILE] | replay FILE]";
+
+static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        None => run_live(),
+        Some("simulate") => match simulate(&args[1..]) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("simulate: {e}");
+                ExitCode::FAILURE
+            }
+        },
+        Some("replay") => match args.get(1).map(|path| replay::run(Path::new(path))) {
+            Some(Ok(0)) => ExitCode::SUCCESS,
+            Some(Ok(_)) => ExitCode::FAILURE,
+            Some(Err(e)) => {
+                eprintln!("replay: {e}");
+                ExitCode::FAILURE
+            }
+            None => {
+                eprintln!("{USAGE}");
+                ExitCode::FAILURE
+            }
+        },
+        Some(other) => {
+            eprintln!("unknown command `{other}`");
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_live() -> ! {
+    let executor = EXECUTOR.init(Executor::new());
+    executor.run(|spawner| {
+        spawner.spawn(tasks::http_task()).unwrap();
+        spawner.spawn(tasks::poll_task()).unwrap();
+        spawner.spawn(tasks::pump_task()).unwrap();
+        spawner.spawn(tasks::sensor_task()).unwrap();
+    })
+}
+
+fn simulate(args: &[String]) -> io::Result<()> {
+    let mut days = SIM_DEFAULT_DAYS;
+    let mut start_day = SIM_DEFAULT_START_DAY;
+    let mut seed = 1;
+    let mut target = None;
+    let mut vacation = None;
+    let mut out: Box<dyn Write> = Box::new(io::stdout().lock());
+    let mut record_path = None;
+
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_owned());
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        let value = args.next().ok_or_else(|| invalid("missing value"))?;
+        match flag.as_str() {
+            "--days" => days = value.parse().map_err(|_| invalid("bad --days"))?,
+            "--start-day" => start_day = value.parse().map_err(|_| invalid("bad --start-day"))?,
+            "--seed" => seed = value.parse().map_err(|_| invalid("bad --seed"))?,
+            "--target" => target = Some(parse_band(value).ok_or_else(|| invalid("bad --target"))?),
+            "--vacation" => vacation = Some(value.parse().map_err(|_| invalid("bad --vacation"))?),
+            "--out" => out = Box::new(File::create(value)?),
+            "--record" => record_path = Some(value),
+            _ => return Err(invalid("unknown flag")),
+        }
+    }
+
+    let mut out = BufWriter::new(out);
+    let mut log = Box::new(Log::<SIM_RECORD_CAPACITY>::new());
+    if !(1..=365).contains(&start_day) {
+        return Err(invalid("--start-day must be 1-365"));
+    }
+    if vacation.is_some_and(|day| !(1..=365).contains(&day)) {
+        return Err(invalid("--vacation must be 1-365"));
+    }
+    sim::run(days, start_day, seed, target, vacation, &mut out, &mut log)?;
+    out.flush()?;
+
+    if let Some(path) = record_path {
+        let header = Header {
+            settings: SIM_SETTINGS,
+        };
+        let mut export = vec![0; HEADER_LEN + SIM_RECORD_CAPACITY];
+        let len = log.export(header, &mut export);
+        std::fs::write(path, &export[..len])?;
+    }
+    Ok(())
+}
+
+// `LOW-HIGH` in percent, e.g. `40-50`
+fn parse_band(value: &str) -> Option<Band> {
+    let (low, high) = value.split_once('-')?;
+    Band::new(low.parse().ok()?, high.parse().ok()?)
+}
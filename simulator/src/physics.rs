@@ -0,0 +1,297 @@
+//! Bucket model of a potted plant, its weather and the water tank.
+
+use std::f32::consts::PI;
+
+use watering_core::soil::{SOIL_DRY, SOIL_WET};
+
+// Volumetric water content of a potting mix, m³/m³
+const SATURATION: f32 = 0.45;
+const FIELD_CAPACITY: f32 = 0.32;
+const WILTING_POINT: f32 = 0.10;
+
+// Reference evapotranspiration per kPa of vapour pressure deficit, mm/day
+const ET_MM_PER_KPA_DAY: f32 = 4.0;
+
+// Illuminance of the midday sun on a clear day
+const PEAK_LUX: f32 = 100_000.0;
+
+// Longest interval integrated in one go
+const STEP_MS: u64 = 60 * 1000;
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+#[derive(Clone, Copy)]
+pub struct Params {
+    pub pot_area_m2: f32,
+    pub root_depth_mm: f32,
+    pub crop_coefficient: f32,
+    // Share of the water above field capacity that drains away per hour
+    pub drainage_per_hour: f32,
+    pub pump_flow_l_per_min: f32,
+    pub tank_capacity_l: f32,
+    pub tank_area_cm2: f32,
+    // Sonar distance to the surface of a full tank
+    pub sonar_offset_cm: f32,
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Self {
+            pot_area_m2: 0.07,
+            root_depth_mm: 200.0,
+            crop_coefficient: 0.9,
+            drainage_per_hour: 0.3,
+            pump_flow_l_per_min: 1.2,
+            tank_capacity_l: 20.0,
+            tank_area_cm2: 600.0,
+            sonar_offset_cm: 5.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Conditions {
+    pub temperature: f32,
+    pub humidity: f32,
+    pub pressure: f32,
+    pub lux: f32,
+}
+
+/// Diurnal weather with a pseudo-random offset per day, reproducible from
+/// the seed.
+pub struct Weather {
+    seed: u32,
+    pub mean_temperature: f32,
+    pub temperature_swing: f32,
+    pub mean_humidity: f32,
+    pub humidity_swing: f32,
+}
+
+impl Weather {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            mean_temperature: 21.0,
+            temperature_swing: 6.0,
+            mean_humidity: 60.0,
+            humidity_swing: 20.0,
+        }
+    }
+
+    pub fn at(&self, time_ms: u64) -> Conditions {
+        let day = (time_ms / DAY_MS) as u32;
+        let hour = (time_ms % DAY_MS) as f32 / 3_600_000.0;
+        // Warmest mid-afternoon, most humid just before dawn
+        let diurnal = (2.0 * PI * (hour - 9.0) / 24.0).sin();
+
+        let temperature =
+            self.mean_temperature + 4.0 * self.day_noise(day, 0) + self.temperature_swing * diurnal;
+        let humidity = (self.mean_humidity + 15.0 * self.day_noise(day, 1)
+            - self.humidity_swing * diurnal)
+            .clamp(15.0, 98.0);
+        let pressure = 1013.0 + 8.0 * self.day_noise(day, 2);
+        // Sun from 6 to 18 h, through between 20 % and all of it on a day
+        let clear = 0.6 + 0.4 * self.day_noise(day, 3);
+        let sun = (2.0 * PI * (hour - 6.0) / 24.0).sin().max(0.0);
+
+        Conditions {
+            temperature,
+            humidity,
+            pressure,
+            lux: PEAK_LUX * sun * clear,
+        }
+    }
+
+    // Uniform in -1..1 for a given day and channel
+    fn day_noise(&self, day: u32, channel: u32) -> f32 {
+        let mut x = self.seed ^ day.wrapping_mul(0x9E37_79B9) ^ channel.wrapping_mul(0x85EB_CA6B);
+        x ^= x >> 16;
+        x = x.wrapping_mul(0x7FEB_352D);
+        x ^= x >> 15;
+        x = x.wrapping_mul(0x846C_A68B);
+        x ^= x >> 16;
+        x as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+/// Saturation vapour pressure in kPa (Tetens).
+fn saturation_vapour_pressure(temperature: f32) -> f32 {
+    0.6108 * (17.27 * temperature / (temperature + 237.3)).exp()
+}
+
+pub struct World {
+    pub params: Params,
+    pub weather: Weather,
+    pub time_ms: u64,
+    pub water_content: f32,
+    pub tank_l: f32,
+    pub pump_on: bool,
+}
+
+impl World {
+    pub fn new(params: Params, weather: Weather) -> Self {
+        Self {
+            params,
+            weather,
+            time_ms: 0,
+            water_content: FIELD_CAPACITY,
+            tank_l: params.tank_capacity_l,
+            pump_on: false,
+        }
+    }
+
+    pub fn conditions(&self) -> Conditions {
+        self.weather.at(self.time_ms)
+    }
+
+    pub fn advance(&mut self, mut ms: u64) {
+        while ms > 0 {
+            let dt = ms.min(STEP_MS);
+            self.step(dt);
+            self.time_ms += dt;
+            ms -= dt;
+        }
+    }
+
+    fn step(&mut self, dt_ms: u64) {
+        let p = self.params;
+        let hours = dt_ms as f32 / 3_600_000.0;
+
+        // Evapotranspiration, driven by VPD and only while the sun is up;
+        // the π makes the daylight factor average to one over a day
+        let c = self.conditions();
+        let vpd = saturation_vapour_pressure(c.temperature) * (1.0 - c.humidity / 100.0);
+        let hour = (self.time_ms % DAY_MS) as f32 / 3_600_000.0;
+        let daylight = (2.0 * PI * (hour - 6.0) / 24.0).sin().max(0.0) * PI;
+        let stress = ((self.water_content - WILTING_POINT) / (FIELD_CAPACITY - WILTING_POINT))
+            .clamp(0.0, 1.0);
+        let et_mm = ET_MM_PER_KPA_DAY * vpd * daylight * p.crop_coefficient * stress * hours / 24.0;
+        self.water_content -= et_mm / p.root_depth_mm;
+
+        // Free drainage above field capacity
+        if self.water_content > FIELD_CAPACITY {
+            let excess = self.water_content - FIELD_CAPACITY;
+            self.water_content -= excess * (p.drainage_per_hour * hours).min(1.0);
+        }
+
+        // Infiltration; whatever would oversaturate the pot runs off
+        if self.pump_on {
+            let litres = (p.pump_flow_l_per_min * hours * 60.0).min(self.tank_l);
+            self.tank_l -= litres;
+            // One litre over one square metre is one millimetre
+            let mm = litres / p.pot_area_m2;
+            self.water_content += mm / p.root_depth_mm;
+        }
+
+        self.water_content = self.water_content.clamp(0.0, SATURATION);
+    }
+
+    /// What the capacitive probe would read, linear between air and saturation.
+    pub fn soil_raw(&self) -> u16 {
+        let wet = self.water_content / SATURATION;
+        (SOIL_DRY as f32 - wet * (SOIL_DRY - SOIL_WET) as f32) as u16
+    }
+
+    pub fn water_distance_cm(&self) -> f32 {
+        let p = self.params;
+        p.sonar_offset_cm + (p.tank_capacity_l - self.tank_l) * 1000.0 / p.tank_area_cm2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR_MS: u64 = 60 * 60 * 1000;
+
+    fn world() -> World {
+        World::new(Params::default(), Weather::new(1))
+    }
+
+    #[test]
+    fn no_water_is_lost_at_night() {
+        let mut world = world();
+        // Midnight to dawn, at field capacity
+        world.advance(6 * HOUR_MS);
+        assert_eq!(world.water_content, FIELD_CAPACITY);
+    }
+
+    #[test]
+    fn a_wet_pot_drains_to_field_capacity() {
+        let mut world = world();
+        world.water_content = SATURATION;
+        let mut last = SATURATION;
+        for _ in 0..6 {
+            world.advance(HOUR_MS);
+            assert!(world.water_content < last);
+            assert!(world.water_content > FIELD_CAPACITY);
+            last = world.water_content;
+        }
+        // 30 % of the excess an hour leaves a sixth of it after six hours
+        let excess = (world.water_content - FIELD_CAPACITY) / (SATURATION - FIELD_CAPACITY);
+        assert!((0.1..0.25).contains(&excess), "{excess}");
+    }
+
+    #[test]
+    fn the_sun_dries_the_pot_down_to_the_wilting_point() {
+        let mut world = world();
+        world.advance(DAY_MS);
+        let after_a_day = world.water_content;
+        assert!(after_a_day < FIELD_CAPACITY);
+
+        // The plant takes less the drier the pot, and never all of it
+        world.advance(60 * DAY_MS);
+        assert!(world.water_content >= WILTING_POINT);
+        assert!(world.water_content < WILTING_POINT + 0.01);
+    }
+
+    #[test]
+    fn the_pump_moves_water_from_the_tank_to_the_pot() {
+        let mut world = world();
+        let full_cm = world.water_distance_cm();
+        assert_eq!(full_cm, world.params.sonar_offset_cm);
+
+        world.pump_on = true;
+        world.advance(60 * 1000);
+        assert!((world.tank_l - 18.8).abs() < 1e-4);
+        // 1.2 l over 600 cm² is 2 cm
+        assert!((world.water_distance_cm() - full_cm - 2.0).abs() < 1e-3);
+        assert!(world.water_content > FIELD_CAPACITY + 0.08);
+    }
+
+    #[test]
+    fn an_empty_tank_delivers_nothing() {
+        let mut world = world();
+        world.tank_l = 0.0;
+        world.pump_on = true;
+        world.advance(60 * 1000);
+        assert_eq!(world.tank_l, 0.0);
+        assert_eq!(world.water_content, FIELD_CAPACITY);
+    }
+
+    #[test]
+    fn the_probe_reads_between_air_and_water() {
+        let mut world = world();
+        world.water_content = 0.0;
+        assert_eq!(world.soil_raw(), SOIL_DRY);
+        world.water_content = SATURATION;
+        assert_eq!(world.soil_raw(), SOIL_WET);
+    }
+
+    #[test]
+    fn weather_follows_the_sun_and_the_seed() {
+        let weather = Weather::new(1);
+        assert_eq!(weather.at(3 * HOUR_MS).lux, 0.0);
+        assert_eq!(weather.at(20 * HOUR_MS).lux, 0.0);
+        let noon = weather.at(12 * HOUR_MS);
+        assert!(noon.lux > 0.2 * PEAK_LUX && noon.lux <= PEAK_LUX);
+        // Warmer in the afternoon than before dawn
+        assert!(weather.at(15 * HOUR_MS).temperature > weather.at(3 * HOUR_MS).temperature);
+
+        let again = Weather::new(1).at(12 * HOUR_MS);
+        assert_eq!(again.temperature, noon.temperature);
+        assert_ne!(
+            Weather::new(2).at(12 * HOUR_MS).temperature,
+            noon.temperature
+        );
+    }
+}
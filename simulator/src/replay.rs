@@ -0,0 +1,118 @@
+//! Feeds a device record log back through the decision logic and reports
+//! where today's logic decides differently.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use watering_core::record::{Entries, Event, Header, Mismatch, Replay};
+use watering_core::watering::Decision;
+
+/// Replays the log at `path` and returns the number of mismatches.
+///
+/// Takes either a binary export (as uploaded over HTTP) or a console
+/// capture containing `REC <hex>` lines.
+pub fn run(path: &Path) -> io::Result<usize> {
+    let raw = fs::read(path)?;
+    let bytes = if raw.starts_with(b"WREC") {
+        raw
+    } else {
+        parse_capture(&String::from_utf8_lossy(&raw))?
+    };
+
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, format!("{e:?}"));
+    let (header, body) = Header::decode(&bytes).map_err(invalid)?;
+    let settings = &header.settings;
+    println!(
+        "pump limit {} s, latitude {}, Kc {}, reference ET {} mm/day, sunny DLI {} mol/m²",
+        settings.pump_max_secs,
+        settings.latitude,
+        settings.crop_coefficient,
+        settings.reference_et_mm,
+        settings.sunny_dli
+    );
+    let guards = &settings.guards;
+    println!(
+        "frost below {} C, humid above {} %, heat wave above {} C, {}+{} runs/day, rain on {} hPa drop for {} s",
+        guards.frost_below,
+        guards.humid_above,
+        guards.heat_wave_above,
+        guards.runs_per_day,
+        guards.heat_wave_extra_runs,
+        guards.rain_drop,
+        guards.rain_delay_secs
+    );
+
+    let mut replay = Replay::new(header);
+    let (mut samples, mut commands, mut runs, mut skips, mut checkpoints) = (0, 0, 0, 0, 0);
+    let mut mismatches = 0;
+
+    for entry in Entries::new(body) {
+        let entry = entry.map_err(invalid)?;
+        match entry.event {
+            Event::Sample(_) => samples += 1,
+            Event::Command(_) => commands += 1,
+            Event::Pump(_) => runs += 1,
+            Event::Skip(_) => skips += 1,
+            Event::Checkpoint(_) => checkpoints += 1,
+        }
+        if let Some(mismatch) = replay.feed(&entry) {
+            report(&mismatch);
+            mismatches += 1;
+        }
+    }
+    if let Some(mismatch) = replay.finish() {
+        report(&mismatch);
+        mismatches += 1;
+    }
+
+    println!(
+        "{samples} samples, {commands} commands, {runs} pump runs, {skips} skips, {checkpoints} checkpoints, {mismatches} mismatches"
+    );
+    Ok(mismatches)
+}
+
+fn report(mismatch: &Mismatch) {
+    let describe = |decision: Option<Decision>| match decision {
+        Some(Ok(program)) if program.pulses > 1 => format!(
+            "run {} x {} s with {} s soak",
+            program.pulses, program.on_secs, program.soak_secs
+        ),
+        Some(Ok(program)) => format!("run {} s", program.on_secs),
+        Some(Err(reason)) => format!("skip ({})", reason.name()),
+        None => "no decision".to_owned(),
+    };
+    println!(
+        "t={}s: recorded {}, replay would {}",
+        mismatch.t_secs,
+        describe(mismatch.recorded),
+        describe(mismatch.expected)
+    );
+}
+
+// Collects the hex after every `REC ` marker; log prefixes are ignored
+fn parse_capture(text: &str) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for line in text.lines() {
+        let Some((_, rest)) = line.split_once("REC ") else {
+            continue;
+        };
+        let hex = rest.trim();
+        if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            // `REC end` and anything else that is not data
+            continue;
+        }
+        for pair in hex.as_bytes().chunks(2) {
+            let pair = std::str::from_utf8(pair).unwrap_or_default();
+            bytes.push(u8::from_str_radix(pair, 16).unwrap_or_default());
+        }
+    }
+
+    if bytes.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "no record data found",
+        ));
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,294 @@
+//! Runs the watering logic against the physics model at accelerated time
+//! and writes a CSV trace.
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::io::{self, Write};
+
+use embassy_futures::block_on;
+use watering_core::dosing::{self, Totals};
+use watering_core::light::LIGHT_SOURCES;
+use watering_core::record::{Entry, Event, Log};
+use watering_core::station::Station;
+use watering_core::target::Band;
+use watering_core::traits::{Actuator, Clock, EnvReading, EnvSensor, RangeSensor, SoilProbe};
+use watering_core::watering::{Controller, Policy};
+use watering_core::{pump, sensors, sonar};
+
+use crate::config::{SIM_SAMPLE_INTERVAL_MS, SIM_STATION, SIM_TARGET};
+use crate::physics::{Params, Weather, World};
+
+const DAY_MS: u64 = 24 * 60 * 60 * 1000;
+
+struct Env<'a>(&'a RefCell<World>);
+
+impl EnvSensor for Env<'_> {
+    type Error = Infallible;
+
+    async fn read(&mut self) -> Result<EnvReading, Infallible> {
+        let c = self.0.borrow().conditions();
+        Ok(EnvReading {
+            temperature: Some(c.temperature),
+            humidity: Some(c.humidity),
+            pressure: Some(c.pressure),
+        })
+    }
+}
+
+struct Soil<'a>(&'a RefCell<World>);
+
+impl SoilProbe for Soil<'_> {
+    type Error = Infallible;
+
+    async fn read_raw(&mut self) -> Result<u16, Infallible> {
+        Ok(self.0.borrow().soil_raw())
+    }
+}
+
+struct Range<'a>(&'a RefCell<World>);
+
+impl RangeSensor for Range<'_> {
+    type Error = Infallible;
+
+    async fn echo_us(&mut self) -> Result<f32, Infallible> {
+        // Sound travels at the speed of the model's air
+        let w = self.0.borrow();
+        let c = w.conditions();
+        Ok(sonar::echo_us(
+            w.water_distance_cm(),
+            Some(c.temperature),
+            Some(c.humidity),
+        ))
+    }
+}
+
+struct Pump<'a>(&'a RefCell<World>);
+
+impl Actuator for Pump<'_> {
+    fn set_on(&mut self, on: bool) {
+        self.0.borrow_mut().pump_on = on;
+    }
+}
+
+// Sleeping advances the model instead of waiting
+struct ModelClock<'a>(&'a RefCell<World>);
+
+impl Clock for ModelClock<'_> {
+    fn now_ms(&self) -> u64 {
+        self.0.borrow().time_ms
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        self.0.borrow_mut().advance(ms);
+    }
+}
+
+/// Runs the model for `days` from `start_day` (day of year), writing the
+/// trace to `out` and every sample, command and decision to `log`. Every
+/// sample goes through a [`Station`] like on the device, dawn runs
+/// included. With a `target` band, target-moisture mode waters instead of
+/// the threshold controller that stands in for the server; with a
+/// `vacation` end day, runs are cut to the tank budget until then.
+pub fn run<const N: usize>(
+    days: u64,
+    start_day: u16,
+    seed: u32,
+    target: Option<Band>,
+    vacation: Option<u16>,
+    out: &mut impl Write,
+    log: &mut Log<N>,
+) -> io::Result<()> {
+    let world = RefCell::new(World::new(Params::default(), Weather::new(seed)));
+    let mut controller = Controller::new(Policy::default());
+    let mut station = Station::new();
+    station.targeting.set_band(target);
+    station.vacation_until = vacation;
+    let mut totals = Totals::default();
+    // The model's pump, as a perfect calibration would measure it
+    let ml_per_sec = world.borrow().params.pump_flow_l_per_min * 1000.0 / 60.0;
+
+    let mut env = Env(&world);
+    let mut soil = Soil(&world);
+    let mut range = Range(&world);
+    let mut pump = Pump(&world);
+    let mut clock = ModelClock(&world);
+
+    writeln!(
+        out,
+        "hours,temperature_c,humidity_pct,vpd_kpa,et0_mm,soil_moisture_pct,hours_until_dry,water_content,pump_secs,skipped,response,target_gain,water_today_l,tank_l,tank_days_remaining,vacation_scale,water_level_cm,lux,dli_mol"
+    )?;
+
+    while clock.now_ms() < days * DAY_MS {
+        // The model starts at midnight, so the date turns with the model day
+        let day_of_year = ((start_day as u64 - 1 + clock.now_ms() / DAY_MS) % 365 + 1) as u16;
+        if station.state.et.day_of_year() != Some(day_of_year) {
+            station.state.set_day_of_year(day_of_year);
+            record(log, &clock, Event::Checkpoint(station.state));
+        }
+
+        let mut data = block_on(sensors::sample(&mut env, &mut soil, &mut range));
+        // The model's light sensor
+        data.telemetry
+            .push(LIGHT_SOURCES[0], world.borrow().conditions().lux);
+        station.refine((clock.now_ms() / 1000) as u32, &mut data, &SIM_STATION);
+        let t_secs = record(log, &clock, Event::Sample(data));
+        let observed = station.observe(t_secs, &mut data, &SIM_STATION);
+        let plan = station.plan_vacation(&SIM_STATION);
+
+        let mut pump_secs = 0;
+        let mut elapsed_secs = 0;
+        let mut skipped = "";
+        let cmd = match observed.command {
+            Some(cmd) => Some(cmd),
+            None if target.is_none() => controller.decide(clock.now_ms(), &data),
+            None => None,
+        };
+        if let Some(cmd) = cmd.and_then(|cmd| station.scale(cmd, &SIM_STATION)) {
+            let t_secs = record(log, &clock, Event::Command(cmd));
+            match station.decide(t_secs, cmd, &SIM_STATION) {
+                Ok(program) => {
+                    record(log, &clock, Event::Pump(program));
+                    let outcome = block_on(pump::run_program(
+                        &mut pump,
+                        &mut clock,
+                        program,
+                        &mut pump::Unattended,
+                    ));
+                    pump_secs = outcome.on_secs();
+                    totals.add(dosing::volume_ml(pump_secs, ml_per_sec), Some(day_of_year));
+                    station.watered((clock.now_ms() / 1000) as u32, pump_secs, &SIM_STATION);
+                    elapsed_secs = program.duration_secs();
+                }
+                Err(reason) => {
+                    skipped = reason.name();
+                    record(log, &clock, Event::Skip(reason));
+                }
+            }
+        }
+
+        let w = world.borrow();
+        writeln!(
+            out,
+            "{:.3},{:.1},{:.1},{:.2},{:.2},{:.1},{:.1},{:.3},{},{},{},{:.3},{:.2},{:.2},{:.1},{:.2},{:.1},{:.0},{:.1}",
+            w.time_ms as f32 / 3_600_000.0,
+            data.temperature.unwrap_or(f32::NAN),
+            data.humidity.unwrap_or(f32::NAN),
+            data.vpd.unwrap_or(f32::NAN),
+            data.et0.unwrap_or(f32::NAN),
+            data.soil_moisture.unwrap_or(f32::NAN),
+            data.hours_until_dry.unwrap_or(f32::NAN),
+            w.water_content,
+            pump_secs,
+            skipped,
+            observed
+                .check
+                .map_or("", |c| if c.responded { "ok" } else { "missing" }),
+            station.targeting.gain(&SIM_TARGET),
+            totals.today_ml(Some(day_of_year)) as f32 / 1000.0,
+            w.tank_l,
+            observed.tank.days_remaining.unwrap_or(f32::NAN),
+            plan.and_then(|plan| plan.scales.first().copied())
+                .unwrap_or(f32::NAN),
+            data.water_level.unwrap_or(f32::NAN),
+            data.lux.unwrap_or(f32::NAN),
+            data.dli.unwrap_or(f32::NAN)
+        )?;
+        drop(w);
+
+        // Stay on the sampling grid however long the pump ran
+        block_on(clock.sleep_ms(SIM_SAMPLE_INTERVAL_MS.saturating_sub(elapsed_secs as u64 * 1000)));
+    }
+
+    Ok(())
+}
+
+fn record<const N: usize>(log: &mut Log<N>, clock: &ModelClock, event: Event) -> u32 {
+    let entry = Entry {
+        t_secs: (clock.now_ms() / 1000) as u32,
+        event,
+    };
+    log.push(&entry);
+    entry.t_secs
+}
+
+#[cfg(test)]
+mod tests {
+    use watering_core::record::{Entries, HEADER_LEN, Header, Replay};
+
+    use super::*;
+    use crate::config::SIM_SETTINGS;
+
+    const CAPACITY: usize = 64 * 1024;
+
+    // The CSV trace and the exported record log of a run
+    fn simulate(
+        days: u64,
+        target: Option<Band>,
+        vacation: Option<u16>,
+    ) -> (Vec<Vec<f32>>, Vec<u8>) {
+        let mut out = Vec::new();
+        let mut log = Box::new(Log::<CAPACITY>::new());
+        run(days, 152, 7, target, vacation, &mut out, &mut log).unwrap();
+
+        let rows = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| {
+                line.split(',')
+                    .map(|field| field.parse().unwrap_or(f32::NAN))
+                    .collect()
+            })
+            .collect();
+        let mut export = vec![0; HEADER_LEN + CAPACITY];
+        let header = Header {
+            settings: SIM_SETTINGS,
+        };
+        let len = log.export(header, &mut export);
+        export.truncate(len);
+        (rows, export)
+    }
+
+    fn mismatches(export: &[u8]) -> usize {
+        let (header, body) = Header::decode(export).unwrap();
+        let mut replay = Replay::new(header);
+        let mut mismatches = 0;
+        for entry in Entries::new(body) {
+            mismatches += replay.feed(&entry.unwrap()).is_some() as usize;
+        }
+        mismatches + replay.finish().is_some() as usize
+    }
+
+    #[test]
+    fn the_record_replays_without_mismatches() {
+        for target in [None, Band::new(40.0, 50.0)] {
+            let (rows, export) = simulate(14, target, None);
+            assert!(rows.iter().any(|row| row[8] > 0.0), "no runs");
+            assert_eq!(mismatches(&export), 0);
+        }
+    }
+
+    #[test]
+    fn target_mode_keeps_the_soil_near_the_band() {
+        // The soil starts wet and takes a week to dry down to the band
+        let (rows, _) = simulate(14, Band::new(40.0, 50.0), None);
+        let first_run = rows.iter().position(|row| row[8] > 0.0).unwrap();
+        for row in &rows[first_run..] {
+            assert!(
+                (38.0..=52.0).contains(&row[5]),
+                "soil at {}% at {} h",
+                row[5],
+                row[0]
+            );
+        }
+    }
+
+    #[test]
+    fn a_vacation_ends_on_its_day() {
+        // From day 152 to the start of day 154
+        let (rows, _) = simulate(4, None, Some(154));
+        for row in &rows {
+            assert_eq!(!row[15].is_nan(), row[0] < 48.0, "at {} h", row[0]);
+        }
+    }
+}
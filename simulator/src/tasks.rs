@@ -0,0 +1,148 @@
+use embassy_time::{Duration, Timer};
+use log::{error, info};
+use watering_core::dosing;
+use watering_core::net::{self, Endpoints};
+use watering_core::pump::{self, Outcome, Program, Progress, Supervisor};
+use watering_core::sensors;
+use watering_core::types::{HttpRequest, SystemEvent};
+
+use crate::channels::{HTTP_CHANNEL, PUMP_CANCEL, PUMP_CHANNEL};
+use crate::config::{
+    EVENTS_ENDPOINT, POLL_INTERVAL_SECS, PUMP_MAX_DURATION_SECS, PUMP_ML_PER_SEC, SENSOR_ENDPOINT,
+    SENSOR_INTERVAL_MS, TASKS_ENDPOINT,
+};
+use crate::fakes::{FakeEnv, FakePump, FakeRange, FakeSoil, SimClock};
+use crate::transport::TcpTransport;
+
+const ENDPOINTS: Endpoints<'static> = Endpoints {
+    sensor: SENSOR_ENDPOINT,
+    alert: "/alert",
+    events: EVENTS_ENDPOINT,
+    tasks: TASKS_ENDPOINT,
+};
+
+#[embassy_executor::task]
+pub async fn sensor_task() {
+    let mut env = FakeEnv;
+    let mut soil = FakeSoil;
+    let mut range = FakeRange;
+
+    loop {
+        let data = sensors::sample(&mut env, &mut soil, &mut range).await;
+        info!(
+            "SM: {:?}%, WL: {:?}cm",
+            data.soil_moisture, data.water_level
+        );
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostSensorData(data))
+            .ok();
+
+        Timer::after_millis(SENSOR_INTERVAL_MS).await;
+    }
+}
+
+// Stops on a cancel from the server and reports each pulse and soak
+struct Attended;
+
+impl Supervisor for Attended {
+    fn cancelled(&mut self) -> bool {
+        PUMP_CANCEL.signaled()
+    }
+
+    fn progress(&mut self, progress: Progress) {
+        info!(
+            "Pump pulse {}/{}: {}",
+            progress.pulse,
+            progress.pulses,
+            progress.phase.name()
+        );
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::WateringProgress {
+                pulse: progress.pulse,
+                pulses: progress.pulses,
+                phase: progress.phase,
+            }))
+            .ok();
+    }
+}
+
+#[embassy_executor::task]
+pub async fn pump_task() {
+    let mut pump = FakePump;
+    let mut clock = SimClock;
+
+    loop {
+        let cmd = PUMP_CHANNEL.receive().await;
+
+        let program = pump::limit(Program::from(cmd), PUMP_MAX_DURATION_SECS);
+        info!(
+            "Pump program: {} x {} secs, {} secs soak",
+            program.pulses, program.on_secs, program.soak_secs
+        );
+
+        PUMP_CANCEL.reset();
+        let outcome = pump::run_program(&mut pump, &mut clock, program, &mut Attended).await;
+
+        let cancelled = matches!(outcome, Outcome::Cancelled { .. });
+        info!(
+            "Pump program {}, {} secs on",
+            if cancelled { "cancelled" } else { "done" },
+            outcome.on_secs()
+        );
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::WateringFinished {
+                on_secs: outcome.on_secs(),
+                cancelled,
+                volume_ml: Some(dosing::volume_ml(outcome.on_secs(), PUMP_ML_PER_SEC)),
+            }))
+            .ok();
+    }
+}
+
+#[embassy_executor::task]
+pub async fn http_task() {
+    let mut transport = TcpTransport;
+
+    loop {
+        let request = HTTP_CHANNEL.receive().await;
+
+        match net::exchange(&mut transport, &request, &ENDPOINTS).await {
+            Ok(exchange) => {
+                info!("Response: {}", exchange.status);
+
+                if let Some(actions) = exchange.actions {
+                    if actions.cancel_pump {
+                        info!("Pump program cancel received");
+                        PUMP_CANCEL.signal(());
+                    }
+                    let pump = actions.pump;
+                    if let Some(cmd) =
+                        pump.and_then(|cmd| dosing::resolve(cmd, Some(PUMP_ML_PER_SEC)))
+                    {
+                        info!(
+                            "Pump command received: {} x {} secs, {} secs soak",
+                            cmd.pulses, cmd.duration_secs, cmd.soak_secs
+                        );
+                        PUMP_CHANNEL.try_send(cmd).ok();
+                    }
+                    if actions.firmware_update {
+                        info!("Firmware update requested; ignored in the simulator");
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Request failed: {:?}", e);
+            }
+        }
+    }
+}
+
+#[embassy_executor::task]
+pub async fn poll_task() {
+    Timer::after(Duration::from_secs(1)).await;
+
+    loop {
+        HTTP_CHANNEL.send(HttpRequest::PollTasks).await;
+        Timer::after(Duration::from_secs(POLL_INTERVAL_SECS)).await;
+    }
+}
@@ -0,0 +1,73 @@
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+use watering_core::net::{ContentType, Method, Request, Response};
+use watering_core::traits::Transport;
+
+use crate::config::{API_KEY, SERVER_ADDR};
+
+/// Plain HTTP/1.1 over a blocking socket, one connection per request.
+///
+/// Blocking is fine here: the simulator has no deadlines to miss.
+pub struct TcpTransport;
+
+impl Transport for TcpTransport {
+    type Error = io::Error;
+
+    async fn send(&mut self, request: &Request, body: &mut [u8]) -> Result<Response, io::Error> {
+        let mut stream = TcpStream::connect(SERVER_ADDR)?;
+
+        let method = match request.method {
+            Method::Get => "GET",
+            Method::Post => "POST",
+        };
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nX-Api-Key: {}\r\nConnection: close\r\n",
+            method,
+            request.path.as_str(),
+            SERVER_ADDR,
+            API_KEY
+        )?;
+        if !request.body.is_empty() {
+            let content_type = match request.content_type {
+                ContentType::Json => "application/json",
+                ContentType::OctetStream => "application/octet-stream",
+            };
+            write!(
+                stream,
+                "Content-Type: {}\r\nContent-Length: {}\r\n",
+                content_type,
+                request.body.len()
+            )?;
+        }
+        stream.write_all(b"\r\n")?;
+        stream.write_all(&request.body)?;
+
+        let mut reply = Vec::new();
+        stream.read_to_end(&mut reply)?;
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response");
+        let header_end = reply
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(invalid)?;
+        let status = std::str::from_utf8(&reply[..header_end])
+            .ok()
+            .and_then(|head| head.split(' ').nth(1))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(invalid)?;
+
+        // Like the device, a reply that does not fit is an error, not cut
+        let mut len = 0;
+        if (200..300).contains(&status) && !body.is_empty() {
+            let payload = &reply[header_end + 4..];
+            len = payload.len();
+            body.get_mut(..len)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "reply too long"))?
+                .copy_from_slice(payload);
+        }
+
+        Ok(Response { status, len })
+    }
+}
@@ -0,0 +1,26 @@
+use embassy_time::Duration;
+
+use crate::config::{INIT_BACKOFF_MAX_SECS, INIT_BACKOFF_MIN_SECS};
+
+// Exponential retry delay for re-initializing flaky peripherals
+pub struct Backoff {
+    current_secs: u64,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self {
+            current_secs: INIT_BACKOFF_MIN_SECS,
+        }
+    }
+
+    pub fn next(&mut self) -> Duration {
+        let delay = Duration::from_secs(self.current_secs);
+        self.current_secs = (self.current_secs * 2).min(INIT_BACKOFF_MAX_SECS);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current_secs = INIT_BACKOFF_MIN_SECS;
+    }
+}
@@ -0,0 +1,71 @@
+use embassy_rp::gpio::{Flex, Pull};
+use embassy_rp::i2c::{self, Config as I2cConfig};
+use embassy_rp::peripherals::{I2C1, PIN_26, PIN_27};
+use embassy_time::Timer;
+use log::warn;
+
+use crate::{I2cBus, Irqs};
+
+// A slave stuck mid-byte releases SDA after at most 9 clocks
+const RECOVERY_CLOCKS: u8 = 9;
+const HALF_PERIOD_US: u64 = 5;
+
+/// Frees a bus held low by a slave and rebuilds the I2C peripheral.
+///
+/// The bus mutex is held for the whole procedure, so the pins can be
+/// taken over as GPIO without racing the other I2C users.
+pub async fn recover(bus: &'static I2cBus) {
+    let mut i2c = bus.lock().await;
+    warn!("I2C bus recovery: clocking out SCL");
+
+    {
+        // SAFETY: the bus lock is held and the peripheral is rebuilt below
+        let mut scl = Flex::new(unsafe { PIN_27::steal() });
+        let mut sda = Flex::new(unsafe { PIN_26::steal() });
+
+        // Open-drain emulation: low when output, pulled up when input
+        for pin in [&mut scl, &mut sda] {
+            pin.set_pull(Pull::Up);
+            pin.set_low();
+            pin.set_as_input();
+        }
+        Timer::after_micros(HALF_PERIOD_US).await;
+
+        for _ in 0..RECOVERY_CLOCKS {
+            if sda.is_high() {
+                break;
+            }
+            scl.set_as_output();
+            Timer::after_micros(HALF_PERIOD_US).await;
+            scl.set_as_input();
+            Timer::after_micros(HALF_PERIOD_US).await;
+        }
+
+        // STOP condition: SDA rises while SCL is high
+        scl.set_as_output();
+        sda.set_as_output();
+        Timer::after_micros(HALF_PERIOD_US).await;
+        scl.set_as_input();
+        Timer::after_micros(HALF_PERIOD_US).await;
+        sda.set_as_input();
+        Timer::after_micros(HALF_PERIOD_US).await;
+
+        if sda.is_low() {
+            warn!("I2C bus recovery: SDA still held low");
+        }
+    }
+
+    // SAFETY: same pins and instance as in main, exclusively owned via the lock
+    let fresh = unsafe {
+        i2c::I2c::new_async(
+            I2C1::steal(),
+            PIN_27::steal(),
+            PIN_26::steal(),
+            Irqs,
+            I2cConfig::default(),
+        )
+    };
+    // The old driver holds no resources; forgetting it keeps its drop from
+    // touching the pins the fresh one now owns.
+    core::mem::forget(core::mem::replace(&mut *i2c, fresh));
+}
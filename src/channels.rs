@@ -1,7 +1,7 @@
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::channel::Channel;
-
-use crate::types::{HttpRequest, PumpCommand, SensorData};
+use embassy_sync::signal::Signal;
+use watering_core::types::{HttpRequest, PumpCommand, SensorData};
 
 // Sensor data to display (capacity 1 - only latest reading matters)
 pub static SENSOR_CHANNEL: Channel<CriticalSectionRawMutex, SensorData, 1> = Channel::new();
@@ -11,3 +11,12 @@ pub static HTTP_CHANNEL: Channel<CriticalSectionRawMutex, HttpRequest, 4> = Chan
 
 // Pump command channel (capacity 1 - only latest command matters)
 pub static PUMP_CHANNEL: Channel<CriticalSectionRawMutex, PumpCommand, 1> = Channel::new();
+
+// Stops the running pump program (console `stop` or the server)
+pub static PUMP_CANCEL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// Starts a pump calibration run (console `calibrate` or the server)
+pub static PUMP_CALIBRATE: Signal<CriticalSectionRawMutex, ()> = Signal::new();
+
+// Raised once a freshly updated image has talked to the server
+pub static OTA_CONFIRMED: Signal<CriticalSectionRawMutex, ()> = Signal::new();
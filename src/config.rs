@@ -1,3 +1,7 @@
+use watering_core::quality::Limits;
+use watering_core::tank::TankShape;
+use watering_core::vacation::Zone;
+
 pub const SENSOR_INTERVAL_MS: u64 = 1 * 60 * 1000; // 1 minutes
 pub const POLL_INTERVAL_SECS: u64 = 30;
 
@@ -7,6 +11,143 @@ pub const WIFI_PASSWORD: &str = "";
 pub const SERVER_URL: &str = "";
 pub const TASKS_ENDPOINT: &str = "";
 pub const SENSOR_ENDPOINT: &str = "";
+pub const EVENTS_ENDPOINT: &str = "";
+pub const RECORD_ENDPOINT: &str = "";
 pub const API_KEY: &str = "";
 
+pub const OTA_MANIFEST_ENDPOINT: &str = "";
+pub const OTA_IMAGE_ENDPOINT: &str = "";
+// Ed25519 key that firmware images are signed with
+pub const OTA_PUBLIC_KEY: [u8; 32] = [0; 32];
+pub const OTA_CONFIRM_TIMEOUT_SECS: u64 = 5 * 60;
+
 pub const PUMP_MAX_DURATION_SECS: u16 = 30;
+pub const CALIBRATION_SECS: u16 = 20; // pump run for measuring the flow rate
+
+// Hall-effect flow meter on GPIO18
+pub const FLOW_PULSES_PER_LITRE: f32 = 0.0; // 0 = no meter fitted; YF-S201: 450
+pub const FLOW_PRIME_SECS: u16 = 5; // before no flow counts, and the window it is judged over
+pub const FLOW_MIN_ML_PER_SEC: f32 = 2.0;
+pub const FLOW_DRAIN_SECS: u16 = 10; // line still running after the pump stops
+pub const FLOW_LEAK_ML: u32 = 20; // per window with the pump off
+pub const FLOW_LEAK_WINDOW_SECS: u32 = 60 * 60;
+
+// Soil moisture rise expected after each run
+pub const RESPONSE_WINDOW_SECS: u32 = 30 * 60;
+pub const RESPONSE_MIN_ON_SECS: u32 = 5; // shorter runs are not checked
+pub const RESPONSE_MIN_RISE_PCT: f32 = 1.0; // until the typical rise is learned
+pub const RESPONSE_SATURATED_PCT: f32 = 90.0; // wetter soil is not checked
+
+// Soil drying forecast
+pub const DRY_BELOW_PCT: f32 = 40.0; // forecast threshold outside target mode
+pub const FORECAST_INTERVAL_SECS: u32 = 30 * 60; // readings averaged per trend point
+pub const FORECAST_MIN_POINTS: u8 = 4;
+pub const FORECAST_RESET_RISE_PCT: f32 = 2.0; // watering or rain starts a new trend
+pub const FORECAST_MAX_HOURS: f32 = 7.0 * 24.0; // slower drying reads as not drying
+
+// Target-moisture mode; the band itself comes from the server
+pub const TARGET_INITIAL_GAIN: f32 = 0.3; // % moisture per pump second, until learned
+pub const TARGET_MIN_GAIN: f32 = 0.05;
+pub const TARGET_MAX_GAIN: f32 = 5.0;
+pub const TARGET_MAX_STEP: f32 = 0.25; // largest gain change per run, as a fraction
+
+// Water tank under the sonar
+pub const TANK_SHAPE: TankShape = TankShape::Cylinder { diameter_cm: 30.0 };
+pub const TANK_HEIGHT_CM: f32 = 40.0; // water depth when full
+pub const TANK_SENSOR_OFFSET_CM: f32 = 5.0; // sonar to the surface of a full tank
+pub const TANK_NOISE_L: f32 = 0.2; // smaller level changes are sonar jitter
+pub const TANK_REFILL_RISE_L: f32 = 2.0;
+pub const TANK_REMINDER_DAYS: f32 = 3.0; // remind with fewer days of water left
+
+// Vacation mode; the end day comes from the server or the console
+pub const VACATION_RESERVE_L: f32 = 1.0; // kept back in the tank, not budgeted
+// One per `pump_zone` the server sends, up to 4; a short budget is shared
+// by weight, and the use shares add up to 1
+pub const VACATION_ZONES: &[Zone] = &[Zone {
+    weight: 1.0,
+    use_share: 1.0,
+}];
+
+pub const ALTITUDE_M: f32 = 0.0; // for the sea-level pressure
+
+// Capacitive soil probe, powered from GPIO19 only while measuring
+pub const SOIL_SETTLE_MS: u64 = 100; // after power-up, before the first sample
+pub const SOIL_SAMPLES: usize = 16; // ADC readings per measurement
+pub const SOIL_TRIM: usize = 4; // lowest and highest left out of the mean
+pub const SOIL_EMA_ALPHA: f32 = 0.3; // 1 = no filtering
+
+// Plausibility checks on each reading; failing fields are dropped
+pub const QUALITY_TEMPERATURE: Limits = Limits {
+    min: -40.0,
+    max: 85.0,
+    max_rate_per_min: Some(3.0),
+    stuck_count: 120, // readings, two hours at SENSOR_INTERVAL_MS
+};
+pub const QUALITY_HUMIDITY: Limits = Limits {
+    min: 0.0,
+    max: 100.0,
+    max_rate_per_min: Some(20.0),
+    stuck_count: 120,
+};
+pub const QUALITY_PRESSURE: Limits = Limits {
+    min: 300.0,
+    max: 1100.0,
+    max_rate_per_min: Some(2.0),
+    stuck_count: 120,
+};
+// Soil can hold steady for hours overnight, so it is not checked for sticking
+pub const QUALITY_SOIL_MOISTURE: Limits = Limits {
+    min: 0.0,
+    max: 100.0,
+    max_rate_per_min: Some(20.0),
+    stuck_count: 0,
+};
+// 0 cm and beyond 4 m are sonar glitches; refills may be as fast as they like
+pub const QUALITY_WATER_LEVEL: Limits = Limits {
+    min: 2.0,
+    max: 400.0,
+    max_rate_per_min: None,
+    stuck_count: 0,
+};
+
+// HC-SR04 sonar; distances are compensated for the BME280 air temperature
+pub const SONAR_PINGS: u8 = 5; // averaged per reading
+pub const SONAR_ECHO_TIMEOUT_MS: u64 = 30;
+pub const SONAR_MIN_ECHO_US: u64 = 120; // about 2 cm
+pub const SONAR_MAX_ECHO_US: u64 = 23_000; // about 4 m
+
+// Evapotranspiration scaling of pump runs
+pub const LATITUDE_DEG: f32 = 50.0; // negative in the southern hemisphere
+pub const CROP_COEFFICIENT: f32 = 1.0; // FAO-56 Kc of the plant
+pub const REFERENCE_ET_MM: f32 = 4.0; // crop ET/day at which runs keep their length
+
+// Daily light integral from a BH1750 on the I2C bus
+pub const SUNNY_DLI: f32 = 40.0; // mol/m² of a clear day at the sensor; 0 = no cut
+pub const DAWN_LUX: f32 = 50.0;
+pub const DAWN_MIN_NIGHT_SECS: u32 = 4 * 60 * 60; // dark before, so a cloud is no dawn
+pub const DAWN_RUN_SECS: u16 = 20; // at dawn if the soil is dry, outside target mode; 0 = off
+
+// Weather guards that can skip a pump run
+pub const FROST_BELOW_C: f32 = 2.0;
+pub const HUMID_ABOVE_PCT: f32 = 95.0;
+pub const HEAT_WAVE_ABOVE_C: f32 = 30.0; // starts 24 h of extra runs
+pub const RUNS_PER_DAY: u8 = 4;
+pub const HEAT_WAVE_EXTRA_RUNS: u8 = 2;
+pub const RAIN_PRESSURE_DROP_HPA: f32 = 3.0; // fall over three hours
+pub const RAIN_DELAY_SECS: u32 = 6 * 60 * 60;
+
+// Bytes of record log kept for replay, about 10 hours of samples
+pub const RECORD_CAPACITY: usize = 16 * 1024;
+
+pub const I2C_TIMEOUT_MS: u64 = 500;
+pub const INA219_SHUNT_OHMS: f32 = 0.1; // the usual breakout board
+pub const ADC_TIMEOUT_MS: u64 = 100;
+pub const ONEWIRE_TIMEOUT_MS: u64 = 10; // per slot, which takes under 1 ms
+pub const INIT_BACKOFF_MIN_SECS: u64 = 1;
+pub const INIT_BACKOFF_MAX_SECS: u64 = 300;
+pub const MAX_CONSECUTIVE_FAILURES: u8 = 3;
+
+pub const WATCHDOG_TIMEOUT_MS: u64 = 8000;
+pub const WATCHDOG_FEED_INTERVAL_MS: u64 = 1000;
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 2;
+pub const HTTP_DEADLINE_SECS: u64 = 120;
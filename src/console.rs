@@ -0,0 +1,162 @@
+use core::fmt::Write;
+
+use embassy_time::{Instant, Timer};
+use heapless::String;
+use log::info;
+use watering_core::target::Band;
+
+use crate::channels::{PUMP_CALIBRATE, PUMP_CANCEL};
+use crate::config::RESPONSE_WINDOW_SECS;
+use crate::crash;
+use crate::decision;
+use crate::dosing;
+use crate::faults;
+use crate::recorder;
+use crate::response;
+use crate::tank;
+use crate::target;
+use crate::vacation;
+
+// Record bytes per console line
+const RECORD_LINE_BYTES: usize = 32;
+
+/// Handles one line typed on the USB serial console.
+pub async fn handle_line(line: &str) {
+    match line.trim() {
+        "" => {}
+        "status" => print_status(),
+        "record" => dump_record().await,
+        "stop" => {
+            info!("Stopping the pump program");
+            PUMP_CANCEL.signal(());
+        }
+        "calibrate" => {
+            info!("Starting a pump calibration run");
+            PUMP_CALIBRATE.signal(());
+        }
+        "target off" => target::set_band(None),
+        "vacation off" => vacation::set(None),
+        "reboot" => crash::reboot(),
+        other => {
+            if let Some(Ok(ml)) = other.strip_prefix("calibrate ").map(|ml| ml.trim().parse()) {
+                dosing::calibrate(ml);
+            } else if let Some(band) = other.strip_prefix("target ").and_then(parse_band) {
+                target::set_band(Some(band));
+            } else if let Some(Ok(day @ 1..=365)) = other
+                .strip_prefix("vacation ")
+                .map(|day| day.trim().parse::<u16>())
+            {
+                vacation::set(Some(day));
+            } else {
+                info!(
+                    "Unknown command: {} (try: status, record, stop, calibrate [ml], target <low> <high>|off, vacation <day>|off, reboot)",
+                    other
+                );
+            }
+        }
+    }
+}
+
+// `<low> <high>` in percent
+fn parse_band(args: &str) -> Option<Band> {
+    let mut args = args.split_whitespace();
+    let low = args.next()?.parse().ok()?;
+    let high = args.next()?.parse().ok()?;
+    Band::new(low, high)
+}
+
+// Prints the record log as `REC <hex>` lines for `watering-sim replay`
+async fn dump_record() {
+    let record = recorder::snapshot().await;
+    info!("Record log: {} bytes", record.len());
+
+    for chunk in record.chunks(RECORD_LINE_BYTES) {
+        let mut line: String<{ 4 + 2 * RECORD_LINE_BYTES }> = String::new();
+        let _ = line.push_str("REC ");
+        for byte in chunk {
+            let _ = write!(line, "{:02x}", byte);
+        }
+        info!("{}", line.as_str());
+        // Let the USB logger drain before the next line
+        Timer::after_millis(5).await;
+    }
+
+    info!("REC end");
+}
+
+fn print_status() {
+    info!("Uptime: {} s", Instant::now().as_secs());
+
+    if let Some(boot) = crash::boot_report() {
+        info!(
+            "Boot #{}, last reset: {}",
+            boot.boot_count,
+            boot.reset_reason.name()
+        );
+        if let Some(task) = boot.stalled_task {
+            info!("Stalled task: {}", task.name());
+        }
+        if let Some(panic) = &boot.panic {
+            info!(
+                "Last panic at {}:{}: {}",
+                panic.file.as_str(),
+                panic.line,
+                panic.message.as_str()
+            );
+        }
+    }
+
+    let totals = dosing::totals();
+    match dosing::ml_per_sec() {
+        Some(rate) => info!("Pump: {} ml/s", rate),
+        None => info!("Pump: not calibrated"),
+    }
+    info!(
+        "Water delivered: {} ml today, {} ml in total",
+        totals.today_ml(decision::day_of_year()),
+        totals.total_ml
+    );
+
+    match response::curve() {
+        Some(curve) => info!(
+            "Soil response, %/s over {} min: {:?}",
+            RESPONSE_WINDOW_SECS / 60,
+            curve
+        ),
+        None => info!("Soil response: not learned yet"),
+    }
+
+    let tank = tank::status();
+    match (tank.volume_l, tank.days_remaining) {
+        (Some(volume), Some(days)) => info!(
+            "Tank: {} l, {:?} l/day, {} days left",
+            volume, tank.daily_use_l, days
+        ),
+        (Some(volume), None) => info!("Tank: {} l, daily use not learned yet", volume),
+        (None, _) => info!("Tank: no sonar reading"),
+    }
+
+    let (band, gain) = target::status();
+    match band {
+        Some(band) => info!(
+            "Target moisture: {}-{}%, gain {} %/s",
+            band.low, band.high, gain
+        ),
+        None => info!("Target moisture mode off"),
+    }
+
+    // The plan first: it ends a vacation that is over
+    match (vacation::plan(), vacation::until_day()) {
+        (Some(plan), _) => info!(
+            "Vacation until day {}: {} days left, {:?} l a day, runs kept by zone {:?}",
+            plan.until_day, plan.days_left, plan.daily_budget_l, plan.scales
+        ),
+        (None, Some(day)) => info!("Vacation until day {}, date not known yet", day),
+        (None, None) => info!("Vacation mode off"),
+    }
+
+    match faults::first_active() {
+        Some(fault) => info!("Fault: {}", fault.message()),
+        None => info!("No active faults"),
+    }
+}
@@ -0,0 +1,134 @@
+use core::fmt::Write;
+use core::mem::MaybeUninit;
+use core::panic::PanicInfo;
+
+use cortex_m::peripheral::SCB;
+use cortex_m_rt::{ExceptionFrame, exception};
+use embassy_rp::pac;
+use embassy_sync::once_lock::OnceLock;
+use heapless::String;
+use watering_core::diagnostics::{CrashRecord, PanicReport, RECORD_SIZE, ResetReason, Truncating};
+
+use crate::safety;
+use crate::tasks::watchdog::WatchdogReset;
+
+// Lives in SRAM9 (see memory.x): not zeroed by the runtime and untouched by
+// the bootloader, so it survives software, watchdog and OTA resets
+#[unsafe(link_section = ".crash_record")]
+static mut CRASH_RECORD: MaybeUninit<[u8; RECORD_SIZE]> = MaybeUninit::uninit();
+
+// What the current boot found, kept for the console and the boot event
+static BOOT_REPORT: OnceLock<CrashRecord> = OnceLock::new();
+
+fn load() -> Option<CrashRecord> {
+    // SAFETY: plain bytes; garbage after power-on is rejected by the checksum
+    let buf = unsafe {
+        (&raw const CRASH_RECORD)
+            .cast::<[u8; RECORD_SIZE]>()
+            .read_volatile()
+    };
+    CrashRecord::decode(&buf).ok()
+}
+
+fn store(record: &CrashRecord) {
+    // SAFETY: only written from main before tasks start and from handlers that never return
+    unsafe {
+        (&raw mut CRASH_RECORD)
+            .cast::<[u8; RECORD_SIZE]>()
+            .write_volatile(record.encode())
+    };
+}
+
+/// Works out why the previous boot ended and starts this boot's record.
+pub fn boot(watchdog: Option<WatchdogReset>) -> &'static CrashRecord {
+    let previous = load();
+
+    let stalled_task = match watchdog {
+        Some(WatchdogReset::Stalled(task)) => Some(task),
+        _ => None,
+    };
+
+    let reset_reason = match &previous {
+        Some(record) if record.panic.is_some() => ResetReason::Panic,
+        _ if watchdog.is_some() => ResetReason::Watchdog,
+        Some(record) if record.software_reset => ResetReason::Software,
+        // The chip reset flags only latch chip-level resets and stay set
+        // across core resets, so they come after everything we record ourselves
+        _ if pac::POWMAN.chip_reset().read().had_bor() => ResetReason::Brownout,
+        Some(_) => ResetReason::External,
+        None => ResetReason::PowerOn,
+    };
+
+    let report = CrashRecord {
+        boot_count: previous
+            .as_ref()
+            .map_or(1, |record| record.boot_count.wrapping_add(1)),
+        reset_reason,
+        stalled_task,
+        panic: previous.and_then(|record| record.panic),
+        software_reset: false,
+    };
+
+    store(&CrashRecord {
+        panic: None,
+        ..report.clone()
+    });
+
+    BOOT_REPORT.get_or_init(|| report)
+}
+
+pub fn boot_report() -> Option<&'static CrashRecord> {
+    BOOT_REPORT.try_get()
+}
+
+/// Resets the chip, recording the reset as deliberate.
+pub fn reboot() -> ! {
+    safety::force_actuators_off();
+    if let Some(mut record) = load() {
+        record.software_reset = true;
+        store(&record);
+    }
+    SCB::sys_reset()
+}
+
+fn record_panic(message: core::fmt::Arguments, file: &str, line: u32) {
+    let mut record = load().unwrap_or(CrashRecord {
+        boot_count: 0,
+        reset_reason: ResetReason::PowerOn,
+        stalled_task: None,
+        panic: None,
+        software_reset: false,
+    });
+
+    let mut report = PanicReport {
+        message: String::new(),
+        file: String::new(),
+        line,
+    };
+    let _ = Truncating(&mut report.message).write_fmt(message);
+    let _ = Truncating(&mut report.file).write_str(file);
+
+    record.panic = Some(report);
+    store(&record);
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    safety::force_actuators_off();
+    cortex_m::interrupt::disable();
+
+    let (file, line) = info
+        .location()
+        .map_or(("", 0), |location| (location.file(), location.line()));
+    record_panic(format_args!("{}", info.message()), file, line);
+    defmt::error!("{}", defmt::Display2Format(info));
+
+    SCB::sys_reset()
+}
+
+#[exception]
+unsafe fn HardFault(frame: &ExceptionFrame) -> ! {
+    safety::force_actuators_off();
+    record_panic(format_args!("HardFault at {:#010x}", frame.pc()), "", 0);
+    SCB::sys_reset()
+}
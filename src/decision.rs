@@ -0,0 +1,172 @@
+//! The station on the device: every reading goes through one
+//! `watering_core::station::Station`, the same pipeline the simulator runs.
+//! This module holds it, logs what goes in and acts on what comes out.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use log::info;
+use watering_core::forecast::ForecastSettings;
+use watering_core::guards::GuardSettings;
+use watering_core::light::DawnSettings;
+use watering_core::quality::QualitySettings;
+use watering_core::record::Event;
+use watering_core::station::{Station, StationSettings};
+use watering_core::types::{PumpCommand, SensorData};
+use watering_core::watering::{Decision, Settings};
+
+use crate::channels::PUMP_CHANNEL;
+use crate::config::{
+    ALTITUDE_M, CROP_COEFFICIENT, DAWN_LUX, DAWN_MIN_NIGHT_SECS, DAWN_RUN_SECS, DRY_BELOW_PCT,
+    FORECAST_INTERVAL_SECS, FORECAST_MAX_HOURS, FORECAST_MIN_POINTS, FORECAST_RESET_RISE_PCT,
+    FROST_BELOW_C, HEAT_WAVE_ABOVE_C, HEAT_WAVE_EXTRA_RUNS, HUMID_ABOVE_PCT, LATITUDE_DEG,
+    PUMP_MAX_DURATION_SECS, QUALITY_HUMIDITY, QUALITY_PRESSURE, QUALITY_SOIL_MOISTURE,
+    QUALITY_TEMPERATURE, QUALITY_WATER_LEVEL, RAIN_DELAY_SECS, RAIN_PRESSURE_DROP_HPA,
+    REFERENCE_ET_MM, RUNS_PER_DAY, SOIL_EMA_ALPHA, SUNNY_DLI, VACATION_RESERVE_L, VACATION_ZONES,
+};
+use crate::recorder;
+use crate::response;
+use crate::tank;
+use crate::target;
+use crate::vacation;
+
+pub const SETTINGS: StationSettings = StationSettings {
+    watering: Settings {
+        pump_max_secs: PUMP_MAX_DURATION_SECS,
+        latitude: LATITUDE_DEG,
+        crop_coefficient: CROP_COEFFICIENT,
+        reference_et_mm: REFERENCE_ET_MM,
+        sunny_dli: SUNNY_DLI,
+        guards: GuardSettings {
+            frost_below: FROST_BELOW_C,
+            humid_above: HUMID_ABOVE_PCT,
+            heat_wave_above: HEAT_WAVE_ABOVE_C,
+            runs_per_day: RUNS_PER_DAY,
+            heat_wave_extra_runs: HEAT_WAVE_EXTRA_RUNS,
+            rain_drop: RAIN_PRESSURE_DROP_HPA,
+            rain_delay_secs: RAIN_DELAY_SECS,
+        },
+    },
+    quality: QualitySettings {
+        temperature: QUALITY_TEMPERATURE,
+        humidity: QUALITY_HUMIDITY,
+        pressure: QUALITY_PRESSURE,
+        soil_moisture: QUALITY_SOIL_MOISTURE,
+        water_level: QUALITY_WATER_LEVEL,
+    },
+    soil_ema_alpha: SOIL_EMA_ALPHA,
+    altitude_m: ALTITUDE_M,
+    response: response::SETTINGS,
+    target: target::SETTINGS,
+    tank: tank::SETTINGS,
+    forecast: ForecastSettings {
+        interval_secs: FORECAST_INTERVAL_SECS,
+        min_points: FORECAST_MIN_POINTS,
+        reset_rise_pct: FORECAST_RESET_RISE_PCT,
+        max_hours: FORECAST_MAX_HOURS,
+    },
+    dawn: DawnSettings {
+        dawn_lux: DAWN_LUX,
+        min_night_secs: DAWN_MIN_NIGHT_SECS,
+        run_secs: DAWN_RUN_SECS,
+    },
+    dry_below: DRY_BELOW_PCT,
+    vacation_reserve_l: VACATION_RESERVE_L,
+    vacation_zones: VACATION_ZONES,
+};
+
+// How often the state is logged for replay
+const CHECKPOINT_INTERVAL_SECS: u32 = 60 * 60;
+
+struct Shared {
+    station: Station,
+    last_checkpoint: Option<u32>,
+}
+
+static SHARED: Mutex<CriticalSectionRawMutex, RefCell<Shared>> = Mutex::new(RefCell::new(Shared {
+    station: Station::new(),
+    last_checkpoint: None,
+}));
+
+/// Runs `f` on the station. Not reentrant: `f` must not call back into
+/// this module or the ones built on it.
+pub fn with<R>(f: impl FnOnce(&mut Station) -> R) -> R {
+    SHARED.lock(|shared| f(&mut shared.borrow_mut().station))
+}
+
+/// Checks and filters a fresh sample and derives the air metrics.
+pub fn refine(data: &mut SensorData) {
+    let t_secs = recorder::now();
+    with(|station| station.refine(t_secs, data, &SETTINGS));
+}
+
+/// Records a refined sample, puts it through the station and acts on what
+/// comes of it: reports, faults and the station's own runs.
+pub fn observe(data: &mut SensorData) {
+    let t_secs = recorder::record(Event::Sample(*data));
+
+    let observed = SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        let observed = shared.station.observe(t_secs, data, &SETTINGS);
+
+        let due = shared
+            .last_checkpoint
+            .is_none_or(|last| t_secs.saturating_sub(last) >= CHECKPOINT_INTERVAL_SECS);
+        if due {
+            recorder::record(Event::Checkpoint(shared.station.state));
+            shared.last_checkpoint = Some(t_secs);
+        }
+        observed
+    });
+
+    if let Some(check) = observed.check {
+        response::report(check);
+    }
+    if observed.gain_learned {
+        target::store_gain();
+    }
+    tank::report(observed.refill, &observed.tank);
+    if observed.dawn {
+        info!("Dawn at {:?} lx", data.lux);
+    }
+    if let Some(cmd) = observed.command.and_then(vacation::scale) {
+        info!(
+            "Soil at {:?}%: {} secs run",
+            data.soil_moisture, cmd.duration_secs
+        );
+        recorder::record(Event::Command(cmd));
+        PUMP_CHANNEL.try_send(cmd).ok();
+    }
+}
+
+pub fn day_of_year() -> Option<u16> {
+    with(|station| station.state.et.day_of_year())
+}
+
+pub fn set_day_of_year(day_of_year: u16) {
+    with(|station| {
+        if station.state.et.day_of_year() != Some(day_of_year) {
+            station.state.set_day_of_year(day_of_year);
+            recorder::record(Event::Checkpoint(station.state));
+        }
+    });
+}
+
+/// Runs `cmd` past the guards and sizes it, logging the outcome.
+pub fn decide(cmd: PumpCommand) -> Decision {
+    let t_secs = recorder::now();
+    let decision = with(|station| station.decide(t_secs, cmd, &SETTINGS));
+    let event = match decision {
+        Ok(program) => Event::Pump(program),
+        Err(reason) => Event::Skip(reason),
+    };
+    recorder::record_at(t_secs, event);
+    decision
+}
+
+/// Starts watching the moisture after a run of `on_secs`.
+pub fn watered(on_secs: u32) {
+    let t_secs = recorder::now();
+    with(|station| station.watered(t_secs, on_secs, &SETTINGS));
+}
@@ -0,0 +1,151 @@
+//! Pump flow calibration and the water delivered, kept in flash.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use log::{error, info, warn};
+use watering_core::dosing::{self, Stored, Totals};
+use watering_core::target::Band;
+use watering_core::types::{HttpRequest, PumpCommand, SensorData, SystemEvent};
+
+use crate::channels::HTTP_CHANNEL;
+use crate::decision;
+use crate::storage::Storage;
+
+struct Shared {
+    stored: Stored,
+    storage: Option<Storage>,
+    // Pump on-time of the last calibration run, waiting for its volume
+    calibration_secs: Option<u32>,
+}
+
+static SHARED: Mutex<CriticalSectionRawMutex, RefCell<Shared>> = Mutex::new(RefCell::new(Shared {
+    stored: Stored::new(),
+    storage: None,
+    calibration_secs: None,
+}));
+
+/// Restores the calibration and totals from flash.
+pub fn init(mut storage: Storage) {
+    let stored = storage.load();
+    match &stored {
+        Some(stored) => info!(
+            "Dosing: {:?} ml/s, {} ml delivered in total",
+            stored.ml_per_sec, stored.totals.total_ml
+        ),
+        None => info!("Dosing: nothing stored yet, pump uncalibrated"),
+    }
+
+    SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        shared.stored = stored.unwrap_or_default();
+        shared.storage = Some(storage);
+    });
+}
+
+pub fn stored() -> Stored {
+    SHARED.lock(|shared| shared.borrow().stored)
+}
+
+pub fn ml_per_sec() -> Option<f32> {
+    SHARED.lock(|shared| shared.borrow().stored.ml_per_sec)
+}
+
+pub fn totals() -> Totals {
+    SHARED.lock(|shared| shared.borrow().stored.totals)
+}
+
+/// Fills in the water delivered for the server.
+pub fn report(data: &mut SensorData) {
+    let totals = totals();
+    data.water_today_l = totals.today_ml(decision::day_of_year()) as f32 / 1000.0;
+    data.water_total_l = totals.total_ml as f32 / 1000.0;
+}
+
+/// Turns a volume request into seconds; `None` while uncalibrated.
+pub fn resolve(cmd: PumpCommand) -> Option<PumpCommand> {
+    let resolved = dosing::resolve(cmd, ml_per_sec());
+    if resolved.is_none() {
+        warn!(
+            "Dosing: {} ml requested but the pump is not calibrated",
+            cmd.volume_ml
+        );
+    }
+    resolved
+}
+
+/// Adds a finished run to the totals and returns its volume, if known.
+/// A `metered` volume is taken over the one estimated from the calibration.
+pub fn record_run(on_secs: u32, metered: Option<u32>) -> Option<u32> {
+    let day = decision::day_of_year();
+    SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        let ml = match metered {
+            Some(ml) => ml,
+            None => dosing::volume_ml(on_secs, shared.stored.ml_per_sec?),
+        };
+        shared.stored.totals.add(ml, day);
+        save(&mut shared);
+        Some(ml)
+    })
+}
+
+pub fn calibration_run_done(on_secs: u32) {
+    SHARED.lock(|shared| shared.borrow_mut().calibration_secs = Some(on_secs));
+}
+
+/// Sets the flow rate from the `ml` the last calibration run delivered,
+/// which are also added to the totals.
+pub fn calibrate(ml: u16) {
+    let day = decision::day_of_year();
+    let rate = SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        let Some(on_secs) = shared.calibration_secs.take() else {
+            warn!("Dosing: no calibration run to go with {} ml", ml);
+            return None;
+        };
+        let rate = dosing::flow_rate(ml as f32, on_secs)?;
+
+        shared.stored.ml_per_sec = Some(rate);
+        shared.stored.totals.add(ml as u32, day);
+        save(&mut shared);
+        Some(rate)
+    });
+
+    if let Some(ml_per_sec) = rate {
+        info!("Dosing: pump calibrated at {} ml/s", ml_per_sec);
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::PumpCalibrated {
+                ml_per_sec,
+            }))
+            .ok();
+    }
+}
+
+/// Keeps the target band and learned gain with the calibration.
+pub fn store_target(target: Option<Band>, gain: Option<f32>) {
+    SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        shared.stored.target = target;
+        shared.stored.gain = gain;
+        save(&mut shared);
+    });
+}
+
+pub fn store_vacation(until_day: Option<u16>) {
+    SHARED.lock(|shared| {
+        let mut shared = shared.borrow_mut();
+        shared.stored.vacation_until = until_day;
+        save(&mut shared);
+    });
+}
+
+fn save(shared: &mut Shared) {
+    let stored = shared.stored;
+    if let Some(storage) = shared.storage.as_mut()
+        && let Err(e) = storage.save(&stored)
+    {
+        error!("Dosing: saving to flash failed: {:?}", e);
+    }
+}
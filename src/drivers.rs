@@ -0,0 +1,199 @@
+//! Sensors found on the shared I2C bus at boot, read into the telemetry
+//! map; the conversions are in `watering_core::drivers`.
+
+use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_rp::i2c;
+use embassy_rp::peripherals::I2C1;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Duration, Timer, with_timeout};
+use embedded_hal_async::i2c::I2c;
+use heapless::Vec;
+use log::{info, warn};
+use watering_core::drivers::{self, BH1750_MTREG, CHIP_ID_REGISTER, Kind, SCAN_ADDRESSES};
+use watering_core::telemetry::Telemetry;
+use watering_core::traits::SensorDriver;
+
+use crate::I2cBus;
+use crate::config::{I2C_TIMEOUT_MS, INA219_SHUNT_OHMS};
+
+pub const MAX_DRIVERS: usize = 8;
+
+type Device = I2cDevice<'static, CriticalSectionRawMutex, i2c::I2c<'static, I2C1, i2c::Async>>;
+
+pub struct I2cSensor {
+    kind: Kind,
+    address: u8,
+    device: Device,
+    ready: bool,
+}
+
+impl I2cSensor {
+    fn new(i2c_bus: &'static I2cBus, kind: Kind, address: u8) -> Self {
+        Self {
+            kind,
+            address,
+            device: I2cDevice::new(i2c_bus),
+            ready: false,
+        }
+    }
+
+    // Puts the chip into the mode `measure` expects
+    async fn init(&mut self) -> Result<(), ()> {
+        let commands: &[&[u8]] = match self.kind {
+            Kind::Aht20 => &[&[0xBE, 0x08, 0x00]],
+            // Power on, then the measurement time in two parts
+            Kind::Bh1750 => &[
+                &[0x01],
+                &[0x40 | (BH1750_MTREG >> 5)],
+                &[0x60 | (BH1750_MTREG & 0x1F)],
+            ],
+            // Periodic measurement, a new reading every 5 s
+            Kind::Scd40 => &[&[0x21, 0xB1]],
+            _ => return Ok(()),
+        };
+        for command in commands {
+            self.device
+                .write(self.address, command)
+                .await
+                .map_err(drop)?;
+        }
+        Timer::after_millis(10).await;
+        Ok(())
+    }
+
+    async fn measure(&mut self, out: &mut Telemetry) -> Result<(), ()> {
+        let address = self.address;
+        let device = &mut self.device;
+        let decoded = match self.kind {
+            Kind::Sht3x => {
+                // Single shot, high repeatability, no clock stretching
+                device.write(address, &[0x24, 0x00]).await.map_err(drop)?;
+                Timer::after_millis(16).await;
+                let mut buf = [0; 6];
+                device.read(address, &mut buf).await.map_err(drop)?;
+                drivers::decode_sht3x(&buf, out)
+            }
+            Kind::Aht20 => {
+                device
+                    .write(address, &[0xAC, 0x33, 0x00])
+                    .await
+                    .map_err(drop)?;
+                Timer::after_millis(80).await;
+                let mut buf = [0; 7];
+                device.read(address, &mut buf).await.map_err(drop)?;
+                drivers::decode_aht20(&buf, out)
+            }
+            Kind::Bh1750 => {
+                // One-time high resolution mode, then the chip powers down
+                device.write(address, &[0x20]).await.map_err(drop)?;
+                Timer::after_millis(180).await;
+                let mut buf = [0; 2];
+                device.read(address, &mut buf).await.map_err(drop)?;
+                drivers::decode_bh1750(&buf, BH1750_MTREG, out)
+            }
+            Kind::Scd40 => {
+                device.write(address, &[0xEC, 0x05]).await.map_err(drop)?;
+                Timer::after_millis(1).await;
+                let mut buf = [0; 9];
+                device.read(address, &mut buf).await.map_err(drop)?;
+                drivers::decode_scd40(&buf, out)
+            }
+            Kind::Ina219 => {
+                let mut bus = [0; 2];
+                let mut shunt = [0; 2];
+                device
+                    .write_read(address, &[0x02], &mut bus)
+                    .await
+                    .map_err(drop)?;
+                device
+                    .write_read(address, &[0x01], &mut shunt)
+                    .await
+                    .map_err(drop)?;
+                drivers::decode_ina219(&bus, &shunt, INA219_SHUNT_OHMS, out)
+            }
+            // Read elsewhere, never registered
+            Kind::Bme280 | Kind::Bmp280 | Kind::Ssd1306 => return Err(()),
+        };
+        decoded.map_err(|e| warn!("{}: {:?}", self.kind.name(), e))
+    }
+}
+
+impl SensorDriver for I2cSensor {
+    type Error = ();
+
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    async fn read(&mut self, out: &mut Telemetry) -> Result<(), ()> {
+        let timeout = Duration::from_millis(I2C_TIMEOUT_MS);
+        if !self.ready {
+            self.ready = matches!(with_timeout(timeout, self.init()).await, Ok(Ok(())));
+            if !self.ready {
+                warn!("{} at {:#04x}: init failed", self.kind.name(), self.address);
+                return Err(());
+            }
+        }
+        // The slowest measurement takes 180 ms on top of the bus timeout
+        let measured = with_timeout(timeout * 2, self.measure(out)).await;
+        if !matches!(measured, Ok(Ok(()))) {
+            warn!("{} at {:#04x}: read failed", self.kind.name(), self.address);
+            // Start over in case the chip was power cycled
+            self.ready = false;
+            return Err(());
+        }
+        Ok(())
+    }
+}
+
+/// Probes every address on the bus and registers the chips found that
+/// report into the telemetry. Call before the other bus users start.
+pub async fn scan(i2c_bus: &'static I2cBus) -> Vec<I2cSensor, MAX_DRIVERS> {
+    let timeout = Duration::from_millis(I2C_TIMEOUT_MS);
+    let mut device = I2cDevice::new(i2c_bus);
+    let mut found: Vec<I2cSensor, MAX_DRIVERS> = Vec::new();
+
+    for address in SCAN_ADDRESSES {
+        let mut byte = [0];
+        if !matches!(
+            with_timeout(timeout, device.read(address, &mut byte)).await,
+            Ok(Ok(()))
+        ) {
+            continue;
+        }
+
+        let mut chip_id = None;
+        if drivers::needs_chip_id(address) {
+            let mut id = [0];
+            let read = device.write_read(address, &[CHIP_ID_REGISTER], &mut id);
+            if let Ok(Ok(())) = with_timeout(timeout, read).await {
+                chip_id = Some(id[0]);
+            }
+        }
+
+        let Some(kind) = drivers::identify(address, chip_id) else {
+            info!("I2C {:#04x}: unknown device", address);
+            continue;
+        };
+        match kind {
+            Kind::Bme280 | Kind::Ssd1306 => {
+                info!("I2C {:#04x}: {}", address, kind.name());
+            }
+            Kind::Bmp280 => {
+                warn!("I2C {:#04x}: BMP280 is not supported", address);
+            }
+            // Measurement names are per chip type
+            _ if found.iter().any(|sensor| sensor.kind == kind) => {
+                warn!("I2C {:#04x}: second {} ignored", address, kind.name());
+            }
+            _ => {
+                info!("I2C {:#04x}: {}, registered", address, kind.name());
+                if found.push(I2cSensor::new(i2c_bus, kind, address)).is_err() {
+                    warn!("I2C {:#04x}: too many sensors", address);
+                }
+            }
+        }
+    }
+
+    found
+}
@@ -0,0 +1,44 @@
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use heapless::String;
+use log::{error, info};
+use watering_core::types::{Fault, HttpRequest};
+
+use crate::channels::HTTP_CHANNEL;
+
+// Bitmask of currently active faults, indexed by `Fault as u16`
+static ACTIVE: AtomicU16 = AtomicU16::new(0);
+
+fn send_alert(message: String<64>) {
+    HTTP_CHANNEL
+        .try_send(HttpRequest::SendAlert { message })
+        .ok();
+}
+
+/// Marks a fault active and sends an alert the first time it is raised.
+pub fn raise(fault: Fault) {
+    let bit = 1 << fault as u16;
+    if ACTIVE.fetch_or(bit, Ordering::Relaxed) & bit != 0 {
+        return;
+    }
+
+    error!("Fault: {}", fault.message());
+
+    let mut message: String<64> = String::new();
+    let _ = message.push_str(fault.message());
+    send_alert(message);
+}
+
+pub fn clear(fault: Fault) {
+    let bit = 1 << fault as u16;
+    if ACTIVE.fetch_and(!bit, Ordering::Relaxed) & bit != 0 {
+        info!("Fault cleared: {}", fault.message());
+    }
+}
+
+pub fn first_active() -> Option<Fault> {
+    let active = ACTIVE.load(Ordering::Relaxed);
+    Fault::ALL
+        .into_iter()
+        .find(|&fault| active & (1 << fault as u16) != 0)
+}
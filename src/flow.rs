@@ -0,0 +1,124 @@
+//! Flow meter pulse count and pump state, shared between the pump task and
+//! the flow task that judges them. A PIO state machine counts the meter's
+//! falling edges, so none are lost however busy the executor is.
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use embassy_rp::Peri;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::gpio::Pull;
+use embassy_rp::peripherals::PIO1;
+use embassy_rp::pio::{
+    Common, Config, Direction, PioPin, ShiftConfig, ShiftDirection, StateMachine,
+};
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use fixed::traits::ToFixed;
+use watering_core::flow::{self, FlowSettings};
+
+use crate::config::{
+    FLOW_DRAIN_SECS, FLOW_LEAK_ML, FLOW_LEAK_WINDOW_SECS, FLOW_MIN_ML_PER_SEC, FLOW_PRIME_SECS,
+    FLOW_PULSES_PER_LITRE,
+};
+
+pub const SETTINGS: FlowSettings = FlowSettings {
+    pulses_per_litre: FLOW_PULSES_PER_LITRE,
+    prime_secs: FLOW_PRIME_SECS,
+    min_ml_per_sec: FLOW_MIN_ML_PER_SEC,
+    drain_secs: FLOW_DRAIN_SECS,
+    leak_ml: FLOW_LEAK_ML,
+    leak_window_secs: FLOW_LEAK_WINDOW_SECS,
+};
+
+/// Edge counter on a PIO state machine. The count lives in the state
+/// machine's X register and is only copied out when asked for, so a read is
+/// never more than one state machine clock (1 µs) behind the meter. How
+/// late a volume target is seen depends on the readers: the pump task's
+/// `Watch::pulse_done` polls about every 250 ms.
+struct PioCounter {
+    sm: StateMachine<'static, PIO1, 1>,
+    count: u32,
+}
+
+impl PioCounter {
+    fn pulses(&mut self) -> u32 {
+        // `in x, 32` autopushes X; it runs on the next state machine clock
+        // even while the program is stalled on a `wait`
+        let read = pio::pio_asm!("in x, 32");
+        unsafe { self.sm.exec_instr(read.program.code[0]) };
+        for _ in 0..1000 {
+            if let Some(x) = self.sm.rx().try_pull() {
+                self.count = !x;
+                break;
+            }
+        }
+        self.count
+    }
+}
+
+static COUNTER: Mutex<CriticalSectionRawMutex, RefCell<Option<PioCounter>>> =
+    Mutex::new(RefCell::new(None));
+static PUMP_ON: AtomicBool = AtomicBool::new(false);
+
+pub const fn fitted() -> bool {
+    FLOW_PULSES_PER_LITRE > 0.0
+}
+
+/// Starts counting the meter on `pin`.
+pub fn init(
+    common: &mut Common<'static, PIO1>,
+    mut sm: StateMachine<'static, PIO1, 1>,
+    pin: Peri<'static, impl PioPin>,
+) {
+    // X counts down from all ones, so its complement is the edge count
+    let program = pio::pio_asm!(
+        "    mov x, ~null",
+        ".wrap_target",
+        "top:",
+        "    wait 1 pin 0",
+        "    wait 0 pin 0",
+        "    jmp x-- top",
+        ".wrap",
+    );
+    let loaded = common.load_program(&program.program);
+
+    // Open-collector hall sensor, pulled up
+    let mut pin = common.make_pio_pin(pin);
+    pin.set_pull(Pull::Up);
+
+    let mut cfg = Config::default();
+    cfg.use_program(&loaded, &[]);
+    cfg.set_in_pins(&[&pin]);
+    cfg.shift_in = ShiftConfig {
+        auto_fill: true,
+        threshold: 32,
+        direction: ShiftDirection::Left,
+    };
+    // 1 MHz is plenty for a meter at a few hundred Hz, and ignores
+    // sub-microsecond glitches
+    cfg.clock_divider = (clk_sys_freq() / 1_000_000).to_fixed();
+    sm.set_config(&cfg);
+    sm.set_pin_dirs(Direction::In, &[&pin]);
+    sm.set_enable(true);
+
+    COUNTER.lock(|counter| *counter.borrow_mut() = Some(PioCounter { sm, count: 0 }));
+}
+
+/// Meter pulses since boot; wraps, so only differences mean anything.
+pub fn pulses() -> u32 {
+    COUNTER.lock(|counter| counter.borrow_mut().as_mut().map_or(0, PioCounter::pulses))
+}
+
+pub fn pump_on() -> bool {
+    PUMP_ON.load(Ordering::Relaxed)
+}
+
+pub fn set_pump_on(on: bool) {
+    PUMP_ON.store(on, Ordering::Relaxed);
+}
+
+/// Millilitres metered since the count was `since`.
+pub fn ml_since(since: u32) -> u32 {
+    flow::pulses_to_ml(pulses().wrapping_sub(since), FLOW_PULSES_PER_LITRE)
+}
@@ -0,0 +1,65 @@
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use embassy_time::{Duration, Instant, Timer};
+use watering_core::traits::Clock;
+use watering_core::types::TaskId;
+
+use crate::config::{HEARTBEAT_INTERVAL_SECS, HTTP_DEADLINE_SECS, SENSOR_INTERVAL_MS};
+
+// Milliseconds since boot of each task's last beat, 0 = not started yet
+static LAST_BEAT: [AtomicU32; TaskId::ALL.len()] = [const { AtomicU32::new(0) }; TaskId::ALL.len()];
+
+fn now_ms() -> u32 {
+    (Instant::now().as_millis() as u32).max(1)
+}
+
+fn deadline(task: TaskId) -> Duration {
+    match task {
+        TaskId::Sensor => Duration::from_millis(SENSOR_INTERVAL_MS) + Duration::from_secs(30),
+        TaskId::Pump => Duration::from_secs(HEARTBEAT_INTERVAL_SECS * 5),
+        TaskId::Http => Duration::from_secs(HTTP_DEADLINE_SECS),
+        TaskId::Display => Duration::from_secs(30),
+    }
+}
+
+pub fn beat(task: TaskId) {
+    LAST_BEAT[task as usize].store(now_ms(), Ordering::Relaxed);
+}
+
+/// Sleeps for `duration` while keeping the task's heartbeat alive.
+pub async fn sleep(task: TaskId, duration: Duration) {
+    let end = Instant::now() + duration;
+    let slice = Duration::from_secs(HEARTBEAT_INTERVAL_SECS);
+
+    loop {
+        beat(task);
+        let now = Instant::now();
+        if now >= end {
+            return;
+        }
+        Timer::after((end - now).min(slice)).await;
+    }
+}
+
+/// Returns the first started task whose last beat is older than its deadline.
+pub fn stalled() -> Option<TaskId> {
+    let now = now_ms();
+    TaskId::ALL.into_iter().find(|&task| {
+        let last = LAST_BEAT[task as usize].load(Ordering::Relaxed);
+        last != 0 && now.wrapping_sub(last) as u64 > deadline(task).as_millis()
+    })
+}
+
+/// Clock for core logic running inside a supervised task: sleeping keeps
+/// the task's heartbeat alive.
+pub struct HeartbeatClock(pub TaskId);
+
+impl Clock for HeartbeatClock {
+    fn now_ms(&self) -> u64 {
+        Instant::now().as_millis()
+    }
+
+    async fn sleep_ms(&mut self, ms: u64) {
+        sleep(self.0, Duration::from_millis(ms)).await;
+    }
+}
@@ -3,10 +3,27 @@
 #![no_std]
 #![no_main]
 
+mod backoff;
+mod bus;
 mod channels;
 mod config;
+mod console;
+mod crash;
+mod decision;
+mod dosing;
+mod drivers;
+mod faults;
+mod flow;
+mod heartbeat;
+mod onewire;
+mod recorder;
+mod response;
+mod safety;
+mod storage;
+mod tank;
+mod target;
 mod tasks;
-mod types;
+mod vacation;
 
 use cyw43::JoinOptions;
 use cyw43_pio::PioSpi;
@@ -16,6 +33,7 @@ use fixed::types::extra::U8;
 // Custom clock divider for Pico 2 W - 0x0300
 const PICO2W_CLOCK_DIVIDER: FixedU32<U8> = FixedU32::from_bits(0x0300);
 
+use defmt_rtt as _;
 use embassy_executor::Spawner;
 use embassy_net::{Config, StackResources};
 use embassy_rp::adc::{Adc, Channel, InterruptHandler as AdcInterruptHandler};
@@ -24,18 +42,21 @@ use embassy_rp::block::ImageDef;
 use embassy_rp::clocks::RoscRng;
 use embassy_rp::gpio::{Input, Level, Output};
 use embassy_rp::i2c::{self, Config as I2cConfig, InterruptHandler as I2cInterruptHandler};
-use embassy_rp::peripherals::{I2C1, PIO0, USB};
+use embassy_rp::peripherals::{I2C1, PIO0, PIO1, USB};
 use embassy_rp::pio::{InterruptHandler as PioInterruptHandler, Pio};
 use embassy_rp::usb::{Driver, InterruptHandler as UsbInterruptHandler};
+use embassy_rp::watchdog::Watchdog;
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::mutex::Mutex;
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer};
 use log::info;
 use static_cell::StaticCell;
-use {defmt_rtt as _, panic_probe as _};
+use watering_core::types::{HttpRequest, SystemEvent};
 
-use config::{WIFI_NETWORK, WIFI_PASSWORD};
-use tasks::{display, logger, network, pump, sensor};
+use channels::HTTP_CHANNEL;
+use config::{WATCHDOG_TIMEOUT_MS, WIFI_NETWORK, WIFI_PASSWORD};
+use storage::Storage;
+use tasks::{display, flow as flow_task, logger, network, pump, sensor, update, watchdog};
 
 #[unsafe(link_section = ".start_block")]
 #[used]
@@ -45,6 +66,7 @@ bind_interrupts!(struct Irqs {
     I2C1_IRQ => I2cInterruptHandler<I2C1>;
     USBCTRL_IRQ => UsbInterruptHandler<USB>;
     PIO0_IRQ_0 => PioInterruptHandler<PIO0>;
+    PIO1_IRQ_0 => PioInterruptHandler<PIO1>;
     ADC_IRQ_FIFO => AdcInterruptHandler;
 });
 
@@ -62,16 +84,54 @@ static I2C_BUS: StaticCell<I2cBus> = StaticCell::new();
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) {
+    // Actuators go to their safe state before anything else can fail, and
+    // stay there once the HAL takes the pins over
+    safety::force_actuators_off();
     let p = embassy_rp::init(Default::default());
+    let pump_pin = Output::new(p.PIN_15, Level::Low);
+
     let mut rng = RoscRng;
 
+    // === Watchdog ===
+    let mut wdt = Watchdog::new(p.WATCHDOG);
+    let boot = crash::boot(watchdog::take_last_reset(&mut wdt));
+    wdt.pause_on_debug(true);
+    wdt.start(Duration::from_millis(WATCHDOG_TIMEOUT_MS));
+    spawner.spawn(watchdog::watchdog_task(wdt)).unwrap();
+
     // === USB Logger ===
     let driver = Driver::new(p.USB, Irqs);
     spawner.spawn(logger::logger_task(driver)).unwrap();
     Timer::after_millis(500).await;
 
+    info!(
+        "Boot #{}, last reset: {}",
+        boot.boot_count,
+        boot.reset_reason.name()
+    );
+    if let Some(task) = boot.stalled_task {
+        info!("Watchdog blamed the {} task", task.name());
+    }
+    if let Some(panic) = &boot.panic {
+        info!(
+            "Previous boot panicked at {}:{}: {}",
+            panic.file.as_str(),
+            panic.line,
+            panic.message.as_str()
+        );
+    }
+
     Timer::after_millis(100).await;
 
+    let flash = storage::init_flash(p.FLASH);
+    dosing::init(Storage::new(flash));
+    target::init();
+    vacation::init();
+    let mut updater = update::new_updater(flash);
+    if update::is_trial_boot(&mut updater) {
+        spawner.spawn(update::rollback_task()).unwrap();
+    }
+
     info!("Loading CYW43 firmware");
     Timer::after_millis(100).await;
     let fw = include_bytes!("../firmware/43439A0.bin");
@@ -96,13 +156,23 @@ async fn main(spawner: Spawner) {
     info!("Initializing ADC");
     let adc = Adc::new(p.ADC, Irqs, embassy_rp::adc::Config::default());
     let soil_pin = Channel::new_pin(p.PIN_28, embassy_rp::gpio::Pull::None);
+    // Powered only while measuring, against corrosion
+    let soil_power = Output::new(p.PIN_19, Level::Low);
 
     info!("Initializing sonar");
     let sonar_trigger = Output::new(p.PIN_16, Level::Low);
     let sonar_echo = Input::new(p.PIN_17, embassy_rp::gpio::Pull::None);
 
-    info!("Initializing pump");
-    let pump_pin = Output::new(p.PIN_15, Level::Low);
+    // PIO0 drives the CYW43; on PIO1 sm0 runs the 1-Wire bus and sm1
+    // counts the flow meter
+    let mut pio1 = Pio::new(p.PIO1, Irqs);
+    if flow::fitted() {
+        flow::init(&mut pio1.common, pio1.sm1, p.PIN_18);
+    }
+
+    info!("Searching 1-Wire bus");
+    let onewire_bus = onewire::PioOneWire::new(&mut pio1.common, pio1.sm0, p.PIN_20);
+    let soil_probes = onewire::SoilProbes::find(onewire_bus).await;
 
     info!("Initializing CYW43");
     static CYW43_STATE: StaticCell<cyw43::State> = StaticCell::new();
@@ -139,16 +209,25 @@ async fn main(spawner: Spawner) {
     let i2c_bus = I2C_BUS.init(Mutex::new(i2c));
 
     info!("I2C bus initialized on GP26/GP27");
+    let i2c_sensors = drivers::scan(i2c_bus).await;
 
     spawner.spawn(display::display_task(i2c_bus)).unwrap();
     spawner.spawn(pump::pump_task(pump_pin)).unwrap();
+    if flow::fitted() {
+        spawner.spawn(flow_task::flow_task()).unwrap();
+    } else {
+        info!("No flow meter configured");
+    }
     spawner
         .spawn(sensor::sensor_task(
             i2c_bus,
             adc,
             soil_pin,
+            soil_power,
             sonar_trigger,
             sonar_echo,
+            i2c_sensors,
+            soil_probes,
         ))
         .unwrap();
 
@@ -162,8 +241,14 @@ async fn main(spawner: Spawner) {
     }
     info!("WiFi connected!");
 
-    spawner.spawn(network::http_task(stack, seed)).unwrap();
+    spawner
+        .spawn(network::http_task(stack, seed, updater))
+        .unwrap();
     spawner.spawn(network::poll_task()).unwrap();
 
+    HTTP_CHANNEL
+        .send(HttpRequest::PostEvent(SystemEvent::Boot(boot.clone())))
+        .await;
+
     info!("All tasks spawned");
 }
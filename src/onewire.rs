@@ -0,0 +1,224 @@
+//! DS18B20 soil temperature probes sharing one pin on the 1-Wire bus. A
+//! PIO state machine times each slot; the search and the decoding are in
+//! `watering_core::onewire`.
+
+use embassy_rp::Peri;
+use embassy_rp::clocks::clk_sys_freq;
+use embassy_rp::gpio::{Level, Pull};
+use embassy_rp::peripherals::PIO1;
+use embassy_rp::pio::{
+    Common, Config, Direction, PioPin, ShiftConfig, ShiftDirection, StateMachine,
+};
+use embassy_time::{Duration, Timer, with_timeout};
+use fixed::traits::ToFixed;
+use heapless::Vec;
+use log::{info, warn};
+use watering_core::onewire::{
+    self, DS18B20_CONVERSION_MS, DS18B20_FAMILY, MAX_PROBES, Rom, Search, SoilTemperatures,
+};
+use watering_core::traits::OneWireBus;
+use watering_core::types::Fault;
+
+use crate::config::ONEWIRE_TIMEOUT_MS;
+use crate::faults;
+
+// Bits of a word pushed to the state machine: the bit to write, and
+// whether to send a reset pulse instead
+const BIT_ONE: u32 = 0b01;
+const RESET: u32 = 0b10;
+
+/// 1-Wire master on a PIO state machine running at 1 MHz, so a cycle is
+/// a microsecond. Each word in gives one word out: the wire sampled 15 µs
+/// into the slot, or 70 µs after a reset pulse.
+pub struct PioOneWire {
+    sm: StateMachine<'static, PIO1, 0>,
+    origin: u8,
+}
+
+impl PioOneWire {
+    pub fn new(
+        common: &mut Common<'static, PIO1>,
+        mut sm: StateMachine<'static, PIO1, 0>,
+        pin: Peri<'static, impl PioPin>,
+    ) -> Self {
+        // The pin is only ever driven low; released, the pull-up takes it
+        // high
+        let program = pio::pio_asm!(
+            ".side_set 1 pindirs",
+            ".wrap_target",
+            "    pull block         side 0",
+            "    out x, 1           side 0",
+            "    out y, 1           side 0",
+            "    jmp y-- reset      side 0",
+            // Low for 6 µs starts the slot; a 1 releases the wire then
+            "    jmp !x zero        side 1 [5]",
+            "    nop                side 0 [8]",
+            "    in pins, 1         side 0 [15]",
+            "    nop                side 0 [15]",
+            "    jmp done           side 0 [15]",
+            // A 0 keeps it low for 63 µs
+            "zero:",
+            "    nop                side 1 [8]",
+            "    in pins, 1         side 1 [15]",
+            "    nop                side 1 [15]",
+            "    nop                side 1 [15]",
+            "done:",
+            "    push block         side 0 [9]",
+            ".wrap",
+            // Low for 496 µs, then a device answering holds it low
+            "reset:",
+            "    set y, 31          side 1 [15]",
+            "low:",
+            "    jmp y-- low        side 1 [14]",
+            "    set y, 3           side 0 [15]",
+            "settle:",
+            "    jmp y-- settle     side 0 [12]",
+            "    in pins, 1         side 0 [1]",
+            "    set y, 31          side 0 [15]",
+            "rest:",
+            "    jmp y-- rest       side 0 [10]",
+            "    jmp done           side 0",
+        );
+        let loaded = common.load_program(&program.program);
+
+        let mut pin = common.make_pio_pin(pin);
+        // Fallback only: the bus needs a 4.7 kΩ pull-up to 3.3 V
+        pin.set_pull(Pull::Up);
+
+        let mut cfg = Config::default();
+        cfg.use_program(&loaded, &[&pin]);
+        cfg.set_in_pins(&[&pin]);
+        cfg.shift_out = ShiftConfig {
+            auto_fill: false,
+            threshold: 32,
+            direction: ShiftDirection::Right,
+        };
+        cfg.shift_in = ShiftConfig {
+            auto_fill: false,
+            threshold: 32,
+            direction: ShiftDirection::Left,
+        };
+        cfg.clock_divider = (clk_sys_freq() / 1_000_000).to_fixed();
+        sm.set_config(&cfg);
+        sm.set_pins(Level::Low, &[&pin]);
+        sm.set_pin_dirs(Direction::In, &[&pin]);
+        sm.set_enable(true);
+
+        Self {
+            sm,
+            origin: loaded.origin,
+        }
+    }
+
+    async fn transfer(&mut self, word: u32) -> Result<bool, ()> {
+        let sm = &mut self.sm;
+        let slot = async {
+            sm.tx().wait_push(word).await;
+            sm.rx().wait_pull().await
+        };
+        match with_timeout(Duration::from_millis(ONEWIRE_TIMEOUT_MS), slot).await {
+            Ok(sampled) => Ok(sampled & 1 != 0),
+            Err(_) => {
+                // Start over from the top of the program
+                self.sm.set_enable(false);
+                self.sm.clear_fifos();
+                self.sm.restart();
+                unsafe { self.sm.exec_jmp(self.origin) };
+                self.sm.set_enable(true);
+                Err(())
+            }
+        }
+    }
+}
+
+impl OneWireBus for PioOneWire {
+    type Error = ();
+
+    async fn reset(&mut self) -> Result<bool, ()> {
+        // A presence pulse reads low
+        self.transfer(RESET).await.map(|high| !high)
+    }
+
+    async fn touch_bit(&mut self, bit: bool) -> Result<bool, ()> {
+        self.transfer(if bit { BIT_ONE } else { 0 }).await
+    }
+}
+
+/// The DS18B20s found on the bus at boot.
+pub struct SoilProbes {
+    bus: PioOneWire,
+    roms: Vec<Rom, MAX_PROBES>,
+}
+
+impl SoilProbes {
+    /// Searches the bus and keeps the DS18B20s on it.
+    pub async fn find(mut bus: PioOneWire) -> Self {
+        let mut roms: Vec<Rom, MAX_PROBES> = Vec::new();
+        let mut search = Search::new();
+
+        while !search.is_done() {
+            let rom = match search.next(&mut bus).await {
+                Ok(Some(rom)) => rom,
+                Ok(None) => break,
+                Err(e) => {
+                    warn!("1-Wire search failed: {:?}", e);
+                    break;
+                }
+            };
+            if rom.family() != DS18B20_FAMILY {
+                info!("1-Wire {}: unknown family {:#04x}", rom, rom.family());
+            } else if roms.push(rom).is_err() {
+                warn!("1-Wire {}: too many probes", rom);
+            } else {
+                info!("1-Wire {}: DS18B20", rom);
+            }
+        }
+
+        if roms.is_empty() {
+            info!("No soil temperature probes found");
+        }
+        Self { bus, roms }
+    }
+
+    /// Converts on every probe at once and reads each; a probe that fails
+    /// is left out.
+    pub async fn read(&mut self) -> SoilTemperatures {
+        let mut temperatures = SoilTemperatures::new();
+        if self.roms.is_empty() {
+            return temperatures;
+        }
+
+        if !matches!(onewire::start_conversion(&mut self.bus).await, Ok(true)) {
+            warn!("1-Wire: no presence pulse");
+            faults::raise(Fault::SoilTempRead);
+            return temperatures;
+        }
+        Timer::after_millis(DS18B20_CONVERSION_MS).await;
+
+        let mut failed = false;
+        for rom in &self.roms {
+            match onewire::read_scratchpad(&mut self.bus, rom).await {
+                Ok(Some(scratchpad)) => match onewire::decode_ds18b20(&scratchpad) {
+                    Ok(celsius) => {
+                        temperatures.push(*rom, celsius);
+                    }
+                    Err(e) => {
+                        warn!("DS18B20 {}: {:?}", rom, e);
+                        failed = true;
+                    }
+                },
+                _ => {
+                    warn!("DS18B20 {}: read failed", rom);
+                    failed = true;
+                }
+            }
+        }
+
+        if failed {
+            faults::raise(Fault::SoilTempRead);
+        } else {
+            faults::clear(Fault::SoilTempRead);
+        }
+        temperatures
+    }
+}
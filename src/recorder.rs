@@ -0,0 +1,64 @@
+//! Device side of the record log: what the sensors saw and what the pump
+//! was told to do, for replay on the host.
+
+use core::cell::RefCell;
+use core::ops::Deref;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::mutex::{Mutex as AsyncMutex, MutexGuard};
+use embassy_time::Instant;
+use watering_core::record::{Entry, Event, HEADER_LEN, Header, Log};
+
+use crate::config::RECORD_CAPACITY;
+use crate::decision::SETTINGS;
+
+const EXPORT_LEN: usize = HEADER_LEN + RECORD_CAPACITY;
+
+static LOG: Mutex<CriticalSectionRawMutex, RefCell<Log<RECORD_CAPACITY>>> =
+    Mutex::new(RefCell::new(Log::new()));
+
+// One export buffer shared by the console and the uploader
+static EXPORT: AsyncMutex<CriticalSectionRawMutex, [u8; EXPORT_LEN]> =
+    AsyncMutex::new([0; EXPORT_LEN]);
+
+/// The timestamp entries are logged with.
+pub fn now() -> u32 {
+    Instant::now().as_secs() as u32
+}
+
+/// Logs `event` and returns the timestamp it was logged with.
+pub fn record(event: Event) -> u32 {
+    let t_secs = now();
+    record_at(t_secs, event);
+    t_secs
+}
+
+/// Logs `event` with a timestamp taken earlier, for a decision made at
+/// that time.
+pub fn record_at(t_secs: u32, event: Event) {
+    LOG.lock(|log| log.borrow_mut().push(&Entry { t_secs, event }));
+}
+
+/// A consistent copy of the log, header included.
+pub struct Snapshot {
+    buf: MutexGuard<'static, CriticalSectionRawMutex, [u8; EXPORT_LEN]>,
+    len: usize,
+}
+
+impl Deref for Snapshot {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+pub async fn snapshot() -> Snapshot {
+    let mut buf = EXPORT.lock().await;
+    let header = Header {
+        settings: SETTINGS.watering,
+    };
+    let len = LOG.lock(|log| log.borrow().export(header, &mut buf[..]));
+    Snapshot { buf, len }
+}
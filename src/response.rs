@@ -0,0 +1,45 @@
+//! Soil moisture response to pump runs on the device; see
+//! `watering_core::response`. The tracker itself is part of the station in
+//! `decision`.
+
+use log::{info, warn};
+use watering_core::response::{Check, Curve, ResponseSettings};
+use watering_core::types::{Fault, HttpRequest, SystemEvent};
+
+use crate::channels::HTTP_CHANNEL;
+use crate::config::{
+    RESPONSE_MIN_ON_SECS, RESPONSE_MIN_RISE_PCT, RESPONSE_SATURATED_PCT, RESPONSE_WINDOW_SECS,
+};
+use crate::decision;
+use crate::faults;
+
+pub const SETTINGS: ResponseSettings = ResponseSettings {
+    window_secs: RESPONSE_WINDOW_SECS,
+    min_on_secs: RESPONSE_MIN_ON_SECS,
+    min_rise_pct: RESPONSE_MIN_RISE_PCT,
+    saturated_pct: RESPONSE_SATURATED_PCT,
+};
+
+/// Reports the check of a run once its window is over.
+pub fn report(check: Check) {
+    if check.responded {
+        info!(
+            "Soil moisture rose {}% after a {} secs run",
+            check.rise_pct, check.on_secs
+        );
+        faults::clear(Fault::NoResponse);
+    } else {
+        warn!(
+            "Soil moisture rose only {}% after a {} secs run (expected {:?}%)",
+            check.rise_pct, check.on_secs, check.expected_pct
+        );
+        faults::raise(Fault::NoResponse);
+    }
+    HTTP_CHANNEL
+        .try_send(HttpRequest::PostEvent(SystemEvent::WateringVerified(check)))
+        .ok();
+}
+
+pub fn curve() -> Option<Curve> {
+    decision::with(|station| station.response.curve())
+}
@@ -0,0 +1,35 @@
+use embassy_rp::pac;
+
+// GPIOs that drive actuators; must match the `Output` pins created in main
+const ACTUATOR_PINS: [usize; 1] = [15];
+
+/// Drives every actuator pin low through raw register writes.
+///
+/// Safe to call from the panic and fault handlers: it does not depend on
+/// HAL state or on the pins being owned by anyone. Also called first thing
+/// after reset, before the HAL is up.
+pub fn force_actuators_off() {
+    // Right after reset the GPIO blocks may still be held in reset, where
+    // writes are lost
+    pac::RESETS.reset().modify(|w| {
+        w.set_io_bank0(false);
+        w.set_pads_bank0(false);
+    });
+    while !{
+        let done = pac::RESETS.reset_done().read();
+        done.io_bank0() && done.pads_bank0()
+    } {}
+
+    for pin in ACTUATOR_PINS {
+        let mask = 1 << pin;
+        pac::SIO.gpio_out(0).value_clr().write_value(mask);
+        pac::SIO.gpio_oe(0).value_set().write_value(mask);
+        pac::IO_BANK0.gpio(pin).ctrl().write(|w| {
+            w.set_funcsel(pac::io::vals::Gpio0ctrlFuncsel::SIO_0 as _);
+        });
+        pac::PADS_BANK0.gpio(pin).modify(|w| {
+            w.set_od(false);
+            w.set_iso(false);
+        });
+    }
+}
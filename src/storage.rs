@@ -0,0 +1,85 @@
+//! The flash shared by the OTA updater and the settings kept across
+//! reboots.
+//!
+//! [`Stored`] lives in the STORAGE region of memory.x, written to its two
+//! sectors in turn so a reset during a write still leaves the other copy.
+
+use core::cell::RefCell;
+
+use embassy_embedded_hal::flash::partition::{self, BlockingPartition};
+use embassy_rp::Peri;
+use embassy_rp::flash::{self, Blocking, ERASE_SIZE, Flash};
+use embassy_rp::peripherals::FLASH;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embedded_storage::nor_flash::{NorFlash, ReadNorFlash};
+use static_cell::StaticCell;
+use watering_core::dosing::{STORED_LEN, Stored};
+
+pub const FLASH_SIZE: usize = 4 * 1024 * 1024;
+
+pub type BoardFlash = Flash<'static, FLASH, Blocking, FLASH_SIZE>;
+pub type SharedFlash = Mutex<CriticalSectionRawMutex, RefCell<BoardFlash>>;
+pub type FlashPartition = BlockingPartition<'static, CriticalSectionRawMutex, BoardFlash>;
+pub type StorageError = partition::Error<flash::Error>;
+
+unsafe extern "C" {
+    static __storage_start: u32;
+    static __storage_end: u32;
+}
+
+pub fn init_flash(flash: Peri<'static, FLASH>) -> &'static SharedFlash {
+    static FLASH_MUTEX: StaticCell<SharedFlash> = StaticCell::new();
+    FLASH_MUTEX.init(Mutex::new(RefCell::new(Flash::new_blocking(flash))))
+}
+
+pub struct Storage {
+    partition: FlashPartition,
+    // Sector the next save goes to
+    next: u32,
+    seq: u32,
+}
+
+impl Storage {
+    pub fn new(flash: &'static SharedFlash) -> Self {
+        // Linker symbols: only their addresses mean anything
+        let start = &raw const __storage_start as u32;
+        let end = &raw const __storage_end as u32;
+        Self {
+            partition: BlockingPartition::new(flash, start, end - start),
+            next: 0,
+            seq: 0,
+        }
+    }
+
+    /// The newest valid copy; `None` on a fresh device.
+    pub fn load(&mut self) -> Option<Stored> {
+        let copies = [0, 1].map(|sector| {
+            let mut buf = [0u8; STORED_LEN];
+            self.partition
+                .read(sector * ERASE_SIZE as u32, &mut buf)
+                .ok()
+                .and_then(|_| Stored::decode(&buf))
+        });
+        let newest = Stored::newest(copies[0], copies[1])?;
+
+        // Keep the newest copy until the next one is safely written
+        self.next = if copies[0] == Some(newest) { 1 } else { 0 };
+        self.seq = newest.seq;
+        Some(newest)
+    }
+
+    pub fn save(&mut self, stored: &Stored) -> Result<(), StorageError> {
+        let stored = Stored {
+            seq: self.seq.wrapping_add(1),
+            ..*stored
+        };
+        let offset = self.next * ERASE_SIZE as u32;
+        self.partition.erase(offset, offset + ERASE_SIZE as u32)?;
+        self.partition.write(offset, &stored.encode())?;
+
+        self.seq = stored.seq;
+        self.next ^= 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+//! Tank volume, daily use and refills on the device; see
+//! `watering_core::tank`. The tracker itself is part of the station in
+//! `decision`.
+
+use log::info;
+use watering_core::tank::{Refill, TankGeometry, TankSettings, TankStatus};
+use watering_core::types::{Fault, HttpRequest, SystemEvent};
+
+use crate::channels::HTTP_CHANNEL;
+use crate::config::{
+    TANK_HEIGHT_CM, TANK_NOISE_L, TANK_REFILL_RISE_L, TANK_REMINDER_DAYS, TANK_SENSOR_OFFSET_CM,
+    TANK_SHAPE,
+};
+use crate::decision;
+use crate::faults;
+
+pub const SETTINGS: TankSettings = TankSettings {
+    geometry: TankGeometry {
+        shape: TANK_SHAPE,
+        height_cm: TANK_HEIGHT_CM,
+        sensor_offset_cm: TANK_SENSOR_OFFSET_CM,
+    },
+    noise_l: TANK_NOISE_L,
+    refill_rise_l: TANK_REFILL_RISE_L,
+    reminder_days: TANK_REMINDER_DAYS,
+};
+
+/// Reports a refill and keeps the refill reminder in step with `status`.
+pub fn report(refill: Option<Refill>, status: &TankStatus) {
+    if let Some(refill) = refill {
+        info!("Tank refilled: {} l to {} l", refill.from_l, refill.to_l);
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::TankRefilled(refill)))
+            .ok();
+    }
+    if status.refill_due {
+        faults::raise(Fault::RefillTank);
+    } else {
+        faults::clear(Fault::RefillTank);
+    }
+}
+
+pub fn status() -> TankStatus {
+    decision::with(|station| station.tank.status(&SETTINGS))
+}
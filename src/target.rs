@@ -0,0 +1,64 @@
+//! Target-moisture mode on the device: runs sized from the learned gain
+//! whenever the soil drops below the band; see `watering_core::target`.
+//! The controller itself is part of the station in `decision`.
+
+use log::info;
+use watering_core::target::{Band, TargetSettings};
+
+use crate::config::{
+    PUMP_MAX_DURATION_SECS, RESPONSE_MIN_ON_SECS, RESPONSE_WINDOW_SECS, TARGET_INITIAL_GAIN,
+    TARGET_MAX_GAIN, TARGET_MAX_STEP, TARGET_MIN_GAIN,
+};
+use crate::decision;
+use crate::dosing;
+
+pub const SETTINGS: TargetSettings = TargetSettings {
+    initial_gain: TARGET_INITIAL_GAIN,
+    min_gain: TARGET_MIN_GAIN,
+    max_gain: TARGET_MAX_GAIN,
+    max_step: TARGET_MAX_STEP,
+    min_interval_secs: RESPONSE_WINDOW_SECS,
+    min_secs: RESPONSE_MIN_ON_SECS as u16,
+    max_secs: PUMP_MAX_DURATION_SECS,
+};
+
+/// Restores the band and gain kept in flash; after `dosing::init`.
+pub fn init() {
+    let stored = dosing::stored();
+    if let Some(band) = stored.target {
+        info!("Target moisture: {}-{}%", band.low, band.high);
+    }
+    decision::with(|station| station.targeting.restore(stored.target, stored.gain));
+}
+
+/// Sets the band, or leaves target mode with `None`. The server may send
+/// the same band with every poll; only a change is written to flash.
+pub fn set_band(band: Option<Band>) {
+    let changed = decision::with(|station| {
+        let controller = &mut station.targeting;
+        let changed = controller.band() != band;
+        controller.set_band(band);
+        changed.then(|| controller.learned_gain())
+    });
+    let Some(gain) = changed else {
+        return;
+    };
+    match band {
+        Some(band) => info!("Target moisture set to {}-{}%", band.low, band.high),
+        None => info!("Target moisture mode off"),
+    }
+    dosing::store_target(band, gain);
+}
+
+/// Writes the gain to flash once a run's response has moved it.
+pub fn store_gain() {
+    let (band, gain) =
+        decision::with(|station| (station.targeting.band(), station.targeting.learned_gain()));
+    info!("Target gain now {:?} %/s", gain);
+    dosing::store_target(band, gain);
+}
+
+/// The band and the gain runs are sized with.
+pub fn status() -> (Option<Band>, f32) {
+    decision::with(|station| (station.targeting.band(), station.targeting.gain(&SETTINGS)))
+}
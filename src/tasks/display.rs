@@ -1,19 +1,25 @@
 use core::fmt::Write;
 
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
-use embassy_time::Timer;
+use embassy_time::{Duration, Timer, with_timeout};
 use embedded_graphics::{
-    mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
+    mono_font::{MonoTextStyle, MonoTextStyleBuilder, ascii::FONT_6X10},
     pixelcolor::BinaryColor,
     prelude::*,
     text::{Baseline, Text},
 };
 use heapless::String;
 use log::info;
-use ssd1306::{prelude::*, I2CDisplayInterface, Ssd1306Async};
+use ssd1306::{I2CDisplayInterface, Ssd1306Async, prelude::*};
+use watering_core::types::{Fault, SensorData, TaskId};
 
-use crate::channels::SENSOR_CHANNEL;
 use crate::I2cBus;
+use crate::backoff::Backoff;
+use crate::bus;
+use crate::channels::SENSOR_CHANNEL;
+use crate::config::{HEARTBEAT_INTERVAL_SECS, I2C_TIMEOUT_MS, MAX_CONSECUTIVE_FAILURES};
+use crate::faults;
+use crate::heartbeat;
 
 #[embassy_executor::task]
 pub async fn display_task(i2c_bus: &'static I2cBus) {
@@ -24,58 +30,151 @@ pub async fn display_task(i2c_bus: &'static I2cBus) {
     let mut display = Ssd1306Async::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
         .into_buffered_graphics_mode();
 
-    if display.init().await.is_err() {
-        info!("Failed to init OLED!");
-        loop {
-            Timer::after_secs(10).await;
-        }
-    }
-
-    info!("OLED initialized!");
-
     let text_style = MonoTextStyleBuilder::new()
         .font(&FONT_6X10)
         .text_color(BinaryColor::On)
         .build();
 
-    display.clear_buffer();
-    Text::with_baseline("Waiting for", Point::new(10, 10), text_style, Baseline::Top)
-        .draw(&mut display)
-        .unwrap();
-    Text::with_baseline("sensor data...", Point::new(10, 25), text_style, Baseline::Top)
-        .draw(&mut display)
-        .unwrap();
-    display.flush().await.ok();
+    let i2c_timeout = Duration::from_millis(I2C_TIMEOUT_MS);
+    let mut backoff = Backoff::new();
+    let mut ready = false;
+    let mut flush_failures: u8 = 0;
+    let mut last: Option<SensorData> = None;
+    // Advances with every reading; see `render`
+    let mut page: usize = 0;
 
     loop {
-        let data = SENSOR_CHANNEL.receive().await;
+        heartbeat::beat(TaskId::Display);
+
+        if !ready {
+            if !matches!(with_timeout(i2c_timeout, display.init()).await, Ok(Ok(_))) {
+                faults::raise(Fault::DisplayInit);
+                bus::recover(i2c_bus).await;
+                heartbeat::sleep(TaskId::Display, backoff.next()).await;
+                continue;
+            }
+
+            info!("OLED initialized!");
+            faults::clear(Fault::DisplayInit);
+            backoff.reset();
+            ready = true;
+            flush_failures = 0;
+        } else {
+            match with_timeout(
+                Duration::from_secs(HEARTBEAT_INTERVAL_SECS),
+                SENSOR_CHANNEL.receive(),
+            )
+            .await
+            {
+                Ok(data) => {
+                    last = Some(data);
+                    page = page.wrapping_add(1);
+                }
+                Err(_) => continue,
+            }
+        }
 
         display.clear_buffer();
+        render(&mut display, last.as_ref(), page, text_style);
 
-        let mut s: String<32> = String::new();
-        write!(s, "Temp: {}C", data.temperature as i32).unwrap();
-        Text::with_baseline(&s, Point::new(5, 5), text_style, Baseline::Top)
-            .draw(&mut display)
-            .unwrap();
+        if matches!(with_timeout(i2c_timeout, display.flush()).await, Ok(Ok(_))) {
+            flush_failures = 0;
+            faults::clear(Fault::DisplayFlush);
+        } else {
+            info!("Display flush error");
+            flush_failures += 1;
+            if flush_failures >= MAX_CONSECUTIVE_FAILURES {
+                faults::raise(Fault::DisplayFlush);
+                bus::recover(i2c_bus).await;
+                ready = false;
+            }
+        }
+    }
+}
 
-        s.clear();
-        write!(s, "Humidity: {}%", data.humidity as i32).unwrap();
-        Text::with_baseline(&s, Point::new(5, 20), text_style, Baseline::Top)
-            .draw(&mut display)
-            .unwrap();
+type Field<'a> = (&'a str, Option<f32>, usize, &'a str);
 
-        s.clear();
-        write!(s, "Pressure: {}hPa", data.pressure as i32).unwrap();
-        Text::with_baseline(&s, Point::new(5, 35), text_style, Baseline::Top)
-            .draw(&mut display)
-            .unwrap();
+// Rows of readings above the status line
+const ROWS: usize = 4;
 
-        Text::with_baseline("System OK", Point::new(5, 52), text_style, Baseline::Top)
-            .draw(&mut display)
+fn render<D>(
+    target: &mut D,
+    data: Option<&SensorData>,
+    page: usize,
+    text_style: MonoTextStyle<'_, BinaryColor>,
+) where
+    D: DrawTarget<Color = BinaryColor>,
+    D::Error: core::fmt::Debug,
+{
+    let Some(data) = data else {
+        Text::with_baseline("Waiting for", Point::new(10, 10), text_style, Baseline::Top)
+            .draw(target)
             .unwrap();
+        Text::with_baseline(
+            "sensor data...",
+            Point::new(10, 25),
+            text_style,
+            Baseline::Top,
+        )
+        .draw(target)
+        .unwrap();
+        return;
+    };
 
-        if display.flush().await.is_err() {
-            info!("Display flush error");
+    // label, value, decimals, unit; "--" while the BME280 is offline
+    let lines: [&[Field]; ROWS] = [
+        &[
+            ("T:", data.temperature, 0, "C"),
+            ("H:", data.humidity, 0, "%"),
+            ("DP:", data.dew_point, 0, "C"),
+        ],
+        &[
+            ("P:", data.pressure, 0, ""),
+            ("SLP:", data.sea_level_pressure, 0, "hPa"),
+        ],
+        &[
+            ("AH:", data.absolute_humidity, 1, "g"),
+            ("VPD:", data.vpd, 2, "kPa"),
+        ],
+        &[
+            ("HI:", data.heat_index, 0, "C"),
+            ("Dry in:", data.hours_until_dry, 0, "h"),
+        ],
+    ];
+
+    // Pages after the first list the telemetry, a row per measurement
+    let page = page % (1 + data.telemetry.len().div_ceil(ROWS));
+
+    let mut s: String<32> = String::new();
+    for (row, fields) in lines.iter().enumerate() {
+        s.clear();
+        if page > 0 {
+            if let Some(m) = data.telemetry.iter().nth((page - 1) * ROWS + row) {
+                write!(s, "{} {:.1}", m.name, m.value).unwrap();
+            }
+        } else {
+            for (i, &(label, value, decimals, unit)) in fields.iter().enumerate() {
+                if i > 0 {
+                    s.push(' ').unwrap();
+                }
+                match value {
+                    Some(v) => write!(s, "{}{:.*}{}", label, decimals, v, unit).unwrap(),
+                    None => write!(s, "{}--", label).unwrap(),
+                }
+            }
         }
+        Text::with_baseline(
+            &s,
+            Point::new(5, 5 + 12 * row as i32),
+            text_style,
+            Baseline::Top,
+        )
+        .draw(target)
+        .unwrap();
     }
+
+    let status = faults::first_active().map_or("System OK", Fault::message);
+    Text::with_baseline(status, Point::new(5, 53), text_style, Baseline::Top)
+        .draw(target)
+        .unwrap();
 }
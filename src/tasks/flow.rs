@@ -0,0 +1,37 @@
+use embassy_time::{Duration, Instant, Ticker};
+use log::info;
+use watering_core::flow::FlowMonitor;
+use watering_core::types::Fault;
+
+use crate::faults;
+use crate::flow::{self, SETTINGS};
+
+// How often the count is judged against the pump state
+const CHECK_INTERVAL_MS: u64 = 1000;
+
+/// Raises a fault on no flow while pumping or flow while the pump is off.
+/// The pulses are counted by the PIO, see `flow`.
+#[embassy_executor::task]
+pub async fn flow_task() {
+    info!("Flow task started");
+
+    let mut monitor = FlowMonitor::new();
+    let mut ticker = Ticker::every(Duration::from_millis(CHECK_INTERVAL_MS));
+
+    loop {
+        ticker.next().await;
+        let status = monitor.observe(
+            Instant::now().as_millis(),
+            flow::pump_on(),
+            flow::pulses(),
+            &SETTINGS,
+        );
+        for (fault, active) in [(Fault::NoFlow, status.no_flow), (Fault::Leak, status.leak)] {
+            if active {
+                faults::raise(fault);
+            } else {
+                faults::clear(fault);
+            }
+        }
+    }
+}
@@ -1,15 +1,48 @@
+use core::cell::RefCell;
+
 use embassy_rp::peripherals::USB;
 use embassy_rp::usb::Driver;
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_usb_logger::ReceiverHandler;
+use heapless::{String, Vec};
+
+use crate::console;
 
-struct Handler;
+struct Handler {
+    // Console input collected until end of line
+    line: Mutex<CriticalSectionRawMutex, RefCell<Vec<u8, 64>>>,
+}
 
 impl ReceiverHandler for Handler {
     fn new() -> Self {
-        Handler
+        Handler {
+            line: Mutex::new(RefCell::new(Vec::new())),
+        }
     }
 
-    async fn handle_data(&self, _data: &[u8]) {}
+    async fn handle_data(&self, data: &[u8]) {
+        for &byte in data {
+            let complete: Option<String<64>> = self.line.lock(|line| {
+                let mut line = line.borrow_mut();
+                if byte == b'\r' || byte == b'\n' {
+                    let text = core::str::from_utf8(&line)
+                        .ok()
+                        .and_then(|s| s.try_into().ok());
+                    line.clear();
+                    text
+                } else {
+                    // Overlong input is truncated, the command just won't match
+                    line.push(byte).ok();
+                    None
+                }
+            });
+
+            if let Some(text) = complete {
+                console::handle_line(&text).await;
+            }
+        }
+    }
 }
 
 #[embassy_executor::task]
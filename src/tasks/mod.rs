@@ -1,5 +1,8 @@
 pub mod display;
+pub mod flow;
 pub mod logger;
 pub mod network;
 pub mod pump;
 pub mod sensor;
+pub mod update;
+pub mod watchdog;
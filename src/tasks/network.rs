@@ -4,17 +4,30 @@ use embassy_net::dns::DnsSocket;
 use embassy_net::tcp::client::{TcpClient, TcpClientState};
 use embassy_rp::gpio::Output;
 use embassy_rp::peripherals::{DMA_CH0, PIO0};
-use embassy_time::{Duration, Timer};
+use embassy_time::{Duration, Timer, with_timeout};
 use heapless::String;
 use log::{error, info};
 use reqwless::client::{HttpClient, TlsConfig, TlsVerify};
 use reqwless::headers::ContentType;
 use reqwless::request::{Method, RequestBuilder};
 use static_cell::StaticCell;
-
-use crate::channels::{HTTP_CHANNEL, PUMP_CHANNEL};
-use crate::config::{API_KEY, POLL_INTERVAL_SECS, SENSOR_ENDPOINT, SERVER_URL, TASKS_ENDPOINT};
-use crate::types::{HttpRequest, PumpCommand, TasksResponse};
+use watering_core::net::{self, Endpoints, Request, Response};
+use watering_core::record::Event;
+use watering_core::traits::Transport;
+use watering_core::types::{HttpRequest, TaskId};
+
+use crate::channels::{HTTP_CHANNEL, OTA_CONFIRMED, PUMP_CALIBRATE, PUMP_CANCEL, PUMP_CHANNEL};
+use crate::config::{
+    API_KEY, EVENTS_ENDPOINT, HEARTBEAT_INTERVAL_SECS, POLL_INTERVAL_SECS, RECORD_ENDPOINT,
+    SENSOR_ENDPOINT, SERVER_URL, TASKS_ENDPOINT,
+};
+use crate::decision;
+use crate::dosing;
+use crate::heartbeat;
+use crate::recorder;
+use crate::target;
+use crate::tasks::update::{self, HttpsClient, Updater};
+use crate::vacation;
 
 pub type Cyw43Runner = cyw43::Runner<'static, Output<'static>, PioSpi<'static, PIO0, 0, DMA_CH0>>;
 
@@ -28,8 +41,75 @@ pub async fn net_task(mut runner: Runner<'static, cyw43::NetDriver<'static>>) ->
     runner.run().await
 }
 
+const ENDPOINTS: Endpoints<'static> = Endpoints {
+    sensor: SENSOR_ENDPOINT,
+    alert: "/alert",
+    events: EVENTS_ENDPOINT,
+    tasks: TASKS_ENDPOINT,
+};
+
+/// Carries core requests over the TLS client, one connection per request.
+struct HttpsTransport<'c, 'a> {
+    client: &'c mut HttpsClient<'a>,
+    rx_buffer: &'c mut [u8],
+}
+
+impl Transport for HttpsTransport<'_, '_> {
+    type Error = reqwless::Error;
+
+    async fn send(
+        &mut self,
+        request: &Request,
+        body: &mut [u8],
+    ) -> Result<Response, reqwless::Error> {
+        // Uploads send many requests in a row; each one is progress
+        heartbeat::beat(TaskId::Http);
+
+        let mut url: String<128> = String::new();
+        let _ = url.push_str(SERVER_URL);
+        let _ = url.push_str(&request.path);
+
+        let method = match request.method {
+            net::Method::Get => Method::GET,
+            net::Method::Post => Method::POST,
+        };
+
+        info!("{} ({} bytes)", url.as_str(), request.body.len());
+
+        let content_type = match request.content_type {
+            net::ContentType::Json => ContentType::ApplicationJson,
+            net::ContentType::OctetStream => ContentType::ApplicationOctetStream,
+        };
+
+        let req = self
+            .client
+            .request(method, url.as_str())
+            .await?
+            .headers(&[("X-Api-Key", API_KEY)]);
+
+        let response = if request.body.is_empty() {
+            req.send(self.rx_buffer).await?
+        } else {
+            req.body(request.body.as_slice())
+                .content_type(content_type)
+                .send(self.rx_buffer)
+                .await?
+        };
+
+        let status = response.status.0;
+        let mut len = 0;
+        if (200..300).contains(&status) && !body.is_empty() {
+            len = response.body().reader().read_to_end(body).await?;
+        }
+
+        Ok(Response { status, len })
+    }
+}
+
 #[embassy_executor::task]
-pub async fn http_task(stack: embassy_net::Stack<'static>, seed: u64) {
+pub async fn http_task(stack: embassy_net::Stack<'static>, seed: u64, mut updater: Updater) {
+    let mut confirmed = !update::is_trial_boot(&mut updater);
+
     stack.wait_link_up().await;
     stack.wait_config_up().await;
     info!("HTTP task: network ready");
@@ -40,7 +120,16 @@ pub async fn http_task(stack: embassy_net::Stack<'static>, seed: u64) {
     let dns_client = DnsSocket::new(stack);
 
     loop {
-        let request = HTTP_CHANNEL.receive().await;
+        heartbeat::beat(TaskId::Http);
+
+        let Ok(request) = with_timeout(
+            Duration::from_secs(HEARTBEAT_INTERVAL_SECS),
+            HTTP_CHANNEL.receive(),
+        )
+        .await
+        else {
+            continue;
+        };
 
         // Buffers for this request
         let mut rx_buffer = [0; 4096];
@@ -55,127 +144,89 @@ pub async fn http_task(stack: embassy_net::Stack<'static>, seed: u64) {
         );
 
         let mut https_client = HttpClient::new_with_tls(&tcp_client, &dns_client, tls_cfg);
-
-        let mut url: String<128> = String::new();
-        let _ = url.push_str(SERVER_URL);
-
-        match request {
-            HttpRequest::PostSensorData(data) => {
-                let _ = url.push_str(SENSOR_ENDPOINT);
-
-                // Serialize sensor data
-                let mut body_buffer = [0u8; 256];
-                let len = match serde_json_core::to_slice(&data, &mut body_buffer) {
-                    Ok(len) => len,
-                    Err(e) => {
-                        error!("Failed to serialize sensor data: {:?}", e);
-                        continue;
+        let mut transport = HttpsTransport {
+            client: &mut https_client,
+            rx_buffer: &mut rx_buffer,
+        };
+
+        let mut update_requested = false;
+        let mut upload_requested = false;
+
+        match net::exchange(&mut transport, &request, &ENDPOINTS).await {
+            Ok(exchange) => {
+                info!("Response: {}", exchange.status);
+
+                let is_poll = matches!(request, HttpRequest::PollTasks);
+                if is_poll && exchange.status == 200 && !confirmed {
+                    match updater.mark_booted() {
+                        Ok(()) => {
+                            info!("OTA: new firmware confirmed");
+                            confirmed = true;
+                            OTA_CONFIRMED.signal(());
+                        }
+                        Err(e) => error!("OTA: failed to confirm firmware: {:?}", e),
                     }
-                };
-
-                info!("POST {} ({} bytes)", url.as_str(), len);
+                }
 
-                let req = match https_client.request(Method::POST, url.as_str()).await {
-                    Ok(req) => req,
-                    Err(e) => {
-                        error!("Request failed: {:?}", e);
-                        continue;
+                if let Some(actions) = exchange.actions {
+                    // Before the command, so the run is planned for today
+                    if let Some(day) = actions.day_of_year {
+                        decision::set_day_of_year(day);
+                        vacation::report();
                     }
-                };
-
-                match req
-                    .body(&body_buffer[..len])
-                    .content_type(ContentType::ApplicationJson)
-                    .headers(&[("X-Api-Key", API_KEY)])
-                    .send(&mut rx_buffer)
-                    .await
-                {
-                    Ok(response) => {
-                        info!("Response: {}", response.status.0);
+                    if actions.cancel_pump {
+                        info!("Pump program cancel received");
+                        PUMP_CANCEL.signal(());
                     }
-                    Err(e) => {
-                        error!("POST failed: {:?}", e);
+                    if actions.calibrate_pump {
+                        info!("Pump calibration run requested");
+                        PUMP_CALIBRATE.signal(());
                     }
-                }
-            }
-
-            HttpRequest::SendAlert { message } => {
-                let _ = url.push_str("/alert");
-
-                info!("POST alert: {}", message.as_str());
-
-                let mut body_buffer = [0u8; 128];
-                let len = match serde_json_core::to_slice(&message, &mut body_buffer) {
-                    Ok(len) => len,
-                    Err(_) => continue,
-                };
-
-                let req = match https_client.request(Method::POST, url.as_str()).await {
-                    Ok(req) => req,
-                    Err(e) => {
-                        error!("Alert request failed: {:?}", e);
-                        continue;
+                    if let Some(ml) = actions.calibration_ml {
+                        dosing::calibrate(ml);
                     }
-                };
-
-                if let Err(e) = req
-                    .body(&body_buffer[..len])
-                    .content_type(ContentType::ApplicationJson)
-                    .headers(&[("X-Api-Key", API_KEY)])
-                    .send(&mut rx_buffer)
-                    .await
-                {
-                    error!("Alert POST failed: {:?}", e);
-                }
-            }
-
-            HttpRequest::PollTasks => {
-                let _ = url.push_str(TASKS_ENDPOINT);
-
-                let req = match https_client.request(Method::GET, url.as_str()).await {
-                    Ok(req) => req,
-                    Err(e) => {
-                        error!("Poll request failed: {:?}", e);
-                        continue;
+                    if actions.target_off {
+                        target::set_band(None);
+                    } else if actions.target.is_some() {
+                        target::set_band(actions.target);
                     }
-                };
-
-                match req
-                    .headers(&[("X-Api-Key", API_KEY)])
-                    .send(&mut rx_buffer)
-                    .await
-                {
-                    Ok(response) => {
-                        info!("Tasks response: {}", response.status.0);
-
-                        if response.status.0 == 200 {
-                            let mut body_buf = [0u8; 256];
-                            match response.body().reader().read_to_end(&mut body_buf).await {
-                                Ok(len) => {
-                                    if let Ok((tasks, _)) =
-                                        serde_json_core::from_slice::<TasksResponse>(&body_buf[..len])
-                                    {
-                                        if tasks.pump_duration > 0 {
-                                            info!("Pump command received: {} secs", tasks.pump_duration);
-                                            PUMP_CHANNEL
-                                                .try_send(PumpCommand {
-                                                    duration_secs: tasks.pump_duration,
-                                                })
-                                                .ok();
-                                        }
-                                    }
-                                }
-                                Err(e) => {
-                                    error!("Failed to read body: {:?}", e);
-                                }
-                            }
-                        }
+                    if actions.vacation_off {
+                        vacation::set(None);
+                    } else if actions.vacation_until.is_some() {
+                        vacation::set(actions.vacation_until);
                     }
-                    Err(e) => {
-                        error!("Poll tasks failed: {:?}", e);
+                    if let Some(cmd) = actions
+                        .pump
+                        .and_then(dosing::resolve)
+                        .and_then(vacation::scale)
+                    {
+                        info!(
+                            "Pump command received: {} x {} secs, {} secs soak",
+                            cmd.pulses, cmd.duration_secs, cmd.soak_secs
+                        );
+                        recorder::record(Event::Command(cmd));
+                        PUMP_CHANNEL.try_send(cmd).ok();
                     }
+                    update_requested = actions.firmware_update;
+                    upload_requested = actions.upload_record;
                 }
             }
+            Err(e) => {
+                error!("Request failed: {:?}", e);
+            }
+        }
+
+        if upload_requested {
+            let record = recorder::snapshot().await;
+            info!("Uploading record log ({} bytes)", record.len());
+            if let Err(e) = net::upload(&mut transport, RECORD_ENDPOINT, &record).await {
+                error!("Record upload failed: {:?}", e);
+            }
+        }
+
+        // A trial image must confirm itself before it may stage another
+        if update_requested && confirmed {
+            update::run(&mut https_client, &mut updater, &mut rx_buffer).await;
         }
     }
 }
@@ -1,24 +1,199 @@
+use embassy_futures::select::{Either, select};
 use embassy_rp::gpio::Output;
-use embassy_time::Timer;
-use log::info;
+use embassy_time::{Duration, with_timeout};
+use log::{info, warn};
+use watering_core::flow::ml_to_pulses;
+use watering_core::pump::{self, Outcome, Phase, Program, Progress, Supervisor};
+use watering_core::traits::Actuator;
+use watering_core::types::{HttpRequest, SystemEvent, TaskId};
 
-use crate::channels::PUMP_CHANNEL;
-use crate::config::PUMP_MAX_DURATION_SECS;
+use crate::channels::{HTTP_CHANNEL, PUMP_CALIBRATE, PUMP_CANCEL, PUMP_CHANNEL};
+use crate::config::{
+    CALIBRATION_SECS, FLOW_PULSES_PER_LITRE, HEARTBEAT_INTERVAL_SECS, PUMP_MAX_DURATION_SECS,
+};
+use crate::decision;
+use crate::dosing;
+use crate::flow;
+use crate::heartbeat::{self, HeartbeatClock};
+
+struct Pump(Output<'static>);
+
+impl Actuator for Pump {
+    fn set_on(&mut self, on: bool) {
+        if on {
+            self.0.set_high();
+        } else {
+            self.0.set_low();
+        }
+        flow::set_pump_on(on);
+    }
+}
+
+/// Stops on a stop command, reports each pulse and soak, and with a flow
+/// meter fitted ends a pulse once its volume has been metered.
+struct Watch {
+    // Meter pulses one program pulse should deliver
+    target: Option<u32>,
+    pulse_start: u32,
+}
+
+impl Watch {
+    fn new(program: Program) -> Self {
+        let target = dosing::ml_per_sec().filter(|_| flow::fitted()).map(|rate| {
+            let ml = watering_core::dosing::volume_ml(program.on_secs as u32, rate);
+            ml_to_pulses(ml, FLOW_PULSES_PER_LITRE)
+        });
+        Self {
+            target,
+            pulse_start: flow::pulses(),
+        }
+    }
+}
+
+impl Supervisor for Watch {
+    fn cancelled(&mut self) -> bool {
+        PUMP_CANCEL.signaled()
+    }
+
+    fn pulse_done(&mut self) -> bool {
+        self.target
+            .is_some_and(|target| flow::pulses().wrapping_sub(self.pulse_start) >= target)
+    }
+
+    fn progress(&mut self, progress: Progress) {
+        if progress.phase == Phase::On {
+            self.pulse_start = flow::pulses();
+        }
+        info!(
+            "Pump pulse {}/{}: {}",
+            progress.pulse,
+            progress.pulses,
+            progress.phase.name()
+        );
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::WateringProgress {
+                pulse: progress.pulse,
+                pulses: progress.pulses,
+                phase: progress.phase,
+            }))
+            .ok();
+    }
+}
 
 #[embassy_executor::task]
-pub async fn pump_task(mut pump_pin: Output<'static>) {
+pub async fn pump_task(pump_pin: Output<'static>) {
     info!("Pump task started");
 
+    let mut pump = Pump(pump_pin);
+    let mut clock = HeartbeatClock(TaskId::Pump);
+
     loop {
-        let cmd = PUMP_CHANNEL.receive().await;
+        heartbeat::beat(TaskId::Pump);
+
+        let Ok(request) = with_timeout(
+            Duration::from_secs(HEARTBEAT_INTERVAL_SECS),
+            select(PUMP_CHANNEL.receive(), PUMP_CALIBRATE.wait()),
+        )
+        .await
+        else {
+            continue;
+        };
+
+        let cmd = match request {
+            Either::First(cmd) => cmd,
+            Either::Second(()) => {
+                calibrate(&mut pump, &mut clock).await;
+                continue;
+            }
+        };
+
+        let program = match decision::decide(cmd) {
+            Ok(program) => program,
+            Err(reason) => {
+                warn!(
+                    "Pump run of {} secs skipped: {}",
+                    cmd.duration_secs,
+                    reason.name()
+                );
+                HTTP_CHANNEL
+                    .try_send(HttpRequest::PostEvent(SystemEvent::WateringSkipped {
+                        reason,
+                        requested_secs: cmd.duration_secs,
+                    }))
+                    .ok();
+                continue;
+            }
+        };
+        info!(
+            "Pump program: {} x {} secs, {} secs soak",
+            program.pulses, program.on_secs, program.soak_secs
+        );
+
+        let start = flow::pulses();
+        let outcome = run(&mut pump, &mut clock, program).await;
+        let metered = flow::fitted().then(|| flow::ml_since(start));
+        let volume_ml = dosing::record_run(outcome.on_secs(), metered);
+        decision::watered(outcome.on_secs());
+
+        let cancelled = matches!(outcome, Outcome::Cancelled { .. });
+        if cancelled {
+            warn!("Pump program cancelled after {} secs on", outcome.on_secs());
+        } else {
+            info!("Pump program done, {} secs on", outcome.on_secs());
+        }
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::WateringFinished {
+                on_secs: outcome.on_secs(),
+                cancelled,
+                volume_ml,
+            }))
+            .ok();
+    }
+}
+
+async fn run(pump: &mut Pump, clock: &mut HeartbeatClock, program: Program) -> Outcome {
+    // A stop sent while idle must not cut the next program short
+    PUMP_CANCEL.reset();
+    pump::run_program(pump, clock, program, &mut Watch::new(program)).await
+}
 
-        let duration = cmd.duration_secs.min(PUMP_MAX_DURATION_SECS);
-        info!("Pump ON for {} secs", duration);
+// Runs the pump into a measuring jug; the volume is entered afterwards,
+// unless a flow meter measures it
+async fn calibrate(pump: &mut Pump, clock: &mut HeartbeatClock) {
+    let program = pump::limit(Program::single(CALIBRATION_SECS), PUMP_MAX_DURATION_SECS);
+    info!("Calibration run: {} secs", program.on_secs);
 
-        pump_pin.set_high();
-        Timer::after_secs(duration as u64).await;
-        pump_pin.set_low();
+    // A full run, not cut short by the old calibration's volume
+    PUMP_CANCEL.reset();
+    let start = flow::pulses();
+    let outcome = pump::run_program(
+        pump,
+        clock,
+        program,
+        &mut Watch {
+            target: None,
+            pulse_start: start,
+        },
+    )
+    .await;
+    dosing::calibration_run_done(outcome.on_secs());
 
-        info!("Pump OFF");
+    if flow::fitted() {
+        let ml = flow::ml_since(start).min(u16::MAX as u32) as u16;
+        info!(
+            "Calibration run done after {} secs, {} ml metered",
+            outcome.on_secs(),
+            ml
+        );
+        if ml > 0 {
+            dosing::calibrate(ml);
+        } else {
+            warn!("Calibration: no flow metered, keeping the old rate");
+        }
+    } else {
+        info!(
+            "Calibration run done after {} secs; enter the measured volume with `calibrate <ml>`",
+            outcome.on_secs()
+        );
     }
 }
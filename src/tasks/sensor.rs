@@ -1,151 +1,313 @@
 use bme280_rs::{AsyncBme280, Configuration, Oversampling, SensorMode};
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDevice;
+use embassy_futures::join::join;
 use embassy_rp::adc::{Adc, Async, Channel};
 use embassy_rp::gpio::{Input, Output};
-use embassy_time::Timer;
+use embassy_rp::i2c;
+use embassy_rp::peripherals::I2C1;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_time::{Delay, Duration, Instant, Timer, with_timeout};
+use heapless::Vec;
 use log::info;
+use watering_core::quality::Quality;
+use watering_core::sensors;
+use watering_core::soil;
+use watering_core::traits::{EnvReading, EnvSensor, RangeSensor, SoilProbe};
+use watering_core::types::{Fault, HttpRequest, TaskId};
 
 use crate::I2cBus;
+use crate::backoff::Backoff;
+use crate::bus;
 use crate::channels::{HTTP_CHANNEL, SENSOR_CHANNEL};
-use crate::config::SENSOR_INTERVAL_MS;
-use crate::types::{HttpRequest, SensorData};
+use crate::config::{
+    ADC_TIMEOUT_MS, I2C_TIMEOUT_MS, MAX_CONSECUTIVE_FAILURES, SENSOR_INTERVAL_MS, SOIL_SAMPLES,
+    SOIL_SETTLE_MS, SOIL_TRIM, SONAR_ECHO_TIMEOUT_MS, SONAR_MAX_ECHO_US, SONAR_MIN_ECHO_US,
+    SONAR_PINGS,
+};
+use crate::decision;
+use crate::dosing;
+use crate::drivers::{I2cSensor, MAX_DRIVERS};
+use crate::faults;
+use crate::heartbeat;
+use crate::onewire::SoilProbes;
 
-pub const SOIL_DRY: u16 = 3550; // air = 0% moisture
-pub const SOIL_WET: u16 = 150; // water = 100% moisture
+type Bme280 = AsyncBme280<
+    I2cDevice<'static, CriticalSectionRawMutex, i2c::I2c<'static, I2C1, i2c::Async>>,
+    Delay,
+>;
 
-#[embassy_executor::task]
-pub async fn sensor_task(
+/// BME280 that re-initializes with backoff and recovers the bus when it
+/// stops answering, so a loose wire never takes the other sensors down.
+struct SupervisedBme280 {
+    bme280: Bme280,
     i2c_bus: &'static I2cBus,
-    mut adc: Adc<'static, Async>,
-    mut soil_pin: Channel<'static>,
-    mut trigger: Output<'static>,
-    echo: Input<'static>,
-) {
-    Timer::after_millis(100).await;
+    ready: bool,
+    failures: u8,
+    backoff: Backoff,
+    next_init: Instant,
+}
 
-    let i2c_dev = I2cDevice::new(i2c_bus);
-    let delay = embassy_time::Delay;
+impl SupervisedBme280 {
+    fn new(i2c_bus: &'static I2cBus) -> Self {
+        Self {
+            bme280: AsyncBme280::new(I2cDevice::new(i2c_bus), Delay),
+            i2c_bus,
+            ready: false,
+            failures: 0,
+            backoff: Backoff::new(),
+            next_init: Instant::now(),
+        }
+    }
 
-    let mut bme280: AsyncBme280<_, _> = AsyncBme280::new(i2c_dev, delay);
+    async fn init(&mut self) {
+        let timeout = Duration::from_millis(I2C_TIMEOUT_MS);
 
-    if bme280.init().await.is_err() {
-        info!("Failed to init BME280!");
-        loop {
-            Timer::after_secs(10).await;
+        if !matches!(with_timeout(timeout, self.bme280.init()).await, Ok(Ok(_))) {
+            faults::raise(Fault::Bme280Init);
+            bus::recover(self.i2c_bus).await;
+            self.next_init = Instant::now() + self.backoff.next();
+            return;
         }
-    }
 
-    if bme280
-        .set_sampling_configuration(
-            Configuration::default()
-                .with_temperature_oversampling(Oversampling::Oversample1)
-                .with_pressure_oversampling(Oversampling::Oversample1)
-                .with_humidity_oversampling(Oversampling::Oversample1)
-                .with_sensor_mode(SensorMode::Normal),
+        let configured = with_timeout(
+            timeout,
+            self.bme280.set_sampling_configuration(
+                Configuration::default()
+                    .with_temperature_oversampling(Oversampling::Oversample1)
+                    .with_pressure_oversampling(Oversampling::Oversample1)
+                    .with_humidity_oversampling(Oversampling::Oversample1)
+                    .with_sensor_mode(SensorMode::Normal),
+            ),
         )
-        .await
-        .is_err()
-    {
-        info!("Failed to configure BME280!");
+        .await;
+        if !matches!(configured, Ok(Ok(_))) {
+            info!("Failed to configure BME280!");
+        }
+
+        info!("BME280 initialized!");
+        faults::clear(Fault::Bme280Init);
+        self.ready = true;
+        self.failures = 0;
+        self.backoff.reset();
+        // Let the first measurement complete
+        Timer::after_millis(100).await;
     }
+}
+
+impl EnvSensor for SupervisedBme280 {
+    type Error = ();
 
-    info!("BME280 initialized!");
+    async fn read(&mut self) -> Result<EnvReading, ()> {
+        if !self.ready && Instant::now() >= self.next_init {
+            self.init().await;
+        }
+        if !self.ready {
+            return Err(());
+        }
 
-    Timer::after_millis(10000).await;
+        let timeout = Duration::from_millis(I2C_TIMEOUT_MS);
+        let temp = with_timeout(timeout, self.bme280.read_temperature()).await;
+        let hum = with_timeout(timeout, self.bme280.read_humidity()).await;
+        let press = with_timeout(timeout, self.bme280.read_pressure()).await;
 
-    loop {
-        // meteo
-        let temp = bme280.read_temperature().await;
-        let hum = bme280.read_humidity().await;
-        let press = bme280.read_pressure().await;
-
-        // soil
-        let soil_raw: u16 = adc.read(&mut soil_pin).await.unwrap();
-        let clamped = soil_raw.clamp(SOIL_WET, SOIL_DRY);
-        let soil_moisture = ((SOIL_DRY - clamped) as f32 / (SOIL_DRY - SOIL_WET) as f32) * 100.0;
-
-        // TODO: sonar is buggy, fix later
-        let water_level = 0.0;
-        let _ = (&mut trigger, &echo);
-
-        match (temp, hum, press) {
-            (Ok(Some(t)), Ok(Some(h)), Ok(Some(p))) => {
-                let data = SensorData {
-                    temperature: t,
-                    humidity: h,
-                    pressure: p / 100.0,
-                    soil_moisture,
-                    water_level,
-                };
-
-                info!(
-                    "T: {}C, H: {}%, P: {}hPa, SM: {:.2}%, WL: {:.2}cm",
-                    data.temperature as i32,
-                    data.humidity as i32,
-                    data.pressure as i32,
-                    data.soil_moisture,
-                    data.water_level
-                );
-
-                SENSOR_CHANNEL.try_send(data).ok();
-                HTTP_CHANNEL
-                    .try_send(HttpRequest::PostSensorData(data))
-                    .ok();
-            }
-            _ => {
-                info!("BME280 read error");
-            }
+        // Whatever was read is kept, even if another field failed
+        let reading = EnvReading {
+            temperature: temp.ok().and_then(|r| r.ok()).flatten(),
+            humidity: hum.ok().and_then(|r| r.ok()).flatten(),
+            pressure: press.ok().and_then(|r| r.ok()).flatten().map(|p| p / 100.0),
+        };
+        if let EnvReading {
+            temperature: Some(_),
+            humidity: Some(_),
+            pressure: Some(_),
+        } = reading
+        {
+            self.failures = 0;
+            faults::clear(Fault::Bme280Read);
+            return Ok(reading);
         }
 
-        Timer::after_millis(SENSOR_INTERVAL_MS).await;
+        info!("BME280 read error");
+        self.failures += 1;
+        if self.failures >= MAX_CONSECUTIVE_FAILURES {
+            faults::raise(Fault::Bme280Read);
+            bus::recover(self.i2c_bus).await;
+            self.ready = false;
+            self.next_init = Instant::now();
+        }
+        match reading {
+            EnvReading {
+                temperature: None,
+                humidity: None,
+                pressure: None,
+            } => Err(()),
+            _ => Ok(reading),
+        }
     }
 }
 
-async fn measure_distance(trigger: &mut Output<'static>, echo: &Input<'static>) -> Option<f32> {
-    trigger.set_high();
-    Timer::after_micros(10).await;
-    trigger.set_low();
+struct AdcSoilProbe {
+    adc: Adc<'static, Async>,
+    pin: Channel<'static>,
+    power: Output<'static>,
+}
 
-    let start_wait = embassy_time::Instant::now();
-    while echo.is_low() {
-        if start_wait.elapsed().as_millis() > 30 {
-            return None;
+impl AdcSoilProbe {
+    async fn oversample(&mut self) -> Result<u16, ()> {
+        let mut samples = [0; SOIL_SAMPLES];
+        for sample in samples.iter_mut() {
+            *sample = match with_timeout(
+                Duration::from_millis(ADC_TIMEOUT_MS),
+                self.adc.read(&mut self.pin),
+            )
+            .await
+            {
+                Ok(Ok(raw)) => raw,
+                _ => return Err(()),
+            };
         }
+        soil::trimmed_mean(&mut samples, SOIL_TRIM).ok_or(())
     }
+}
+
+impl SoilProbe for AdcSoilProbe {
+    type Error = ();
+
+    /// Powers the probe up, lets it settle and takes the trimmed mean of
+    /// `SOIL_SAMPLES` readings.
+    async fn read_raw(&mut self) -> Result<u16, ()> {
+        self.power.set_high();
+        Timer::after_millis(SOIL_SETTLE_MS).await;
+        let raw = self.oversample().await;
+        self.power.set_low();
 
-    let start = embassy_time::Instant::now();
-    while echo.is_high() {
-        if start.elapsed().as_millis() > 30 {
-            return None;
+        match raw {
+            Ok(raw) => {
+                faults::clear(Fault::SoilRead);
+                Ok(raw)
+            }
+            Err(()) => {
+                faults::raise(Fault::SoilRead);
+                Err(())
+            }
         }
     }
+}
 
-    let duration_us = start.elapsed().as_micros();
-    Some(duration_us as f32 / 58.0)
+struct Sonar {
+    trigger: Output<'static>,
+    echo: Input<'static>,
 }
 
-async fn measure_distance_avg(
-    trigger: &mut Output<'static>,
-    echo: &Input<'static>,
-    samples: u8,
-) -> Option<f32> {
-    let mut total = 0.0;
-    let mut valid = 0;
-
-    for _ in 0..samples {
-        if let Some(dist) = measure_distance(trigger, echo).await {
-            if dist > 2.0 && dist < 400.0 {
-                // valid range
-                total += dist;
+impl Sonar {
+    /// One ping; `None` if no echo came back in time or it was out of
+    /// range.
+    ///
+    /// The edges are timestamped as soon as the GPIO interrupt wakes the
+    /// task, so other tasks keep running while the echo is out.
+    async fn ping(&mut self) -> Option<u64> {
+        let timeout = Duration::from_millis(SONAR_ECHO_TIMEOUT_MS);
+        let longest = Duration::from_micros(SONAR_MAX_ECHO_US);
+        self.trigger.set_high();
+        Timer::after_micros(10).await;
+        self.trigger.set_low();
+
+        with_timeout(timeout, self.echo.wait_for_rising_edge())
+            .await
+            .ok()?;
+        let start = Instant::now();
+        with_timeout(longest, self.echo.wait_for_falling_edge())
+            .await
+            .ok()?;
+        Some(start.elapsed().as_micros())
+    }
+}
+
+impl RangeSensor for Sonar {
+    type Error = ();
+
+    /// Mean echo time of `SONAR_PINGS` pings, leaving out echoes outside
+    /// the sensor's range.
+    async fn echo_us(&mut self) -> Result<f32, ()> {
+        let mut total = 0;
+        let mut valid = 0;
+
+        for _ in 0..SONAR_PINGS {
+            if let Some(echo_us) = self.ping().await
+                && (SONAR_MIN_ECHO_US..=SONAR_MAX_ECHO_US).contains(&echo_us)
+            {
+                total += echo_us;
                 valid += 1;
             }
+            // Let the last ping's echoes die down
+            Timer::after_millis(60).await;
+        }
+
+        match valid {
+            0 => Err(()),
+            _ => Ok(total as f32 / valid as f32),
         }
-        Timer::after_millis(60).await; // wait between pings
     }
+}
 
-    if valid > 0 {
-        Some(total / valid as f32)
-    } else {
-        None
+#[embassy_executor::task]
+pub async fn sensor_task(
+    i2c_bus: &'static I2cBus,
+    adc: Adc<'static, Async>,
+    soil_pin: Channel<'static>,
+    soil_power: Output<'static>,
+    trigger: Output<'static>,
+    echo: Input<'static>,
+    mut i2c_sensors: Vec<I2cSensor, MAX_DRIVERS>,
+    mut soil_probes: SoilProbes,
+) {
+    Timer::after_millis(100).await;
+
+    let mut env = SupervisedBme280::new(i2c_bus);
+    let mut soil = AdcSoilProbe {
+        adc,
+        pin: soil_pin,
+        power: soil_power,
+    };
+    let mut sonar = Sonar { trigger, echo };
+
+    loop {
+        heartbeat::beat(TaskId::Sensor);
+
+        // The probes convert while the other sensors are read
+        let (mut data, soil_temperatures) = join(
+            sensors::sample(&mut env, &mut soil, &mut sonar),
+            soil_probes.read(),
+        )
+        .await;
+        data.soil_temperatures = soil_temperatures;
+        data.telemetry = sensors::read_drivers(&mut i2c_sensors).await;
+        decision::refine(&mut data);
+        decision::observe(&mut data);
+        dosing::report(&mut data);
+
+        info!(
+            "T: {:?}C, H: {:?}%, P: {:?}hPa, SM: {:?}% ({:?}% raw), WL: {:?}cm, L: {:?}lx",
+            data.temperature,
+            data.humidity,
+            data.pressure,
+            data.soil_moisture,
+            data.soil_moisture_raw,
+            data.water_level,
+            data.lux
+        );
+        for (rom, celsius) in data.soil_temperatures.iter() {
+            info!("Soil {}: {}C", rom, celsius);
+        }
+        if data.quality != Quality::default() {
+            info!("Reading flagged: {:?}", data.quality);
+        }
+
+        SENSOR_CHANNEL.try_send(data).ok();
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostSensorData(data))
+            .ok();
+
+        Timer::after_millis(SENSOR_INTERVAL_MS).await;
     }
 }
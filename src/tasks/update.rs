@@ -0,0 +1,179 @@
+use core::fmt::Write;
+
+use embassy_boot_rp::{AlignedBuffer, BlockingFirmwareUpdater, FirmwareUpdaterConfig, State};
+use embassy_net::dns::DnsSocket;
+use embassy_net::tcp::client::TcpClient;
+use embassy_time::{Duration, with_timeout};
+use heapless::String;
+use log::{error, info};
+use reqwless::client::HttpClient;
+use static_cell::StaticCell;
+use watering_core::ota::{CHUNK_SIZE, Download, Manifest};
+use watering_core::types::TaskId;
+
+use crate::channels::OTA_CONFIRMED;
+use crate::config::{
+    API_KEY, OTA_CONFIRM_TIMEOUT_SECS, OTA_IMAGE_ENDPOINT, OTA_MANIFEST_ENDPOINT, OTA_PUBLIC_KEY,
+    SERVER_URL,
+};
+use crate::crash;
+use crate::heartbeat;
+use crate::storage::{FlashPartition, SharedFlash};
+
+pub type Updater = BlockingFirmwareUpdater<'static, FlashPartition, FlashPartition>;
+pub type HttpsClient<'a> = HttpClient<'a, TcpClient<'static, 1, 4096, 4096>, DnsSocket<'static>>;
+
+pub fn new_updater(flash: &'static SharedFlash) -> Updater {
+    static ALIGNED: StaticCell<AlignedBuffer<1>> = StaticCell::new();
+
+    let config = FirmwareUpdaterConfig::from_linkerfile_blocking(flash, flash);
+    BlockingFirmwareUpdater::new(config, &mut ALIGNED.init(AlignedBuffer([0; 1])).0)
+}
+
+/// True while running a freshly swapped image that has not confirmed itself yet.
+pub fn is_trial_boot(updater: &mut Updater) -> bool {
+    matches!(updater.get_state(), Ok(State::Swap))
+}
+
+/// Reboots into the previous image unless the new one reaches the server in time.
+#[embassy_executor::task]
+pub async fn rollback_task() {
+    info!("OTA: trial boot, waiting for server contact");
+
+    if with_timeout(
+        Duration::from_secs(OTA_CONFIRM_TIMEOUT_SECS),
+        OTA_CONFIRMED.wait(),
+    )
+    .await
+    .is_err()
+    {
+        error!("OTA: new firmware never reached the server, rolling back");
+        crash::reboot();
+    }
+}
+
+/// Downloads, verifies and stages a new image, then reboots into it.
+///
+/// Returns only if there is nothing to install or something went wrong.
+pub async fn run(client: &mut HttpsClient<'_>, updater: &mut Updater, rx_buffer: &mut [u8]) {
+    let mut resource = match client.resource(SERVER_URL).await {
+        Ok(resource) => resource,
+        Err(e) => {
+            error!("OTA: connect failed: {:?}", e);
+            return;
+        }
+    };
+
+    let mut body_buf = [0u8; 512];
+    let len = match resource
+        .get(OTA_MANIFEST_ENDPOINT)
+        .headers(&[("X-Api-Key", API_KEY)])
+        .send(rx_buffer)
+        .await
+    {
+        Ok(response) if response.status.0 == 200 => {
+            match response.body().reader().read_to_end(&mut body_buf).await {
+                Ok(len) => len,
+                Err(e) => {
+                    error!("OTA: failed to read manifest: {:?}", e);
+                    return;
+                }
+            }
+        }
+        Ok(response) => {
+            error!("OTA: manifest response {}", response.status.0);
+            return;
+        }
+        Err(e) => {
+            error!("OTA: manifest request failed: {:?}", e);
+            return;
+        }
+    };
+
+    let Ok((manifest, _)) = serde_json_core::from_slice::<Manifest>(&body_buf[..len]) else {
+        error!("OTA: malformed manifest");
+        return;
+    };
+
+    let plan = match manifest.plan(env!("CARGO_PKG_VERSION")) {
+        Ok(plan) => plan,
+        Err(e) => {
+            info!(
+                "OTA: skipping {}: {}",
+                manifest.version.as_str(),
+                e.message()
+            );
+            return;
+        }
+    };
+    info!(
+        "OTA: downloading {} ({} bytes)",
+        manifest.version.as_str(),
+        plan.size
+    );
+
+    let mut download = Download::new(plan);
+    let mut chunk = AlignedBuffer([0u8; CHUNK_SIZE]);
+
+    while let Some((offset, len)) = download.next_chunk() {
+        heartbeat::beat(TaskId::Http);
+
+        let mut range: String<32> = String::new();
+        let _ = write!(range, "bytes={}-{}", offset, offset + len as u32 - 1);
+
+        let received = match resource
+            .get(OTA_IMAGE_ENDPOINT)
+            .headers(&[("X-Api-Key", API_KEY), ("Range", range.as_str())])
+            .send(rx_buffer)
+            .await
+        {
+            Ok(response) if response.status.0 == 206 => {
+                match response.body().reader().read_to_end(&mut chunk.0).await {
+                    Ok(received) => received,
+                    Err(e) => {
+                        error!("OTA: failed to read chunk at {}: {:?}", offset, e);
+                        return;
+                    }
+                }
+            }
+            Ok(response) => {
+                error!("OTA: chunk response {}", response.status.0);
+                return;
+            }
+            Err(e) => {
+                error!("OTA: chunk request failed: {:?}", e);
+                return;
+            }
+        };
+
+        if let Err(e) = download.accept(&chunk.0[..received]) {
+            error!("OTA: {}", e.message());
+            return;
+        }
+
+        // The updater erases whole sectors, so pad the last one with erased bytes
+        chunk.0[received..].fill(0xFF);
+        if let Err(e) = updater.write_firmware(offset as usize, &chunk.0) {
+            error!("OTA: flash write failed: {:?}", e);
+            return;
+        }
+    }
+
+    info!("OTA: {} bytes written, verifying", download.written());
+
+    let plan = match download.finish(&OTA_PUBLIC_KEY) {
+        Ok(plan) => plan,
+        Err(e) => {
+            error!("OTA: {}", e.message());
+            return;
+        }
+    };
+
+    if let Err(e) = updater.verify_and_mark_updated(&OTA_PUBLIC_KEY, &plan.signature, plan.size) {
+        error!("OTA: signature check failed: {:?}", e);
+        return;
+    }
+
+    info!("OTA: update staged, rebooting");
+    crash::reboot();
+}
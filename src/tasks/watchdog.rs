@@ -0,0 +1,56 @@
+use embassy_rp::watchdog::{ResetReason, Watchdog};
+use embassy_time::{Duration, Timer};
+use log::{error, info};
+use watering_core::types::TaskId;
+
+use crate::config::WATCHDOG_FEED_INTERVAL_MS;
+use crate::heartbeat;
+
+// Scratch registers survive a watchdog reset but not a power cycle
+const STALL_SCRATCH: usize = 0;
+const STALL_MAGIC: u32 = 0x5747_0000;
+const STALL_MAGIC_MASK: u32 = 0xFFFF_0000;
+
+#[derive(Clone, Copy)]
+pub enum WatchdogReset {
+    // A supervised task missed its heartbeat deadline
+    Stalled(TaskId),
+    // The supervisor itself stopped feeding, e.g. the executor wedged
+    Hung,
+}
+
+/// Reads and clears the reason the previous boot was reset by the watchdog.
+pub fn take_last_reset(watchdog: &mut Watchdog) -> Option<WatchdogReset> {
+    let scratch = watchdog.get_scratch(STALL_SCRATCH);
+    watchdog.set_scratch(STALL_SCRATCH, 0);
+
+    if scratch & STALL_MAGIC_MASK == STALL_MAGIC {
+        if let Some(task) = TaskId::from_index(scratch & !STALL_MAGIC_MASK) {
+            return Some(WatchdogReset::Stalled(task));
+        }
+    }
+
+    match watchdog.reset_reason() {
+        Some(ResetReason::TimedOut) => Some(WatchdogReset::Hung),
+        _ => None,
+    }
+}
+
+#[embassy_executor::task]
+pub async fn watchdog_task(mut watchdog: Watchdog) {
+    info!("Watchdog supervisor started");
+
+    loop {
+        if let Some(task) = heartbeat::stalled() {
+            error!(
+                "Watchdog: {} task missed its deadline, resetting",
+                task.name()
+            );
+            watchdog.set_scratch(STALL_SCRATCH, STALL_MAGIC | task as u32);
+            watchdog.trigger_reset();
+        }
+
+        watchdog.feed();
+        Timer::after(Duration::from_millis(WATCHDOG_FEED_INTERVAL_MS)).await;
+    }
+}
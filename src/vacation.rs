@@ -0,0 +1,107 @@
+//! Vacation mode on the device: every run is shortened so the tank lasts
+//! until the end day; see `watering_core::vacation`. The end day is kept
+//! by the station in `decision`.
+
+use core::cell::RefCell;
+
+use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use log::{info, warn};
+use watering_core::types::{HttpRequest, PumpCommand, SystemEvent};
+use watering_core::vacation::{self, Plan};
+
+use crate::channels::HTTP_CHANNEL;
+use crate::decision;
+use crate::dosing;
+
+// Day the plan was last sent for
+static REPORTED_DAY: Mutex<CriticalSectionRawMutex, RefCell<Option<u16>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Restores the end day kept in flash; after `dosing::init`.
+pub fn init() {
+    let until_day = dosing::stored().vacation_until;
+    if let Some(day) = until_day {
+        info!("Vacation until day {}", day);
+    }
+    decision::with(|station| station.vacation_until = until_day);
+}
+
+/// Sets the day a vacation ends, or ends it with `None`. Like the target
+/// band, only a change is written to flash.
+pub fn set(until_day: Option<u16>) {
+    let changed = decision::with(|station| {
+        let changed = station.vacation_until != until_day;
+        station.vacation_until = until_day;
+        changed
+    });
+    if !changed {
+        return;
+    }
+    REPORTED_DAY.lock(|day| *day.borrow_mut() = None);
+    match until_day {
+        Some(day) => info!("Vacation mode until day {}", day),
+        None => info!("Vacation mode off"),
+    }
+    dosing::store_vacation(until_day);
+    report();
+}
+
+pub fn until_day() -> Option<u16> {
+    decision::with(|station| station.vacation_until)
+}
+
+/// Today's budget; `None` outside a vacation or while the date is unknown.
+/// Ends vacation mode once the end day is reached.
+pub fn plan() -> Option<Plan> {
+    let (plan, ended) = decision::with(|station| {
+        let active = station.vacation_until.is_some();
+        let plan = station.plan_vacation(&decision::SETTINGS);
+        (plan, active && station.vacation_until.is_none())
+    });
+    if ended {
+        info!("Vacation mode off");
+        dosing::store_vacation(None);
+    }
+    plan
+}
+
+/// Sends the plan to the server, once per day; call when the date may have
+/// moved on.
+pub fn report() {
+    let Some(plan) = plan() else {
+        return;
+    };
+    let today = decision::day_of_year();
+    let due = REPORTED_DAY.lock(|reported| reported.replace(today) != today);
+    if due {
+        info!(
+            "Vacation: {} days left, {:?} l a day, runs kept by zone {:?}",
+            plan.days_left, plan.daily_budget_l, plan.scales
+        );
+        HTTP_CHANNEL
+            .try_send(HttpRequest::PostEvent(SystemEvent::VacationPlan(plan)))
+            .ok();
+    }
+}
+
+/// `cmd` shortened to today's budget; `None` if the budget leaves nothing
+/// of it. Commands pass unchanged outside a vacation.
+pub fn scale(cmd: PumpCommand) -> Option<PumpCommand> {
+    let Some(plan) = plan() else {
+        return Some(cmd);
+    };
+    let scaled = vacation::scale(cmd, &plan);
+    match scaled {
+        Some(scaled) if scaled.duration_secs != cmd.duration_secs => info!(
+            "Vacation: zone {} run cut from {} to {} secs",
+            cmd.zone, cmd.duration_secs, scaled.duration_secs
+        ),
+        Some(_) => {}
+        None => warn!(
+            "Vacation: no water to spare for zone {}, run dropped",
+            cmd.zone
+        ),
+    }
+    scaled
+}